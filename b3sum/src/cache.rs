@@ -0,0 +1,322 @@
+//! The `--cache` file, which lets b3sum skip re-reading files that haven't changed.
+//!
+//! Each entry maps a file's absolute path and the hashing mode to the file's metadata and its
+//! 32-byte hash. A file counts as unchanged if its size and modification time, and on Unix its
+//! device, inode, and status change time, all match the entry. The file format is versioned and
+//! checksummed, and all integers are little-endian:
+//!
+//! | field         | size   | notes                                                         |
+//! |---------------|--------|---------------------------------------------------------------|
+//! | magic         | 4      | `b"B3SC"`                                                     |
+//! | version       | 1      | currently 1                                                   |
+//! | entry count   | 8      |                                                               |
+//! | entries       | varies | see below                                                     |
+//! | checksum      | 32     | the BLAKE3 hash of everything before it                       |
+//!
+//! Each entry is the path length (4 bytes) and the path bytes, the size (8), the modification
+//! time seconds (8, signed) and nanoseconds (4), the status change time seconds (8, signed) and
+//! nanoseconds (4), the device (8), the inode (8), the mode fingerprint (32), and the hash (32).
+//! The mode fingerprint is the mode's hash of the empty input, which tells the default, keyed,
+//! and key derivation modes apart without storing the key.
+
+use anyhow::{Context, bail, ensure};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MAGIC: &[u8; 4] = b"B3SC";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 4 + 1 + 8;
+const CHECKSUM_LEN: usize = 32;
+// Everything in an entry other than the path bytes.
+const ENTRY_FIXED_LEN: usize = 4 + 8 + 8 + 4 + 8 + 4 + 8 + 8 + 32 + 32;
+
+// Files modified this recently aren't cached. A file could be modified again right after we hash
+// it, without changing its modification time, if the filesystem's timestamps are coarse enough.
+// This is the same problem as Git's "racy" index entries.
+const RACY_WINDOW: Duration = Duration::from_secs(2);
+
+/// The metadata that has to match for a cache entry to be used.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct FileStamp {
+    size: u64,
+    mtime_secs: i64,
+    mtime_nanos: u32,
+    ctime_secs: i64,
+    ctime_nanos: u32,
+    dev: u64,
+    ino: u64,
+}
+
+impl FileStamp {
+    // Returns None for anything other than a regular file, and for files we can't stat. Those
+    // are never cached.
+    fn of(path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            Some(Self {
+                size: metadata.len(),
+                mtime_secs: metadata.mtime(),
+                mtime_nanos: metadata.mtime_nsec() as u32,
+                ctime_secs: metadata.ctime(),
+                ctime_nanos: metadata.ctime_nsec() as u32,
+                dev: metadata.dev(),
+                ino: metadata.ino(),
+            })
+        }
+        #[cfg(not(unix))]
+        {
+            let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+            Some(Self {
+                size: metadata.len(),
+                mtime_secs: mtime.as_secs().try_into().ok()?,
+                mtime_nanos: mtime.subsec_nanos(),
+                ..Self::default()
+            })
+        }
+    }
+
+    fn is_racy(&self, now: SystemTime) -> bool {
+        let Ok(mtime_secs) = u64::try_from(self.mtime_secs) else {
+            return false;
+        };
+        let mtime = UNIX_EPOCH + Duration::new(mtime_secs, self.mtime_nanos);
+        // A modification time in the future is racy too.
+        mtime + RACY_WINDOW > now
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct CacheKey {
+    path: Vec<u8>,
+    mode: [u8; 32],
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct CacheEntry {
+    stamp: FileStamp,
+    hash: [u8; 32],
+}
+
+pub struct Cache {
+    path: PathBuf,
+    entries: Mutex<HashMap<CacheKey, CacheEntry>>,
+    dirty: AtomicBool,
+}
+
+impl Cache {
+    /// Load the cache file at `path`. A missing file is an empty cache. An invalid file is
+    /// reported on stderr and treated as empty, so that it gets rewritten.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mut invalid = false;
+        let entries = match fs::read(path) {
+            Ok(bytes) => decode(&bytes).unwrap_or_else(|e| {
+                eprintln!(
+                    "{}: {}: ignoring invalid cache: {}",
+                    crate::NAME,
+                    path.to_string_lossy(),
+                    e,
+                );
+                invalid = true;
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                return Err(e).with_context(|| format!("{}", path.to_string_lossy()));
+            }
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            entries: Mutex::new(entries),
+            dirty: AtomicBool::new(invalid),
+        })
+    }
+
+    /// Return the cached hash of the file at `path`, or compute it with `hash_file` and cache the
+    /// result. `base_hasher` must be a fresh hasher in the mode `hash_file` uses. With `rehash`,
+    /// always call `hash_file`.
+    pub fn get_or_hash(
+        &self,
+        path: &Path,
+        base_hasher: &blake3::Hasher,
+        rehash: bool,
+        hash_file: impl FnOnce() -> anyhow::Result<blake3::Hash>,
+    ) -> anyhow::Result<blake3::Hash> {
+        let (Some(stamp_before), Some(key_path)) = (FileStamp::of(path), key_path(path)) else {
+            return hash_file();
+        };
+        let key = CacheKey {
+            path: key_path,
+            mode: *base_hasher.clone().finalize().as_bytes(),
+        };
+        if !rehash {
+            let entries = self.entries.lock().unwrap();
+            if let Some(entry) = entries.get(&key) {
+                if entry.stamp == stamp_before {
+                    return Ok(entry.hash.into());
+                }
+            }
+        }
+        let hash = hash_file()?;
+        // Don't cache the hash if the file changed while we were reading it, or if it might
+        // change again without its metadata changing.
+        if FileStamp::of(path) == Some(stamp_before) && !stamp_before.is_racy(SystemTime::now()) {
+            let entry = CacheEntry {
+                stamp: stamp_before,
+                hash: *hash.as_bytes(),
+            };
+            let mut entries = self.entries.lock().unwrap();
+            if entries.get(&key) != Some(&entry) {
+                entries.insert(key, entry);
+                self.dirty.store(true, Ordering::Relaxed);
+            }
+        }
+        Ok(hash)
+    }
+
+    /// Write the cache file, if anything changed. The new file is written next to the old one
+    /// and renamed over it, so that an interrupted run doesn't leave a truncated cache.
+    pub fn save(&self) -> anyhow::Result<()> {
+        if !self.dirty.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let bytes = encode(&self.entries.lock().unwrap());
+        let mut temp_name = self.path.file_name().unwrap_or_default().to_owned();
+        temp_name.push(format!(".tmp{}", std::process::id()));
+        let temp_path = self.path.with_file_name(temp_name);
+        let result = fs::write(&temp_path, bytes).and_then(|()| fs::rename(&temp_path, &self.path));
+        if result.is_err() {
+            _ = fs::remove_file(&temp_path);
+        }
+        result.with_context(|| format!("{}", self.path.to_string_lossy()))
+    }
+}
+
+// The bytes of the absolute path, which is what entries are keyed on. A relative path on the
+// command line refers to a different file depending on the working directory.
+fn key_path(path: &Path) -> Option<Vec<u8>> {
+    let absolute = std::env::current_dir().ok()?.join(path);
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        Some(absolute.as_os_str().as_bytes().to_vec())
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        Some(
+            absolute
+                .as_os_str()
+                .encode_wide()
+                .flat_map(u16::to_le_bytes)
+                .collect(),
+        )
+    }
+    #[cfg(not(any(unix, windows)))]
+    {
+        Some(absolute.to_str()?.as_bytes().to_vec())
+    }
+}
+
+fn encode(entries: &HashMap<CacheKey, CacheEntry>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(MAGIC);
+    bytes.push(VERSION);
+    bytes.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+    // Sort the entries, so that the same cache always encodes the same way.
+    let mut sorted: Vec<_> = entries.iter().collect();
+    sorted.sort_by(|a, b| (&a.0.path, &a.0.mode).cmp(&(&b.0.path, &b.0.mode)));
+    for (key, entry) in sorted {
+        bytes.extend_from_slice(&(key.path.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&key.path);
+        let stamp = &entry.stamp;
+        bytes.extend_from_slice(&stamp.size.to_le_bytes());
+        bytes.extend_from_slice(&stamp.mtime_secs.to_le_bytes());
+        bytes.extend_from_slice(&stamp.mtime_nanos.to_le_bytes());
+        bytes.extend_from_slice(&stamp.ctime_secs.to_le_bytes());
+        bytes.extend_from_slice(&stamp.ctime_nanos.to_le_bytes());
+        bytes.extend_from_slice(&stamp.dev.to_le_bytes());
+        bytes.extend_from_slice(&stamp.ino.to_le_bytes());
+        bytes.extend_from_slice(&key.mode);
+        bytes.extend_from_slice(&entry.hash);
+    }
+    let checksum = blake3::hash(&bytes);
+    bytes.extend_from_slice(checksum.as_bytes());
+    bytes
+}
+
+fn decode(bytes: &[u8]) -> anyhow::Result<HashMap<CacheKey, CacheEntry>> {
+    ensure!(
+        bytes.len() >= HEADER_LEN + CHECKSUM_LEN && bytes.starts_with(MAGIC),
+        "not a b3sum cache file"
+    );
+    ensure!(
+        bytes[4] == VERSION,
+        "unsupported cache version {}",
+        bytes[4]
+    );
+    let (contents, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+    // blake3::Hash comparisons are constant-time, but that doesn't matter here.
+    ensure!(blake3::hash(contents) == *checksum, "checksum mismatch");
+
+    let mut reader = Reader {
+        bytes: &contents[HEADER_LEN..],
+    };
+    let count = u64::from_le_bytes(contents[5..HEADER_LEN].try_into().unwrap());
+    // Don't let a bogus count allocate a huge map.
+    ensure!(
+        count <= (reader.bytes.len() / ENTRY_FIXED_LEN) as u64,
+        "invalid entry count"
+    );
+    let mut entries = HashMap::with_capacity(count as usize);
+    for _ in 0..count {
+        let path_len = u32::from_le_bytes(reader.take()?) as usize;
+        let path = reader.take_slice(path_len)?.to_vec();
+        let stamp = FileStamp {
+            size: u64::from_le_bytes(reader.take()?),
+            mtime_secs: i64::from_le_bytes(reader.take()?),
+            mtime_nanos: u32::from_le_bytes(reader.take()?),
+            ctime_secs: i64::from_le_bytes(reader.take()?),
+            ctime_nanos: u32::from_le_bytes(reader.take()?),
+            dev: u64::from_le_bytes(reader.take()?),
+            ino: u64::from_le_bytes(reader.take()?),
+        };
+        let mode = reader.take()?;
+        let hash = reader.take()?;
+        let key = CacheKey { path, mode };
+        ensure!(
+            entries.insert(key, CacheEntry { stamp, hash }).is_none(),
+            "duplicate entry"
+        );
+    }
+    ensure!(reader.bytes.is_empty(), "trailing bytes");
+    Ok(entries)
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take_slice(&mut self, len: usize) -> anyhow::Result<&'a [u8]> {
+        if len > self.bytes.len() {
+            bail!("truncated entry");
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn take<const N: usize>(&mut self) -> anyhow::Result<[u8; N]> {
+        Ok(self.take_slice(N)?.try_into().unwrap())
+    }
+}
@@ -0,0 +1,247 @@
+//! Just enough JSON for `--json`, `--json-lines`, and JSON checkfiles.
+//!
+//! Output records are written by hand with `write_string`. Input is parsed by `parse`, which
+//! supports all of JSON, but keeps numbers as their original text, because the only numbers we
+//! care about are `u64` lengths and offsets.
+
+use anyhow::{bail, ensure};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+/// Append `s` to `out` as a quoted JSON string.
+pub fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Parse a single JSON value, surrounded by optional whitespace.
+pub fn parse(input: &str) -> anyhow::Result<Value> {
+    let mut parser = Parser {
+        input: input.as_bytes(),
+        position: 0,
+        depth: 0,
+    };
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    ensure!(
+        parser.position == parser.input.len(),
+        "Trailing characters after JSON value"
+    );
+    Ok(value)
+}
+
+// Checkfiles come from untrusted places. Don't let deeply nested input overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+struct Parser<'a> {
+    input: &'a [u8],
+    position: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> anyhow::Result<u8> {
+        let Some(byte) = self.peek() else {
+            bail!("Unexpected end of JSON");
+        };
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn expect_literal(&mut self, literal: &str) -> anyhow::Result<()> {
+        ensure!(
+            self.input[self.position..].starts_with(literal.as_bytes()),
+            "Invalid JSON"
+        );
+        self.position += literal.len();
+        Ok(())
+    }
+
+    fn parse_value(&mut self) -> anyhow::Result<Value> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b'n') => self.expect_literal("null").map(|_| Value::Null),
+            Some(b't') => self.expect_literal("true").map(|_| Value::Bool(true)),
+            Some(b'f') => self.expect_literal("false").map(|_| Value::Bool(false)),
+            Some(b'"') => self.parse_string().map(Value::String),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(b'[') => {
+                self.enter()?;
+                let mut elements = Vec::new();
+                self.parse_sequence(b']', |parser| {
+                    elements.push(parser.parse_value()?);
+                    Ok(())
+                })?;
+                self.depth -= 1;
+                Ok(Value::Array(elements))
+            }
+            Some(b'{') => {
+                self.enter()?;
+                let mut members = Vec::new();
+                self.parse_sequence(b'}', |parser| {
+                    parser.skip_whitespace();
+                    ensure!(parser.peek() == Some(b'"'), "Expected a JSON object key");
+                    let key = parser.parse_string()?;
+                    parser.skip_whitespace();
+                    ensure!(parser.next()? == b':', "Expected : in JSON object");
+                    members.push((key, parser.parse_value()?));
+                    Ok(())
+                })?;
+                self.depth -= 1;
+                Ok(Value::Object(members))
+            }
+            Some(_) => bail!("Invalid JSON"),
+            None => bail!("Unexpected end of JSON"),
+        }
+    }
+
+    fn enter(&mut self) -> anyhow::Result<()> {
+        self.depth += 1;
+        ensure!(self.depth <= MAX_DEPTH, "JSON nested too deeply");
+        // Skip the opening bracket or brace.
+        self.position += 1;
+        Ok(())
+    }
+
+    // Parse comma-separated items up to the closing byte.
+    fn parse_sequence(
+        &mut self,
+        close: u8,
+        mut parse_item: impl FnMut(&mut Self) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        self.skip_whitespace();
+        if self.peek() == Some(close) {
+            self.position += 1;
+            return Ok(());
+        }
+        loop {
+            parse_item(self)?;
+            self.skip_whitespace();
+            match self.next()? {
+                b',' => continue,
+                byte if byte == close => return Ok(()),
+                _ => bail!("Invalid JSON"),
+            }
+        }
+    }
+
+    fn parse_number(&mut self) -> anyhow::Result<Value> {
+        let start = self.position;
+        if self.peek() == Some(b'-') {
+            self.position += 1;
+        }
+        let digits = |parser: &mut Self| {
+            let start = parser.position;
+            while let Some(b'0'..=b'9') = parser.peek() {
+                parser.position += 1;
+            }
+            parser.position - start
+        };
+        let int_start = self.position;
+        let int_digits = digits(self);
+        ensure!(int_digits > 0, "Invalid JSON number");
+        ensure!(
+            int_digits == 1 || self.input[int_start] != b'0',
+            "Invalid JSON number"
+        );
+        if self.peek() == Some(b'.') {
+            self.position += 1;
+            ensure!(digits(self) > 0, "Invalid JSON number");
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            self.position += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.position += 1;
+            }
+            ensure!(digits(self) > 0, "Invalid JSON number");
+        }
+        let text = std::str::from_utf8(&self.input[start..self.position]).unwrap();
+        Ok(Value::Number(text.to_string()))
+    }
+
+    fn parse_hex4(&mut self) -> anyhow::Result<u16> {
+        let mut n = 0;
+        for _ in 0..4 {
+            let digit = (self.next()? as char).to_digit(16);
+            let Some(digit) = digit else {
+                bail!("Invalid JSON escape");
+            };
+            n = 16 * n + digit as u16;
+        }
+        Ok(n)
+    }
+
+    fn parse_string(&mut self) -> anyhow::Result<String> {
+        // Skip the opening quote.
+        self.position += 1;
+        let mut bytes = Vec::new();
+        loop {
+            match self.next()? {
+                b'"' => break,
+                b'\\' => {
+                    let c = match self.next()? {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let high = self.parse_hex4()?;
+                            let code_point = if (0xd800..0xdc00).contains(&high) {
+                                // A surrogate pair.
+                                self.expect_literal("\\u")?;
+                                let low = self.parse_hex4()?;
+                                ensure!((0xdc00..0xe000).contains(&low), "Invalid JSON escape");
+                                0x10000 + ((high as u32 - 0xd800) << 10) + (low as u32 - 0xdc00)
+                            } else {
+                                high as u32
+                            };
+                            let Some(c) = char::from_u32(code_point) else {
+                                bail!("Invalid JSON escape");
+                            };
+                            c
+                        }
+                        _ => bail!("Invalid JSON escape"),
+                    };
+                    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                byte if byte < 0x20 => bail!("Control character in JSON string"),
+                byte => bytes.push(byte),
+            }
+        }
+        // The input was a &str, and escapes produce whole characters, so this can't fail.
+        Ok(String::from_utf8(bytes).unwrap())
+    }
+}
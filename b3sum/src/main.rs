@@ -6,8 +6,11 @@ use std::io;
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 
+mod cache;
+mod json;
 #[cfg(test)]
 mod unit_tests;
+mod walk;
 
 const NAME: &str = "b3sum";
 
@@ -18,6 +21,11 @@ const NO_NAMES_ARG: &str = "no_names";
 const RAW_ARG: &str = "raw";
 const TAG_ARG: &str = "tag";
 const CHECK_ARG: &str = "check";
+const RECURSIVE_ARG: &str = "recursive";
+const SEEK_ARG: &str = "seek";
+const TREE_DIGEST_ARG: &str = "tree_digest";
+const JSON_ARG: &str = "json";
+const CACHE_ARG: &str = "cache";
 
 #[derive(Parser)]
 #[command(version, max_term_width(100))]
@@ -59,7 +67,8 @@ struct Inner {
 
     /// Disable memory mapping
     ///
-    /// Currently this also disables multithreading.
+    /// Files are read with positional reads on multiple threads instead,
+    /// which can be faster on network filesystems.
     #[arg(long)]
     no_mmap: bool,
 
@@ -77,7 +86,113 @@ struct Inner {
     #[arg(long)]
     tag: bool,
 
+    /// Hash the files in directories recursively
+    ///
+    /// The files in each directory are hashed in sorted order, and the output
+    /// can be checked with --check. Symlinks and hidden files inside
+    /// directories are skipped by default, and FIFOs, sockets, and devices
+    /// inside directories are always skipped. Symlinks given on the command
+    /// line are always followed.
+    #[arg(short, long, conflicts_with(CHECK_ARG), conflicts_with(RAW_ARG))]
+    recursive: bool,
+
+    /// Follow symlinks inside directories
+    ///
+    /// Must be used with --recursive. Symlink loops are reported as errors.
+    #[arg(long, requires(RECURSIVE_ARG))]
+    follow_symlinks: bool,
+
+    /// Include hidden files and directories, whose names start with a dot
+    ///
+    /// Must be used with --recursive.
+    #[arg(long, requires(RECURSIVE_ARG))]
+    hidden: bool,
+
+    /// Skip files and directories matching GLOB
+    ///
+    /// Must be used with --recursive, and can be given more than once. A GLOB
+    /// without a / matches names at any depth, and a GLOB with a / matches
+    /// paths relative to the directory argument. * and ? don't match /, ** does,
+    /// and [...] matches a character class.
+    #[arg(
+        long,
+        value_name("GLOB"),
+        requires(RECURSIVE_ARG),
+        value_parser = walk::Pattern::parse
+    )]
+    exclude: Vec<walk::Pattern>,
+
+    /// Print a single digest for each directory [FILE]
+    ///
+    /// The digest covers the relative paths, types, executable bits, and
+    /// contents of everything under the directory, including hidden files.
+    /// Symlinks are recorded but not followed. See the blake3::tree_digest
+    /// module docs for the exact format. The output looks like
+    /// BLAKE3-TREE ([FILE]) = [HASH], and --check verifies these lines.
+    #[arg(
+        long,
+        conflicts_with(CHECK_ARG),
+        conflicts_with(DERIVE_KEY_ARG),
+        conflicts_with(KEYED_ARG),
+        conflicts_with(LENGTH_ARG),
+        conflicts_with(RAW_ARG),
+        conflicts_with(RECURSIVE_ARG),
+        conflicts_with(SEEK_ARG),
+        conflicts_with(TAG_ARG)
+    )]
+    tree_digest: bool,
+
+    /// Reuse hashes of unchanged files from FILE, and save new ones there
+    ///
+    /// A file counts as unchanged if its size and modification time, and on
+    /// Unix its device, inode, and status change time, are the same as when
+    /// its hash was cached. Only the first 32 bytes of output are cached, so
+    /// --length or --seek past that always reads the file. Standard input is
+    /// never cached.
+    #[arg(long, value_name("FILE"))]
+    cache: Option<PathBuf>,
+
+    /// Read every file in full, even with --cache
+    ///
+    /// The new hashes are still saved to the cache.
+    #[arg(long, visible_alias("paranoid"), requires(CACHE_ARG))]
+    rehash: bool,
+
+    /// Output a JSON array with a record for each file
+    ///
+    /// Each record has "path", "hash", "length", and "seek" fields, or "path"
+    /// and "error" if the file couldn't be hashed. On Unix, a path that isn't
+    /// valid UTF-8 is given as "path_hex" instead, the hex encoding of its raw
+    /// bytes. With --check, read checkfiles in this format or in the
+    /// --json-lines format.
+    #[arg(
+        long,
+        conflicts_with(NO_NAMES_ARG),
+        conflicts_with(RAW_ARG),
+        conflicts_with(TAG_ARG),
+        conflicts_with(TREE_DIGEST_ARG)
+    )]
+    json: bool,
+
+    /// Output a line of JSON for each file
+    ///
+    /// The records are the same as for --json.
+    #[arg(
+        long,
+        conflicts_with(JSON_ARG),
+        conflicts_with(NO_NAMES_ARG),
+        conflicts_with(RAW_ARG),
+        conflicts_with(TAG_ARG),
+        conflicts_with(TREE_DIGEST_ARG)
+    )]
+    json_lines: bool,
+
     /// Read BLAKE3 sums from the [FILE]s and check them
+    ///
+    /// The exit status is 1 if any computed checksum did NOT match, or else 3
+    /// if any listed file was missing or couldn't be read, or else 4 if any
+    /// line was improperly formatted and --strict is given, or if a checkfile
+    /// had no properly formatted lines at all.
     #[arg(
         short,
         long,
@@ -95,12 +210,32 @@ struct Inner {
     /// Must be used with --check.
     #[arg(long, requires(CHECK_ARG))]
     quiet: bool,
+
+    /// Don't fail or print anything for missing files
+    ///
+    /// Must be used with --check. A checkfile where every listed file is
+    /// missing is still an error.
+    #[arg(long, requires(CHECK_ARG))]
+    ignore_missing: bool,
+
+    /// Warn about each improperly formatted line
+    ///
+    /// Must be used with --check.
+    #[arg(long, requires(CHECK_ARG))]
+    warn: bool,
+
+    /// Fail if any line is improperly formatted
+    ///
+    /// Must be used with --check.
+    #[arg(long, requires(CHECK_ARG))]
+    strict: bool,
 }
 
 struct Args {
     inner: Inner,
     file_args: Vec<PathBuf>,
     base_hasher: blake3::Hasher,
+    cache: Option<cache::Cache>,
 }
 
 impl Args {
@@ -125,10 +260,15 @@ impl Args {
         } else {
             blake3::Hasher::new()
         };
+        let cache = match inner.cache {
+            Some(ref cache_path) => Some(cache::Cache::load(cache_path)?),
+            None => None,
+        };
         Ok(Self {
             inner,
             file_args,
             base_hasher,
+            cache,
         })
     }
 
@@ -171,9 +311,135 @@ impl Args {
     fn quiet(&self) -> bool {
         self.inner.quiet
     }
+
+    fn cache(&self) -> Option<&cache::Cache> {
+        self.cache.as_ref()
+    }
+
+    fn rehash(&self) -> bool {
+        self.inner.rehash
+    }
+
+    fn ignore_missing(&self) -> bool {
+        self.inner.ignore_missing
+    }
+
+    fn warn(&self) -> bool {
+        self.inner.warn
+    }
+
+    fn strict(&self) -> bool {
+        self.inner.strict
+    }
+
+    fn recursive(&self) -> bool {
+        self.inner.recursive
+    }
+
+    fn tree_digest(&self) -> bool {
+        self.inner.tree_digest
+    }
+
+    fn json_format(&self) -> Option<JsonFormat> {
+        if self.inner.json {
+            Some(JsonFormat::Array)
+        } else if self.inner.json_lines {
+            Some(JsonFormat::Lines)
+        } else {
+            None
+        }
+    }
+
+    fn walk_options(&self) -> walk::WalkOptions<'_> {
+        walk::WalkOptions {
+            follow_symlinks: self.inner.follow_symlinks,
+            hidden: self.inner.hidden,
+            excludes: &self.inner.exclude,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum JsonFormat {
+    Array,
+    Lines,
+}
+
+// The output of hash_path. With --cache, an unchanged file's output comes from the cache, which
+// only has the first 32 bytes of it.
+enum HashOutput {
+    Reader(blake3::OutputReader),
+    Cached { hash: [u8; 32], position: u64 },
+}
+
+impl HashOutput {
+    fn set_position(&mut self, position: u64) {
+        match self {
+            Self::Reader(reader) => reader.set_position(position),
+            Self::Cached {
+                position: cached_position,
+                ..
+            } => *cached_position = position,
+        }
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) {
+        match self {
+            Self::Reader(reader) => reader.fill(buf),
+            Self::Cached { .. } => {
+                let n = self.read(buf).unwrap();
+                assert_eq!(n, buf.len(), "read past the end of a cached hash");
+            }
+        }
+    }
 }
 
-fn hash_path(args: &Args, path: &Path) -> anyhow::Result<blake3::OutputReader> {
+impl Read for HashOutput {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Reader(reader) => reader.read(buf),
+            Self::Cached { hash, position } => {
+                let start = cmp::min(*position, hash.len() as u64) as usize;
+                let n = cmp::min(buf.len(), hash.len() - start);
+                buf[..n].copy_from_slice(&hash[start..][..n]);
+                *position += n as u64;
+                Ok(n)
+            }
+        }
+    }
+}
+
+// Hash a file in the mode of `base_hasher`, using the --cache file if there is one.
+fn hash_file_with_cache(
+    args: &Args,
+    base_hasher: &blake3::Hasher,
+    path: &Path,
+) -> anyhow::Result<blake3::Hash> {
+    let hash_file = || {
+        let mut hasher = base_hasher.clone();
+        if args.no_mmap() {
+            hasher.update_file_parallel(path)?;
+        } else {
+            hasher.update_mmap_rayon(path)?;
+        }
+        Ok(hasher.finalize())
+    };
+    match args.cache() {
+        Some(cache) => cache.get_or_hash(path, base_hasher, args.rehash(), hash_file),
+        None => hash_file(),
+    }
+}
+
+// `output_end` is how far into the output the caller is going to read. The cache is only used if
+// that's within the first 32 bytes.
+fn hash_path(args: &Args, path: &Path, output_end: u64) -> anyhow::Result<HashOutput> {
+    if args.cache().is_some() && path != Path::new("-") && output_end <= blake3::OUT_LEN as u64 {
+        let hash = hash_file_with_cache(args, &args.base_hasher, path)?;
+        return Ok(HashOutput::Cached {
+            hash: *hash.as_bytes(),
+            position: args.seek(),
+        });
+    }
     let mut hasher = args.base_hasher.clone();
     if path == Path::new("-") {
         if args.keyed() {
@@ -181,33 +447,33 @@ fn hash_path(args: &Args, path: &Path) -> anyhow::Result<blake3::OutputReader> {
         }
         hasher.update_reader(io::stdin().lock())?;
     } else if args.no_mmap() {
-        hasher.update_reader(File::open(path)?)?;
+        // Read the file with pread (or equivalent) and hash it with multiple threads.
+        hasher.update_file_parallel(path)?;
     } else {
         // The fast path: Try to mmap the file and hash it with multiple threads.
         hasher.update_mmap_rayon(path)?;
     }
     let mut output_reader = hasher.finalize_xof();
     output_reader.set_position(args.seek());
-    Ok(output_reader)
+    Ok(HashOutput::Reader(output_reader))
 }
 
-fn write_hex_output(mut output: blake3::OutputReader, args: &Args) -> anyhow::Result<()> {
+fn write_hex_output(mut output: HashOutput, args: &Args) -> anyhow::Result<()> {
     // Encoding multiples of the 64 bytes is most efficient.
     // TODO: This computes each output block twice when the --seek argument isn't a multiple of 64.
     // We'll refactor all of this soon anyway, once SIMD optimizations are available for the XOF.
     let mut len = args.len();
     let mut block = [0; blake3::BLOCK_LEN];
     while len > 0 {
-        output.fill(&mut block);
-        let hex_str = hex::encode(&block[..]);
-        let take_bytes = cmp::min(len, block.len() as u64);
-        print!("{}", &hex_str[..2 * take_bytes as usize]);
-        len -= take_bytes;
+        let take_bytes = cmp::min(len, block.len() as u64) as usize;
+        output.fill(&mut block[..take_bytes]);
+        print!("{}", hex::encode(&block[..take_bytes]));
+        len -= take_bytes as u64;
     }
     Ok(())
 }
 
-fn write_raw_output(output: blake3::OutputReader, args: &Args) -> anyhow::Result<()> {
+fn write_raw_output(output: HashOutput, args: &Args) -> anyhow::Result<()> {
     let mut output = output.take(args.len());
     let stdout = std::io::stdout();
     let mut handler = stdout.lock();
@@ -279,6 +545,19 @@ fn hex_half_byte(c: char) -> anyhow::Result<u8> {
     bail!("Invalid hex");
 }
 
+// A line or record whose hash is well formed, but whose path we refuse to check, for one of the
+// reasons below. Unlike an improperly formatted line, this is always a failure.
+#[derive(Debug)]
+struct UnsafePathError(anyhow::Error);
+
+impl std::fmt::Display for UnsafePathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for UnsafePathError {}
+
 // The `check` command is a security tool. That means it's much better for a
 // check to fail more often than it should (a false negative), than for a check
 // to ever succeed when it shouldn't (a false positive). By forbidding certain
@@ -332,6 +611,8 @@ struct ParsedCheckLine {
     is_escaped: bool,
     file_path: PathBuf,
     expected_hash: blake3::Hash,
+    // A directory from --tree-digest, rather than a file.
+    is_tree: bool,
 }
 
 fn split_untagged_check_line(line_after_slash: &str) -> Option<(&str, &str)> {
@@ -340,14 +621,13 @@ fn split_untagged_check_line(line_after_slash: &str) -> Option<(&str, &str)> {
     line_after_slash.split_once("  ")
 }
 
-fn split_tagged_check_line(line_after_slash: &str) -> Option<(&str, &str)> {
-    // Of the form "BLAKE3 (<file>) = <hash>". The file might contain ") = ", so we need to split
-    // from the *right*.
-    let prefix = "BLAKE3 (";
-    if !line_after_slash.starts_with(prefix) {
-        return None;
-    }
-    line_after_slash[prefix.len()..].rsplit_once(") = ")
+fn split_tagged_check_line<'a>(
+    line_after_slash: &'a str,
+    prefix: &str,
+) -> Option<(&'a str, &'a str)> {
+    // Of the form "BLAKE3 (<file>) = <hash>", or "BLAKE3-TREE (<dir>) = <hash>" for a tree digest.
+    // The file might contain ") = ", so we need to split from the *right*.
+    line_after_slash.strip_prefix(prefix)?.rsplit_once(") = ")
 }
 
 fn parse_check_line(mut line: &str) -> anyhow::Result<ParsedCheckLine> {
@@ -368,16 +648,21 @@ fn parse_check_line(mut line: &str) -> anyhow::Result<ParsedCheckLine> {
         line_after_slash = line;
     }
 
-    // Split the line. It might be "<hash>  <file>" or "BLAKE3 (<file>) = <hash>". The latter comes
-    // from the --tag flag.
+    // Split the line. It might be "<hash>  <file>", "BLAKE3 (<file>) = <hash>" from the --tag
+    // flag, or "BLAKE3-TREE (<dir>) = <hash>" from the --tree-digest flag.
     let hash_hex;
     let file_str;
+    let mut is_tree = false;
     if let Some((left, right)) = split_untagged_check_line(line_after_slash) {
         hash_hex = left;
         file_str = right;
-    } else if let Some((left, right)) = split_tagged_check_line(line_after_slash) {
+    } else if let Some((left, right)) = split_tagged_check_line(line_after_slash, "BLAKE3 (") {
+        file_str = left;
+        hash_hex = right;
+    } else if let Some((left, right)) = split_tagged_check_line(line_after_slash, "BLAKE3-TREE (") {
         file_str = left;
         hash_hex = right;
+        is_tree = true;
     } else {
         bail!("Invalid check line format");
     }
@@ -395,23 +680,24 @@ fn parse_check_line(mut line: &str) -> anyhow::Result<ParsedCheckLine> {
 
     // Unescape and validate the filepath.
     let file_path_string = if is_escaped {
-        unescape(file_str)?
+        unescape(file_str).map_err(UnsafePathError)?
     } else {
         file_str.to_string()
     };
     ensure!(!file_path_string.is_empty(), "empty file path");
-    check_for_invalid_characters(&file_path_string)?;
+    check_for_invalid_characters(&file_path_string).map_err(UnsafePathError)?;
 
     Ok(ParsedCheckLine {
         file_string: file_str.to_string(),
         is_escaped,
         file_path: file_path_string.into(),
         expected_hash,
+        is_tree,
     })
 }
 
 fn hash_one_input(path: &Path, args: &Args) -> anyhow::Result<()> {
-    let output = hash_path(args, path)?;
+    let output = hash_path(args, path, args.seek().saturating_add(args.len()))?;
     if args.raw() {
         write_raw_output(output, args)?;
         return Ok(());
@@ -439,22 +725,278 @@ fn hash_one_input(path: &Path, args: &Args) -> anyhow::Result<()> {
     Ok(())
 }
 
-// Returns true for success. Having a boolean return value here, instead of
-// passing down the files_failed reference, makes it less likely that we might
-// forget to set it in some error condition.
-fn check_one_line(line: &str, args: &Args) -> bool {
-    let parse_result = parse_check_line(&line);
+// Append the "path" or "path_hex" field of a JSON record. On Windows, as with filepath_to_string,
+// backslashes are normalized to forward slashes. Paths that aren't valid Unicode are an error on
+// Windows, and in that case we append a lossy "path" field for the error record.
+fn append_json_path(record: &mut String, path: &Path) -> anyhow::Result<()> {
+    if let Some(utf8_path) = path.to_str() {
+        record.push_str("\"path\":");
+        if cfg!(windows) {
+            json::write_string(record, &utf8_path.replace('\\', "/"));
+        } else {
+            json::write_string(record, utf8_path);
+        }
+        return Ok(());
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        record.push_str("\"path_hex\":\"");
+        record.push_str(&hex::encode(path.as_os_str().as_bytes()));
+        record.push('"');
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        record.push_str("\"path\":");
+        json::write_string(record, &path.to_string_lossy());
+        bail!("Path is not valid Unicode");
+    }
+}
+
+// Print the separator that goes before each record of JSON output.
+fn start_json_record(args: &Args, records_written: &mut u64) {
+    if args.json_format() == Some(JsonFormat::Array) {
+        print!("{}", if *records_written == 0 { "[\n" } else { ",\n" });
+    }
+    *records_written += 1;
+}
+
+fn finish_json_output(args: &Args, records_written: u64) {
+    if args.json_format() == Some(JsonFormat::Array) {
+        println!("{}]", if records_written == 0 { "[\n" } else { "\n" });
+    }
+}
+
+// Print a JSON record for `path`. If `walk_error` is given, or if hashing fails, print an error
+// record instead and return the error.
+fn hash_one_input_json(
+    path: &Path,
+    walk_error: Option<anyhow::Error>,
+    args: &Args,
+    records_written: &mut u64,
+) -> anyhow::Result<()> {
+    let mut record = String::from("{");
+    let result = append_json_path(&mut record, path).and_then(|()| match walk_error {
+        Some(e) => Err(e),
+        None => hash_path(args, path, args.seek().saturating_add(args.len())),
+    });
+    start_json_record(args, records_written);
+    let end_of_record = if args.json_format() == Some(JsonFormat::Lines) {
+        "}\n"
+    } else {
+        "}"
+    };
+    match result {
+        Ok(output) => {
+            print!("{record},\"hash\":\"");
+            write_hex_output(output, args)?;
+            print!(
+                "\",\"length\":{},\"seek\":{}{end_of_record}",
+                args.len(),
+                args.seek()
+            );
+            Ok(())
+        }
+        Err(e) => {
+            record.push_str(",\"error\":");
+            json::write_string(&mut record, &e.to_string());
+            print!("{record}{end_of_record}");
+            Err(e)
+        }
+    }
+}
+
+fn tree_digest(path: &Path, args: &Args) -> anyhow::Result<blake3::Hash> {
+    if path == Path::new("-") {
+        bail!("Cannot use `-` with --tree-digest");
+    }
+    let digest = blake3::tree_digest::digest_dir_with(path, |file_path| {
+        hash_file_with_cache(args, &blake3::Hasher::new(), file_path)
+            .map_err(|e| e.downcast::<io::Error>().unwrap_or_else(io::Error::other))
+    })?;
+    Ok(digest)
+}
+
+fn hash_one_tree(path: &Path, args: &Args) -> anyhow::Result<()> {
+    let digest = tree_digest(path, args)?;
+    if args.no_names() {
+        println!("{}", digest.to_hex());
+        return Ok(());
+    }
+    let FilepathString {
+        filepath_string,
+        is_escaped,
+    } = filepath_to_string(path);
+    if is_escaped {
+        print!("\\");
+    }
+    // This is distinct from the output for a file, so that --check doesn't hash the directory
+    // as one.
+    println!("BLAKE3-TREE ({}) = {}", filepath_string, digest.to_hex());
+    Ok(())
+}
+
+// The outcome of checking one line of a checkfile.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum CheckStatus {
+    Ok,
+    Mismatch,
+    Missing,
+    // A missing file with --ignore-missing.
+    Ignored,
+    Unreadable,
+}
+
+// Counts of each CheckStatus, plus improperly formatted lines and checkfiles that were themselves
+// a problem.
+#[derive(Debug, Default)]
+struct CheckSummary {
+    ok: u64,
+    mismatched: u64,
+    missing: u64,
+    ignored: u64,
+    unreadable: u64,
+    malformed: u64,
+    // Checkfiles that couldn't be read, or where every listed file was ignored.
+    incomplete_checkfiles: u64,
+    // Checkfiles that had improperly formatted lines and nothing else.
+    malformed_checkfiles: u64,
+}
+
+impl CheckSummary {
+    // We use nonzero counts to decide the exit status, so it's important for correctness that
+    // it's impossible for these counters to overflow.
+    fn add(&mut self, status: CheckStatus) {
+        let count = match status {
+            CheckStatus::Ok => &mut self.ok,
+            CheckStatus::Mismatch => &mut self.mismatched,
+            CheckStatus::Missing => &mut self.missing,
+            CheckStatus::Ignored => &mut self.ignored,
+            CheckStatus::Unreadable => &mut self.unreadable,
+        };
+        *count = count.saturating_add(1);
+    }
+
+    fn add_malformed(&mut self) {
+        self.malformed = self.malformed.saturating_add(1);
+    }
+
+    fn merge(&mut self, other: &CheckSummary) {
+        self.ok = self.ok.saturating_add(other.ok);
+        self.mismatched = self.mismatched.saturating_add(other.mismatched);
+        self.missing = self.missing.saturating_add(other.missing);
+        self.ignored = self.ignored.saturating_add(other.ignored);
+        self.unreadable = self.unreadable.saturating_add(other.unreadable);
+        self.malformed = self.malformed.saturating_add(other.malformed);
+        self.incomplete_checkfiles = self
+            .incomplete_checkfiles
+            .saturating_add(other.incomplete_checkfiles);
+        self.malformed_checkfiles = self
+            .malformed_checkfiles
+            .saturating_add(other.malformed_checkfiles);
+    }
+
+    fn print_warnings(&self, args: &Args) {
+        let plural =
+            |n: u64, one: &'static str, many: &'static str| if n == 1 { one } else { many };
+        if self.malformed > 0 {
+            eprintln!(
+                "{}: WARNING: {} line{} improperly formatted",
+                NAME,
+                self.malformed,
+                plural(self.malformed, " is", "s are"),
+            );
+        }
+        if self.missing > 0 {
+            eprintln!(
+                "{}: WARNING: {} listed file{} missing",
+                NAME,
+                self.missing,
+                plural(self.missing, " is", "s are"),
+            );
+        }
+        if self.unreadable > 0 {
+            eprintln!(
+                "{}: WARNING: {} listed file{} could not be read",
+                NAME,
+                self.unreadable,
+                plural(self.unreadable, "", "s"),
+            );
+        }
+        if self.mismatched > 0 {
+            eprintln!(
+                "{}: WARNING: {} computed checksum{} did NOT match",
+                NAME,
+                self.mismatched,
+                plural(self.mismatched, "", "s"),
+            );
+        }
+        if self.ignored > 0 && !args.quiet() {
+            eprintln!(
+                "{}: {} missing file{} ignored",
+                NAME,
+                self.ignored,
+                plural(self.ignored, "", "s"),
+            );
+        }
+    }
+
+    // Corrupted files take precedence over incomplete checks, which take precedence over
+    // formatting problems.
+    fn exit_code(&self, args: &Args) -> i32 {
+        if self.mismatched > 0 {
+            1
+        } else if self.missing > 0 || self.unreadable > 0 || self.incomplete_checkfiles > 0 {
+            3
+        } else if self.malformed_checkfiles > 0 || (args.strict() && self.malformed > 0) {
+            4
+        } else {
+            0
+        }
+    }
+}
+
+// Print the error for a file that couldn't be hashed, and classify it.
+fn check_hash_error(display_string: &str, e: &anyhow::Error, args: &Args) -> CheckStatus {
+    let is_missing = e
+        .downcast_ref::<io::Error>()
+        .is_some_and(|e| e.kind() == io::ErrorKind::NotFound);
+    if is_missing && args.ignore_missing() {
+        return CheckStatus::Ignored;
+    }
+    println!("{}: FAILED ({})", display_string, e);
+    if is_missing {
+        CheckStatus::Missing
+    } else {
+        CheckStatus::Unreadable
+    }
+}
+
+// Print the error for a line with an unsafe path, and count it as a failure. Any other parsing
+// error means the line was improperly formatted, and we return it.
+fn check_parse_error(e: anyhow::Error) -> anyhow::Result<CheckStatus> {
+    if e.is::<UnsafePathError>() {
+        eprintln!("{}: {}", NAME, e);
+        Ok(CheckStatus::Mismatch)
+    } else {
+        Err(e)
+    }
+}
+
+// An error here means that the line was improperly formatted. Any other failure is reported in
+// the returned CheckStatus. Returning the status, instead of passing down the CheckSummary,
+// makes it less likely that we might forget to count some error condition.
+fn check_one_line(line: &str, args: &Args) -> anyhow::Result<CheckStatus> {
     let ParsedCheckLine {
         file_string,
         is_escaped,
         file_path,
         expected_hash,
-    } = match parse_result {
+        is_tree,
+    } = match parse_check_line(line) {
         Ok(parsed) => parsed,
-        Err(e) => {
-            eprintln!("{}: {}", NAME, e);
-            return false;
-        }
+        Err(e) => return check_parse_error(e),
     };
     let file_string = if is_escaped {
         "\\".to_string() + &file_string
@@ -462,30 +1004,277 @@ fn check_one_line(line: &str, args: &Args) -> bool {
         file_string
     };
     let found_hash: blake3::Hash;
-    match hash_path(args, &file_path) {
-        Ok(mut output) => {
-            let mut found_hash_bytes = [0; blake3::OUT_LEN];
-            output.fill(&mut found_hash_bytes);
-            found_hash = found_hash_bytes.into();
+    if is_tree {
+        match tree_digest(&file_path, args) {
+            Ok(digest) => found_hash = digest,
+            Err(e) => return Ok(check_hash_error(&file_string, &e, args)),
         }
-        Err(e) => {
-            println!("{}: FAILED ({})", file_string, e);
-            return false;
+    } else {
+        let output_end = args.seek().saturating_add(blake3::OUT_LEN as u64);
+        match hash_path(args, &file_path, output_end) {
+            Ok(mut output) => {
+                let mut found_hash_bytes = [0; blake3::OUT_LEN];
+                output.fill(&mut found_hash_bytes);
+                found_hash = found_hash_bytes.into();
+            }
+            Err(e) => return Ok(check_hash_error(&file_string, &e, args)),
         }
-    };
+    }
     // This is a constant-time comparison.
     if expected_hash == found_hash {
         if !args.quiet() {
             println!("{}: OK", file_string);
         }
-        true
+        Ok(CheckStatus::Ok)
     } else {
         println!("{}: FAILED", file_string);
-        false
+        Ok(CheckStatus::Mismatch)
+    }
+}
+
+#[derive(Debug)]
+struct ParsedJsonRecord {
+    display_string: String,
+    file_path: PathBuf,
+    expected_output: Vec<u8>,
+    seek: u64,
+    // The "error" field of a file that couldn't be hashed when the checkfile was written.
+    recorded_error: Option<String>,
+}
+
+fn parse_json_u64(value: &json::Value) -> anyhow::Result<u64> {
+    match value {
+        json::Value::Number(text) => Ok(text.parse()?),
+        _ => bail!("Expected a number"),
     }
 }
 
-fn check_one_checkfile(path: &Path, args: &Args, files_failed: &mut u64) -> anyhow::Result<()> {
+fn parse_json_record(value: &json::Value) -> anyhow::Result<ParsedJsonRecord> {
+    let json::Value::Object(members) = value else {
+        bail!("Expected a JSON object");
+    };
+    let mut path = None;
+    let mut path_hex = None;
+    let mut hash_hex = None;
+    let mut length = None;
+    let mut seek = None;
+    let mut error = None;
+    for (key, value) in members {
+        let field = match key.as_str() {
+            "path" => &mut path,
+            "path_hex" => &mut path_hex,
+            "hash" => &mut hash_hex,
+            "length" => &mut length,
+            "seek" => &mut seek,
+            "error" => &mut error,
+            // Ignore unknown fields, for forward compatibility.
+            _ => continue,
+        };
+        ensure!(field.is_none(), "Duplicate field {key:?}");
+        *field = Some(value);
+    }
+
+    let (display_string, file_path) = match (path, path_hex) {
+        (Some(json::Value::String(path)), None) => {
+            ensure!(!path.is_empty(), "empty file path");
+            check_for_invalid_characters(path).map_err(UnsafePathError)?;
+            let FilepathString {
+                filepath_string,
+                is_escaped,
+            } = filepath_to_string(Path::new(path));
+            let prefix = if is_escaped { "\\" } else { "" };
+            (prefix.to_string() + &filepath_string, PathBuf::from(path))
+        }
+        #[cfg(unix)]
+        (None, Some(json::Value::String(path_hex))) => {
+            use std::os::unix::ffi::OsStrExt;
+            let bytes = hex::decode(path_hex)?;
+            ensure!(!bytes.is_empty(), "empty file path");
+            if bytes.contains(&0) {
+                let e = anyhow::anyhow!("Null character in path");
+                return Err(UnsafePathError(e).into());
+            }
+            let file_path = PathBuf::from(std::ffi::OsStr::from_bytes(&bytes));
+            (file_path.to_string_lossy().to_string(), file_path)
+        }
+        #[cfg(not(unix))]
+        (None, Some(_)) => bail!("path_hex is only supported on Unix"),
+        _ => bail!("Expected exactly one string \"path\" or \"path_hex\" field"),
+    };
+
+    if let Some(error) = error {
+        let json::Value::String(error) = error else {
+            bail!("Expected a string \"error\" field");
+        };
+        return Ok(ParsedJsonRecord {
+            display_string,
+            file_path,
+            expected_output: Vec::new(),
+            seek: 0,
+            recorded_error: Some(error.clone()),
+        });
+    }
+    let Some(json::Value::String(hash_hex)) = hash_hex else {
+        bail!("Expected a string \"hash\" field");
+    };
+    ensure!(
+        !hash_hex.is_empty() && hash_hex.len() % 2 == 0,
+        "Invalid hash length"
+    );
+    let mut expected_output = Vec::with_capacity(hash_hex.len() / 2);
+    let mut hex_chars = hash_hex.chars();
+    while let (Some(high_char), Some(low_char)) = (hex_chars.next(), hex_chars.next()) {
+        expected_output.push(16 * hex_half_byte(high_char)? + hex_half_byte(low_char)?);
+    }
+    if let Some(length) = length {
+        ensure!(
+            parse_json_u64(length)? == expected_output.len() as u64,
+            "Hash doesn't match length"
+        );
+    }
+    let seek = match seek {
+        Some(seek) => parse_json_u64(seek)?,
+        None => 0,
+    };
+
+    Ok(ParsedJsonRecord {
+        display_string,
+        file_path,
+        expected_output,
+        seek,
+        recorded_error: None,
+    })
+}
+
+// As check_one_line, for a JSON record.
+fn check_one_json_record(value: &json::Value, args: &Args) -> anyhow::Result<CheckStatus> {
+    let ParsedJsonRecord {
+        display_string,
+        file_path,
+        expected_output,
+        seek,
+        recorded_error,
+    } = match parse_json_record(value) {
+        Ok(parsed) => parsed,
+        Err(e) => return check_parse_error(e),
+    };
+    if let Some(e) = recorded_error {
+        // The file wasn't hashed, so there's nothing to check it against.
+        println!("{}: FAILED ({})", display_string, e);
+        return Ok(CheckStatus::Unreadable);
+    }
+    let mut found_output = vec![0; expected_output.len()];
+    let output_end = seek.saturating_add(expected_output.len() as u64);
+    match hash_path(args, &file_path, output_end) {
+        Ok(mut output) => {
+            output.set_position(seek);
+            output.fill(&mut found_output);
+        }
+        Err(e) => return Ok(check_hash_error(&display_string, &e, args)),
+    };
+    // Unlike blake3::Hash, this isn't a constant-time comparison. That's fine for checking files
+    // against a checkfile that we've just read, where the expected hashes aren't secret.
+    if expected_output == found_output {
+        if !args.quiet() {
+            println!("{}: OK", display_string);
+        }
+        Ok(CheckStatus::Ok)
+    } else {
+        println!("{}: FAILED", display_string);
+        Ok(CheckStatus::Mismatch)
+    }
+}
+
+// A JSON checkfile is either a single array of records, from --json, or JSON Lines, from
+// --json-lines. An invalid line in JSON Lines is reported like an improperly formatted line in a
+// regular checkfile, and an invalid array counts as a single improperly formatted line. For
+// --warn, array elements are numbered from 1, and JSON Lines records use their line numbers.
+fn check_one_json_checkfile(
+    path: &Path,
+    mut reader: impl BufRead,
+    args: &Args,
+    summary: &mut CheckSummary,
+) -> anyhow::Result<()> {
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+    let mut records = Vec::new();
+    if contents.trim_start().starts_with('[') {
+        match json::parse(&contents) {
+            Ok(json::Value::Array(elements)) => {
+                records.extend(elements.into_iter().map(Ok).enumerate());
+            }
+            Ok(_) => unreachable!(),
+            Err(e) => {
+                if args.warn() {
+                    eprintln!("{}: {}: {}", NAME, path.to_string_lossy(), e);
+                }
+                summary.add_malformed();
+            }
+        }
+    } else {
+        let lines = contents.lines().enumerate();
+        let lines = lines.filter(|(_, line)| !line.trim().is_empty());
+        records.extend(lines.map(|(i, line)| (i, json::parse(line))));
+    }
+    for (i, record) in records {
+        match record.and_then(|value| check_one_json_record(&value, args)) {
+            Ok(status) => summary.add(status),
+            Err(e) => report_malformed_line(path, i + 1, &e, args, summary),
+        }
+    }
+    Ok(())
+}
+
+fn report_malformed_line(
+    checkfile: &Path,
+    line_number: usize,
+    e: &anyhow::Error,
+    args: &Args,
+    summary: &mut CheckSummary,
+) {
+    if args.warn() {
+        eprintln!(
+            "{}: {}: {}: {}",
+            NAME,
+            checkfile.to_string_lossy(),
+            line_number,
+            e,
+        );
+    }
+    summary.add_malformed();
+}
+
+// Check every line of one checkfile, and add the results to `summary`. An error here means that
+// the checkfile itself couldn't be read.
+fn check_one_checkfile(path: &Path, args: &Args, summary: &mut CheckSummary) -> anyhow::Result<()> {
+    let mut checkfile_summary = CheckSummary::default();
+    let result = check_one_checkfile_inner(path, args, &mut checkfile_summary);
+    let formatted_lines = checkfile_summary.ok
+        + checkfile_summary.mismatched
+        + checkfile_summary.missing
+        + checkfile_summary.ignored
+        + checkfile_summary.unreadable;
+    if result.is_ok() && formatted_lines == 0 && checkfile_summary.malformed > 0 {
+        eprintln!(
+            "{}: {}: no properly formatted checksum lines found",
+            NAME,
+            path.to_string_lossy(),
+        );
+        checkfile_summary.malformed_checkfiles = 1;
+    } else if result.is_ok() && formatted_lines > 0 && formatted_lines == checkfile_summary.ignored
+    {
+        eprintln!("{}: {}: no file was verified", NAME, path.to_string_lossy());
+        checkfile_summary.incomplete_checkfiles = 1;
+    }
+    summary.merge(&checkfile_summary);
+    result
+}
+
+fn check_one_checkfile_inner(
+    path: &Path,
+    args: &Args,
+    summary: &mut CheckSummary,
+) -> anyhow::Result<()> {
     let mut file;
     let stdin;
     let mut stdin_lock;
@@ -498,20 +1287,22 @@ fn check_one_checkfile(path: &Path, args: &Args, files_failed: &mut u64) -> anyh
         file = File::open(path)?;
         bufreader = io::BufReader::new(&mut file);
     }
+    if args.json_format().is_some() {
+        return check_one_json_checkfile(path, bufreader, args, summary);
+    }
     let mut line = String::new();
+    let mut line_number = 0;
     loop {
         line.clear();
         let n = bufreader.read_line(&mut line)?;
         if n == 0 {
             return Ok(());
         }
-        // check_one_line() prints errors and turns them into a success=false
-        // return, so it doesn't return a Result.
-        let success = check_one_line(&line, args);
-        if !success {
-            // We use `files_failed > 0` to indicate a mismatch, so it's important for correctness
-            // that it's impossible for this counter to overflow.
-            *files_failed = files_failed.saturating_add(1);
+        line_number += 1;
+        // check_one_line() prints hashing errors itself and reports them in the returned status.
+        match check_one_line(&line, args) {
+            Ok(status) => summary.add(status),
+            Err(e) => report_malformed_line(path, line_number, &e, args, summary),
         }
     }
 }
@@ -525,31 +1316,75 @@ fn main() -> anyhow::Result<()> {
     let thread_pool = thread_pool_builder.build()?;
     thread_pool.install(|| {
         let mut files_failed = 0u64;
+        let mut check_summary = CheckSummary::default();
+        let mut json_records_written = 0u64;
+        // Errors encountered in hashing are tolerated and printed to stderr,
+        // or included in the output in JSON mode. This allows e.g. `b3sum *`
+        // to print errors for non-files and keep going. However, if we
+        // encounter any errors we'll still return non-zero at the end. This
+        // returns false on errors.
+        let mut hash_and_report = |path: &Path, walk_error: Option<anyhow::Error>| {
+            let result = if args.json_format().is_some() {
+                hash_one_input_json(path, walk_error, &args, &mut json_records_written)
+            } else {
+                let result = match walk_error {
+                    Some(e) => Err(e),
+                    None => hash_one_input(path, &args),
+                };
+                if let Err(e) = &result {
+                    eprintln!("{}: {}: {}", NAME, path.to_string_lossy(), e);
+                }
+                result
+            };
+            result.is_ok()
+        };
         // Note that file_args automatically includes `-` if nothing is given.
         for path in &args.file_args {
             if args.check() {
-                check_one_checkfile(path, &args, &mut files_failed)?;
-            } else {
-                // Errors encountered in hashing are tolerated and printed to
-                // stderr. This allows e.g. `b3sum *` to print errors for
-                // non-files and keep going. However, if we encounter any
-                // errors we'll still return non-zero at the end.
-                let result = hash_one_input(path, &args);
-                if let Err(e) = result {
+                if let Err(e) = check_one_checkfile(path, &args, &mut check_summary) {
+                    check_summary.incomplete_checkfiles =
+                        check_summary.incomplete_checkfiles.saturating_add(1);
+                    eprintln!("{}: {}: {}", NAME, path.to_string_lossy(), e);
+                }
+            } else if args.tree_digest() {
+                if let Err(e) = hash_one_tree(path, &args) {
                     files_failed = files_failed.saturating_add(1);
                     eprintln!("{}: {}: {}", NAME, path.to_string_lossy(), e);
                 }
+            } else if args.recursive() {
+                walk::walk(path, &args.walk_options(), &mut |entry| {
+                    let success = match entry {
+                        walk::Entry::File(path) => hash_and_report(&path, None),
+                        walk::Entry::Error(path, e) => hash_and_report(&path, Some(e)),
+                    };
+                    if !success {
+                        files_failed = files_failed.saturating_add(1);
+                    }
+                });
+            } else if !hash_and_report(path, None) {
+                files_failed = files_failed.saturating_add(1);
             }
         }
-        if args.check() && files_failed > 0 {
-            eprintln!(
-                "{}: WARNING: {} computed checksum{} did NOT match",
-                NAME,
-                files_failed,
-                if files_failed == 1 { "" } else { "s" },
-            );
+        if !args.check() && args.json_format().is_some() {
+            finish_json_output(&args, json_records_written);
+        }
+        let mut exit_code = if args.check() {
+            check_summary.print_warnings(&args);
+            check_summary.exit_code(&args)
+        } else if files_failed > 0 {
+            1
+        } else {
+            0
+        };
+        if let Some(cache) = args.cache() {
+            if let Err(e) = cache.save() {
+                eprintln!("{}: failed to save cache: {}", NAME, e);
+                if exit_code == 0 {
+                    exit_code = 1;
+                }
+            }
         }
-        std::process::exit(if files_failed > 0 { 1 } else { 0 });
+        std::process::exit(exit_code);
     })
 }
 
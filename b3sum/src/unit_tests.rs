@@ -12,6 +12,7 @@ fn test_parse_check_line() {
         is_escaped,
         file_path,
         expected_hash,
+        ..
     } = crate::parse_check_line(
         "0909090909090909090909090909090909090909090909090909090909090909  foo",
     )
@@ -27,6 +28,7 @@ fn test_parse_check_line() {
         is_escaped,
         file_path,
         expected_hash,
+        ..
     } = crate::parse_check_line(
         "fafafafafafafafafafafafafafafafafafafafafafafafafafafafafafafafa   \t\r\n\n\r \t\r\n\n\r",
     )
@@ -42,6 +44,7 @@ fn test_parse_check_line() {
         is_escaped,
         file_path,
         expected_hash,
+        ..
     } = crate::parse_check_line(
         "4242424242424242424242424242424242424242424242424242424242424242   ",
     )
@@ -61,6 +64,7 @@ fn test_parse_check_line() {
             is_escaped,
             file_path,
             expected_hash,
+            ..
         } = crate::parse_check_line(
             "4343434343434343434343434343434343434343434343434343434343434343  fo\\a\\no",
         )
@@ -77,6 +81,7 @@ fn test_parse_check_line() {
         is_escaped,
         file_path,
         expected_hash,
+        ..
     } = crate::parse_check_line(
         "\\4444444444444444444444444444444444444444444444444444444444444444  fo\\r\\n\\n\\ro",
     )
@@ -94,6 +99,7 @@ fn test_parse_check_line() {
             is_escaped,
             file_path,
             expected_hash,
+            ..
         } = crate::parse_check_line(
             "\\4545454545454545454545454545454545454545454545454545454545454545  fo\\n\\\\o",
         )
@@ -110,6 +116,7 @@ fn test_parse_check_line() {
         is_escaped,
         file_path,
         expected_hash,
+        ..
     } = crate::parse_check_line(
         "4646464646464646464646464646464646464646464646464646464646464646  否认",
     )
@@ -125,6 +132,7 @@ fn test_parse_check_line() {
         is_escaped,
         file_path,
         expected_hash,
+        ..
     } = crate::parse_check_line(
         "4747474747474747474747474747474747474747474747474747474747474747  foo  bar",
     )
@@ -140,6 +148,7 @@ fn test_parse_check_line() {
         is_escaped,
         file_path,
         expected_hash,
+        ..
     } = crate::parse_check_line(
         "BLAKE3 (foo) = bar) = 4848484848484848484848484848484848484848484848484848484848484848",
     )
@@ -149,6 +158,20 @@ fn test_parse_check_line() {
     assert_eq!(file_string, "foo) = bar");
     assert_eq!(file_path, Path::new("foo) = bar"));
 
+    // tree digest
+    let crate::ParsedCheckLine {
+        file_string,
+        expected_hash,
+        is_tree,
+        ..
+    } = crate::parse_check_line(
+        "BLAKE3-TREE (dir) = 4949494949494949494949494949494949494949494949494949494949494949",
+    )
+    .unwrap();
+    assert_eq!(expected_hash, blake3::Hash::from([0x49; 32]));
+    assert_eq!(file_string, "dir");
+    assert!(is_tree);
+
     // =========================
     // ===== Failure Cases =====
     // =========================
@@ -233,3 +256,191 @@ fn test_filepath_to_string() {
     }
     assert!(output.is_escaped);
 }
+
+#[test]
+fn test_exclude_patterns() {
+    use crate::walk::Pattern;
+    let matches = |pattern: &str, path: &str| Pattern::parse(pattern).unwrap().matches_entry(path);
+
+    // Patterns without a slash match the name at any depth.
+    assert!(matches("*.o", "foo.o"));
+    assert!(matches("*.o", "src/foo.o"));
+    assert!(!matches("*.o", "foo.obj"));
+    assert!(matches("target", "a/b/target"));
+    assert!(matches("target/", "target"));
+    assert!(matches("?.txt", "a/b.txt"));
+    assert!(!matches("?.txt", "ab.txt"));
+
+    // Patterns with a slash match the whole relative path.
+    assert!(matches("src/*.o", "src/foo.o"));
+    assert!(!matches("src/*.o", "src/a/foo.o"));
+    assert!(!matches("src/*.o", "x/src/foo.o"));
+    assert!(matches("/build", "build"));
+    assert!(matches("src/**", "src/a/b/c"));
+    assert!(matches("**/foo", "foo"));
+    assert!(matches("**/foo", "a/b/foo"));
+    assert!(!matches("**/foo", "a/bfoo"));
+    assert!(matches("a/**/b", "a/b"));
+    assert!(matches("a/**/b", "a/x/y/b"));
+
+    // Character classes and escapes.
+    assert!(matches("[abc].txt", "b.txt"));
+    assert!(!matches("[abc].txt", "d.txt"));
+    assert!(matches("file[0-9]", "file7"));
+    assert!(!matches("file[!0-9]", "file7"));
+    assert!(matches("file[!0-9]", "filex"));
+    assert!(matches("[]]", "]"));
+    assert!(matches("\\*", "*"));
+    assert!(!matches("\\*", "a"));
+
+    // Many stars don't make matching exponentially slow.
+    let long_name = "a".repeat(200);
+    assert!(!matches("*a*a*a*a*a*a*a*a*a*a*b", &long_name));
+    assert!(!matches("**a**a**a**a**a**a**a**a**b", &long_name));
+    assert!(matches("*a*a*a*a*a*a*a*a*a*a*", &long_name));
+
+    // Invalid patterns.
+    Pattern::parse("").unwrap_err();
+    Pattern::parse("/").unwrap_err();
+    Pattern::parse("[abc").unwrap_err();
+    Pattern::parse("[z-a]").unwrap_err();
+    Pattern::parse("foo\\").unwrap_err();
+}
+
+#[test]
+fn test_json() {
+    use crate::json::{Value, parse, write_string};
+
+    let mut out = String::new();
+    write_string(&mut out, "a\"b\\c\nd\u{1}é");
+    assert_eq!(out, r#""a\"b\\c\nd\u0001é""#);
+    assert_eq!(
+        parse(&out).unwrap(),
+        Value::String("a\"b\\c\nd\u{1}é".into())
+    );
+
+    assert_eq!(
+        parse(r#" {"a": [1, -2.5e3, true, false, null], "b": {}} "#).unwrap(),
+        Value::Object(vec![
+            (
+                "a".into(),
+                Value::Array(vec![
+                    Value::Number("1".into()),
+                    Value::Number("-2.5e3".into()),
+                    Value::Bool(true),
+                    Value::Bool(false),
+                    Value::Null,
+                ])
+            ),
+            ("b".into(), Value::Object(vec![])),
+        ])
+    );
+    assert_eq!(
+        parse(r#""\ud83d\ude00\u00e9\/""#).unwrap(),
+        Value::String("😀é/".into())
+    );
+
+    // Invalid JSON.
+    for input in [
+        "",
+        "[",
+        "[1,]",
+        "{\"a\"}",
+        "{1: 2}",
+        "01",
+        "1.",
+        "nul",
+        "\"\\x\"",
+        "\"\\ud83d\"",
+        "\"\n\"",
+        "[] []",
+    ] {
+        parse(input).unwrap_err();
+    }
+    let deep = "[".repeat(1000) + &"]".repeat(1000);
+    parse(&deep).unwrap_err();
+}
+
+#[test]
+fn test_cache() {
+    use crate::cache::Cache;
+    use std::cell::Cell;
+    use std::time::{Duration, SystemTime};
+
+    let dir = tempfile::tempdir().unwrap();
+    let file_path = dir.path().join("file");
+    let cache_path = dir.path().join("cache");
+    let set_old_mtime = || {
+        let old = SystemTime::now() - Duration::from_secs(3600);
+        let file = std::fs::File::options()
+            .write(true)
+            .open(&file_path)
+            .unwrap();
+        file.set_modified(old).unwrap();
+    };
+    std::fs::write(&file_path, b"foo").unwrap();
+    set_old_mtime();
+
+    let calls = Cell::new(0);
+    let hasher = blake3::Hasher::new();
+    let hash_with = |cache: &Cache, hasher: &blake3::Hasher, rehash: bool| {
+        cache
+            .get_or_hash(&file_path, hasher, rehash, || {
+                calls.set(calls.get() + 1);
+                let mut hasher = hasher.clone();
+                hasher.update(&std::fs::read(&file_path)?);
+                Ok(hasher.finalize())
+            })
+            .unwrap()
+    };
+
+    // The first lookup hashes the file, and the second one doesn't.
+    let cache = Cache::load(&cache_path).unwrap();
+    assert_eq!(hash_with(&cache, &hasher, false), blake3::hash(b"foo"));
+    assert_eq!(hash_with(&cache, &hasher, false), blake3::hash(b"foo"));
+    assert_eq!(calls.get(), 1);
+    // --rehash always hashes the file.
+    assert_eq!(hash_with(&cache, &hasher, true), blake3::hash(b"foo"));
+    assert_eq!(calls.get(), 2);
+    // A different mode is a different entry.
+    let keyed = blake3::Hasher::new_keyed(&[42; 32]);
+    assert_eq!(
+        hash_with(&cache, &keyed, false),
+        blake3::keyed_hash(&[42; 32], b"foo"),
+    );
+    assert_eq!(calls.get(), 3);
+
+    // The entries survive saving and loading.
+    cache.save().unwrap();
+    let cache = Cache::load(&cache_path).unwrap();
+    assert_eq!(hash_with(&cache, &hasher, false), blake3::hash(b"foo"));
+    assert_eq!(
+        hash_with(&cache, &keyed, false),
+        blake3::keyed_hash(&[42; 32], b"foo"),
+    );
+    assert_eq!(calls.get(), 3);
+
+    // Changing the file invalidates its entry, even if the modification time doesn't change.
+    std::fs::write(&file_path, b"quux").unwrap();
+    set_old_mtime();
+    assert_eq!(hash_with(&cache, &hasher, false), blake3::hash(b"quux"));
+    assert_eq!(calls.get(), 4);
+
+    // Recently modified files aren't cached.
+    std::fs::write(&file_path, b"baz").unwrap();
+    assert_eq!(hash_with(&cache, &hasher, false), blake3::hash(b"baz"));
+    assert_eq!(hash_with(&cache, &hasher, false), blake3::hash(b"baz"));
+    assert_eq!(calls.get(), 6);
+
+    // A corrupt cache file is ignored.
+    let mut bytes = std::fs::read(&cache_path).unwrap();
+    let last = bytes.len() - 1;
+    bytes[last] ^= 1;
+    std::fs::write(&cache_path, &bytes).unwrap();
+    set_old_mtime();
+    let cache = Cache::load(&cache_path).unwrap();
+    assert_eq!(hash_with(&cache, &hasher, false), blake3::hash(b"baz"));
+    assert_eq!(calls.get(), 7);
+    std::fs::write(&cache_path, b"B3SC\x02").unwrap();
+    Cache::load(&cache_path).unwrap();
+}
@@ -0,0 +1,302 @@
+//! Directory walking for `--recursive`.
+//!
+//! The walk is deterministic: the entries of each directory are visited in sorted order, and
+//! subdirectories are walked depth-first at the position where they sort. The paths we produce
+//! are the command line argument joined with the path relative to it, so that `b3sum --check`
+//! run from the same directory reads them back unchanged.
+
+use anyhow::{bail, ensure};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct WalkOptions<'a> {
+    /// Follow symlinks found inside directories. Symlinks given on the command line are always
+    /// followed. When this is false, symlinks inside directories are skipped.
+    pub follow_symlinks: bool,
+    /// Include files and directories whose names start with a dot.
+    pub hidden: bool,
+    /// Skip files and directories that match any of these patterns.
+    pub excludes: &'a [Pattern],
+}
+
+pub enum Entry {
+    /// A file to hash. This might also be something we couldn't stat, like a missing file given on
+    /// the command line. In that case, opening it will produce the appropriate error.
+    File(PathBuf),
+    /// An error reading a directory, or a symlink loop.
+    Error(PathBuf, anyhow::Error),
+}
+
+/// Walk `root`, calling `visit` with each file in order. If `root` isn't a directory, that's just
+/// `root` itself.
+pub fn walk(root: &Path, options: &WalkOptions, visit: &mut dyn FnMut(Entry)) {
+    // Note that fs::metadata follows symlinks.
+    if root == Path::new("-") || !fs::metadata(root).is_ok_and(|m| m.is_dir()) {
+        visit(Entry::File(root.to_owned()));
+        return;
+    }
+    // Canonicalized paths of the directories we're currently inside, for detecting symlink loops.
+    // We only need these when we're following symlinks.
+    let mut ancestors = Vec::new();
+    if options.follow_symlinks {
+        match fs::canonicalize(root) {
+            Ok(canonical) => ancestors.push(canonical),
+            Err(e) => return visit(Entry::Error(root.to_owned(), e.into())),
+        }
+    }
+    walk_dir(root, "", options, &mut ancestors, visit);
+}
+
+fn walk_dir(
+    dir: &Path,
+    relative_dir: &str,
+    options: &WalkOptions,
+    ancestors: &mut Vec<PathBuf>,
+    visit: &mut dyn FnMut(Entry),
+) {
+    let mut entries: Vec<(OsString, fs::DirEntry)> = Vec::new();
+    let read_dir = match fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) => return visit(Entry::Error(dir.to_owned(), e.into())),
+    };
+    for entry in read_dir {
+        match entry {
+            Ok(entry) => entries.push((entry.file_name(), entry)),
+            // Report the error, but keep the other entries.
+            Err(e) => visit(Entry::Error(dir.to_owned(), e.into())),
+        }
+    }
+    // Sort by name, so that the output doesn't depend on the filesystem's directory order.
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    for (name, entry) in entries {
+        let name_string = name.to_string_lossy();
+        if !options.hidden && name_string.starts_with('.') {
+            continue;
+        }
+        let relative_path = if relative_dir.is_empty() {
+            name_string.to_string()
+        } else {
+            format!("{relative_dir}/{name_string}")
+        };
+        if options
+            .excludes
+            .iter()
+            .any(|pattern| pattern.matches_entry(&relative_path))
+        {
+            continue;
+        }
+        let path = dir.join(&name);
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(e) => {
+                visit(Entry::Error(path, e.into()));
+                continue;
+            }
+        };
+        let file_type = if file_type.is_symlink() {
+            if !options.follow_symlinks {
+                continue;
+            }
+            match fs::metadata(&path) {
+                Ok(metadata) => metadata.file_type(),
+                // A broken symlink. Opening it will report the error.
+                Err(_) => {
+                    visit(Entry::File(path));
+                    continue;
+                }
+            }
+        } else {
+            file_type
+        };
+        if file_type.is_file() {
+            visit(Entry::File(path));
+            continue;
+        }
+        // Skip FIFOs, sockets, and devices. Reading a FIFO could block forever.
+        if !file_type.is_dir() {
+            continue;
+        }
+        if options.follow_symlinks {
+            let canonical = match fs::canonicalize(&path) {
+                Ok(canonical) => canonical,
+                Err(e) => {
+                    visit(Entry::Error(path, e.into()));
+                    continue;
+                }
+            };
+            if ancestors.contains(&canonical) {
+                visit(Entry::Error(path, anyhow::anyhow!("Symlink loop")));
+                continue;
+            }
+            ancestors.push(canonical);
+            walk_dir(&path, &relative_path, options, ancestors, visit);
+            ancestors.pop();
+        } else {
+            walk_dir(&path, &relative_path, options, ancestors, visit);
+        }
+    }
+}
+
+/// A glob pattern for `--exclude`.
+///
+/// `*` matches any sequence of characters other than `/`, `?` matches any single character other
+/// than `/`, `**` matches any sequence of characters including `/`, and `[...]` matches a
+/// character class like `[abc]`, `[a-z]`, or `[!0-9]`. A backslash escapes the next character.
+/// Like a `.gitignore` line, a pattern without a `/` matches the name of a file or directory at
+/// any depth, and a pattern with a `/` matches its path relative to the directory being walked.
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    tokens: Vec<Token>,
+    match_full_path: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Literal(char),
+    AnyChar,
+    Star,
+    DoubleStar,
+    // "**/", which also matches zero directories.
+    DoubleStarSlash,
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Pattern {
+    pub fn parse(pattern: &str) -> anyhow::Result<Self> {
+        ensure!(!pattern.is_empty(), "Empty pattern");
+        // A trailing slash doesn't mean anything special here, but it's a common way to write a
+        // directory in .gitignore, so don't let it make the pattern unmatchable.
+        let trimmed = pattern.trim_end_matches('/');
+        ensure!(!trimmed.is_empty(), "Pattern matches nothing");
+        let trimmed = trimmed.strip_prefix('/').unwrap_or(trimmed);
+        let match_full_path = pattern.trim_end_matches('/').contains('/');
+        let mut tokens = Vec::new();
+        let mut chars = trimmed.chars().peekable();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '\\' => match chars.next() {
+                    Some(escaped) => Token::Literal(escaped),
+                    None => bail!("Trailing backslash in pattern"),
+                },
+                '?' => Token::AnyChar,
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        Token::DoubleStarSlash
+                    } else {
+                        Token::DoubleStar
+                    }
+                }
+                '*' => Token::Star,
+                '[' => {
+                    let negated = chars.next_if(|&c| c == '!' || c == '^').is_some();
+                    let mut ranges = Vec::new();
+                    let mut first = true;
+                    loop {
+                        let Some(start) = chars.next() else {
+                            bail!("Unclosed [ in pattern");
+                        };
+                        // A ] right after the [ is a literal.
+                        if start == ']' && !first {
+                            break;
+                        }
+                        first = false;
+                        let end = if chars.next_if_eq(&'-').is_some() {
+                            match chars.next() {
+                                Some(']') | None => bail!("Invalid range in pattern"),
+                                Some(end) => end,
+                            }
+                        } else {
+                            start
+                        };
+                        ensure!(start <= end, "Invalid range in pattern");
+                        ranges.push((start, end));
+                    }
+                    Token::Class { negated, ranges }
+                }
+                c => Token::Literal(c),
+            };
+            tokens.push(token);
+        }
+        Ok(Self {
+            tokens,
+            match_full_path,
+        })
+    }
+
+    /// Match a path relative to the walk root, with `/` separators.
+    pub fn matches_entry(&self, relative_path: &str) -> bool {
+        if self.match_full_path {
+            self.matches(relative_path)
+        } else {
+            let name = relative_path.rsplit('/').next().unwrap();
+            self.matches(name)
+        }
+    }
+
+    fn matches(&self, s: &str) -> bool {
+        let chars: Vec<char> = s.chars().collect();
+        matches_tokens(&self.tokens, &chars)
+    }
+}
+
+fn matches_tokens(tokens: &[Token], s: &[char]) -> bool {
+    // Backtracking over every way to split the input between stars is exponential in the number
+    // of stars, so remember the result for each (token index, position) pair.
+    let mut memo = vec![None; (tokens.len() + 1) * (s.len() + 1)];
+    matches_at(tokens, s, 0, 0, &mut memo)
+}
+
+fn matches_at(
+    tokens: &[Token],
+    s: &[char],
+    token_index: usize,
+    position: usize,
+    memo: &mut [Option<bool>],
+) -> bool {
+    let memo_index = token_index * (s.len() + 1) + position;
+    if let Some(result) = memo[memo_index] {
+        return result;
+    }
+    let next = token_index + 1;
+    let c = s.get(position).copied();
+    let result = match tokens.get(token_index) {
+        None => position == s.len(),
+        Some(Token::Literal(literal)) => {
+            c == Some(*literal) && matches_at(tokens, s, next, position + 1, memo)
+        }
+        Some(Token::AnyChar) => {
+            c.is_some_and(|c| c != '/') && matches_at(tokens, s, next, position + 1, memo)
+        }
+        Some(Token::Class { negated, ranges }) => {
+            c.is_some_and(|c| {
+                c != '/' && ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated
+            }) && matches_at(tokens, s, next, position + 1, memo)
+        }
+        Some(Token::Star) => {
+            // Try every prefix that doesn't cross a separator, including the empty one.
+            let max = s[position..]
+                .iter()
+                .position(|&c| c == '/')
+                .map_or(s.len(), |i| position + i);
+            (position..=max).any(|i| matches_at(tokens, s, next, i, memo))
+        }
+        Some(Token::DoubleStar) => {
+            (position..=s.len()).any(|i| matches_at(tokens, s, next, i, memo))
+        }
+        Some(Token::DoubleStarSlash) => {
+            // Zero directories, or any prefix that ends in a separator.
+            matches_at(tokens, s, next, position, memo)
+                || (position..s.len())
+                    .any(|i| s[i] == '/' && matches_at(tokens, s, next, i + 1, memo))
+        }
+    };
+    memo[memo_index] = Some(result);
+    result
+}
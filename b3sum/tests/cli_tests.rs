@@ -529,7 +529,7 @@ fn test_check() {
         a: OK\n\
         b: FAILED\n\
         c/d: OK\n";
-        assert!(!output.status.success());
+        assert_eq!(output.status.code(), Some(1));
         assert_eq!(expected_check_failure, stdout);
         assert_eq!(
             "b3sum: WARNING: 1 computed checksum did NOT match\n",
@@ -554,12 +554,9 @@ fn test_check() {
          c/d: OK\n",
             open_file_error,
         );
-        assert!(!output.status.success());
+        assert_eq!(output.status.code(), Some(3));
         assert_eq!(expected_check_failure, stdout);
-        assert_eq!(
-            "b3sum: WARNING: 1 computed checksum did NOT match\n",
-            stderr,
-        );
+        assert_eq!("b3sum: WARNING: 1 listed file is missing\n", stderr);
 
         // Confirm that --quiet suppresses the OKs but not the FAILEDs.
         let output = cmd!(b3sum_exe(), "--check", "--quiet", &checkfile_path)
@@ -572,12 +569,21 @@ fn test_check() {
         let stdout = std::str::from_utf8(&output.stdout).unwrap();
         let stderr = std::str::from_utf8(&output.stderr).unwrap();
         let expected_check_failure = format!("b: FAILED ({})\n", open_file_error);
-        assert!(!output.status.success());
+        assert_eq!(output.status.code(), Some(3));
         assert_eq!(expected_check_failure, stdout);
-        assert_eq!(
-            "b3sum: WARNING: 1 computed checksum did NOT match\n",
-            stderr,
-        );
+        assert_eq!("b3sum: WARNING: 1 listed file is missing\n", stderr);
+
+        // --ignore-missing skips the missing file.
+        let output = cmd!(b3sum_exe(), "--check", "--ignore-missing", &checkfile_path)
+            .dir(dir.path())
+            .stdout_capture()
+            .stderr_capture()
+            .run()
+            .unwrap();
+        let stdout = std::str::from_utf8(&output.stdout).unwrap();
+        let stderr = std::str::from_utf8(&output.stderr).unwrap();
+        assert_eq!("a: OK\nc/d: OK\n", stdout);
+        assert_eq!("b3sum: 1 missing file ignored\n", stderr);
     }
 }
 
@@ -655,6 +661,666 @@ fn test_check_invalid_characters() {
     }
 }
 
+#[test]
+fn test_check_unsafe_path_with_valid_lines() {
+    // A line with an unsafe path is a failure, not an improperly formatted line that's skipped,
+    // even when other lines are fine.
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a"), b"a").unwrap();
+    let checkfile = format!(
+        "{}  a\n0000000000000000000000000000000000000000000000000000000000000000  \0\n",
+        blake3::hash(b"a"),
+    );
+    let output = cmd!(b3sum_exe(), "--check")
+        .stdin_bytes(checkfile)
+        .dir(dir.path())
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(&b"a: OK\n"[..], &output.stdout[..]);
+    let expected_stderr = "\
+        b3sum: Null character in path\n\
+        b3sum: WARNING: 1 computed checksum did NOT match\n";
+    assert_eq!(expected_stderr.as_bytes(), &output.stderr[..]);
+}
+
+#[test]
+fn test_recursive() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("tree/sub/deeper")).unwrap();
+    fs::create_dir(dir.path().join("tree/.git")).unwrap();
+    fs::create_dir(dir.path().join("tree/target")).unwrap();
+    fs::write(dir.path().join("tree/b"), b"b").unwrap();
+    fs::write(dir.path().join("tree/a"), b"a").unwrap();
+    fs::write(dir.path().join("tree/sub/c"), b"c").unwrap();
+    fs::write(dir.path().join("tree/sub/deeper/d"), b"d").unwrap();
+    fs::write(dir.path().join("tree/sub/e.o"), b"e").unwrap();
+    fs::write(dir.path().join("tree/.hidden"), b"h").unwrap();
+    fs::write(dir.path().join("tree/.git/config"), b"g").unwrap();
+    fs::write(dir.path().join("tree/target/t"), b"t").unwrap();
+    fs::write(dir.path().join("top"), b"top").unwrap();
+    let line = |contents: &[u8], path: &str| format!("{}  {}\n", blake3::hash(contents), path);
+
+    // Files are sorted within each directory, directories are walked where they sort, and hidden
+    // files are skipped. Non-directory arguments are hashed as usual.
+    let output = cmd!(b3sum_exe(), "--recursive", "tree", "top")
+        .dir(dir.path())
+        .read()
+        .unwrap();
+    let expected = line(b"a", "tree/a")
+        + &line(b"b", "tree/b")
+        + &line(b"c", "tree/sub/c")
+        + &line(b"d", "tree/sub/deeper/d")
+        + &line(b"e", "tree/sub/e.o")
+        + &line(b"t", "tree/target/t")
+        + &line(b"top", "top");
+    assert_eq!(expected.trim_end(), output);
+
+    // The output works as a checkfile.
+    let check_output = cmd!(b3sum_exe(), "--check", "--quiet")
+        .stdin_bytes(expected.as_bytes())
+        .dir(dir.path())
+        .read()
+        .unwrap();
+    assert_eq!("", check_output);
+
+    // --hidden and --exclude.
+    let output = cmd!(
+        b3sum_exe(),
+        "-r",
+        "--hidden",
+        "--exclude",
+        "*.o",
+        "--exclude",
+        "/target",
+        "--exclude",
+        "sub/deeper",
+        "tree"
+    )
+    .dir(dir.path())
+    .read()
+    .unwrap();
+    let expected = line(b"g", "tree/.git/config")
+        + &line(b"h", "tree/.hidden")
+        + &line(b"a", "tree/a")
+        + &line(b"b", "tree/b")
+        + &line(b"c", "tree/sub/c");
+    assert_eq!(expected.trim_end(), output);
+
+    // These flags require --recursive, and --recursive doesn't work with --check or --raw.
+    for args in [
+        &["--hidden", "tree"][..],
+        &["--follow-symlinks", "tree"],
+        &["--exclude", "x", "tree"],
+        &["-r", "--check", "tree"],
+        &["-r", "--raw", "tree"],
+        &["-r", "--exclude", "[x", "tree"],
+    ] {
+        let result = cmd(b3sum_exe(), args)
+            .dir(dir.path())
+            .stdout_capture()
+            .stderr_capture()
+            .run();
+        assert!(result.is_err(), "{args:?}");
+    }
+}
+
+#[test]
+#[cfg(unix)]
+fn test_recursive_symlinks() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("tree/sub")).unwrap();
+    fs::create_dir(dir.path().join("outside")).unwrap();
+    fs::write(dir.path().join("tree/sub/a"), b"a").unwrap();
+    fs::write(dir.path().join("outside/b"), b"b").unwrap();
+    std::os::unix::fs::symlink("../outside", dir.path().join("tree/link_dir")).unwrap();
+    std::os::unix::fs::symlink("sub/a", dir.path().join("tree/link_file")).unwrap();
+    std::os::unix::fs::symlink("..", dir.path().join("tree/sub/loop")).unwrap();
+    let line = |contents: &[u8], path: &str| format!("{}  {}\n", blake3::hash(contents), path);
+
+    // By default, symlinks inside directories are skipped.
+    let output = cmd!(b3sum_exe(), "-r", "tree")
+        .dir(dir.path())
+        .read()
+        .unwrap();
+    assert_eq!(line(b"a", "tree/sub/a").trim_end(), output);
+
+    // A symlink on the command line is followed.
+    let output = cmd!(b3sum_exe(), "-r", "tree/link_dir")
+        .dir(dir.path())
+        .read()
+        .unwrap();
+    assert_eq!(line(b"b", "tree/link_dir/b").trim_end(), output);
+
+    // With --follow-symlinks, they're followed, and loops are errors.
+    let output = cmd!(b3sum_exe(), "-r", "--follow-symlinks", "tree")
+        .dir(dir.path())
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .unwrap();
+    assert!(!output.status.success());
+    let expected =
+        line(b"b", "tree/link_dir/b") + &line(b"a", "tree/link_file") + &line(b"a", "tree/sub/a");
+    assert_eq!(expected.as_bytes(), &output.stdout[..]);
+    assert_eq!(
+        "b3sum: tree/sub/loop: Symlink loop\n".as_bytes(),
+        &output.stderr[..],
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn test_recursive_skips_special_files() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir(dir.path().join("tree")).unwrap();
+    fs::write(dir.path().join("tree/a"), b"a").unwrap();
+    cmd!("mkfifo", dir.path().join("tree/fifo")).run().unwrap();
+    std::os::unix::fs::symlink("fifo", dir.path().join("tree/link")).unwrap();
+
+    // Reading the FIFO would block forever, so it's skipped, even through a followed symlink.
+    for extra_args in [&[][..], &["--follow-symlinks"]] {
+        let output = cmd(b3sum_exe(), extra_args.iter().chain(&["-r", "tree"]))
+            .dir(dir.path())
+            .read()
+            .unwrap();
+        assert_eq!(
+            format!("{}  tree/a", blake3::hash(b"a")),
+            output,
+            "{extra_args:?}",
+        );
+    }
+}
+
+#[test]
+// Tree digests need Unix permission bits.
+#[cfg(unix)]
+fn test_tree_digest() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::create_dir_all(dir.path().join("tree/sub/empty")).unwrap();
+    fs::write(dir.path().join("tree/a"), b"a").unwrap();
+    fs::write(dir.path().join("tree/sub/.hidden"), vec![0xab; 100_000]).unwrap();
+    fs::write(dir.path().join("file"), b"file").unwrap();
+    let expected_digest = blake3::tree_digest::digest_dir(dir.path().join("tree")).unwrap();
+
+    for extra_args in [&[][..], &["--no-mmap"]] {
+        let output = cmd(
+            b3sum_exe(),
+            extra_args.iter().chain(&["--tree-digest", "tree"]),
+        )
+        .dir(dir.path())
+        .read()
+        .unwrap();
+        assert_eq!(format!("BLAKE3-TREE (tree) = {}", expected_digest), output);
+    }
+    let output = cmd!(b3sum_exe(), "--tree-digest", "--no-names", "tree")
+        .dir(dir.path())
+        .read()
+        .unwrap();
+    assert_eq!(expected_digest.to_hex().as_str(), output);
+
+    // --check verifies tree digest lines, alongside regular lines.
+    let checkfile = format!(
+        "BLAKE3-TREE (tree) = {}\n{}  file\n",
+        expected_digest,
+        blake3::hash(b"file"),
+    );
+    let output = cmd!(b3sum_exe(), "--check")
+        .stdin_bytes(checkfile.as_bytes())
+        .dir(dir.path())
+        .read()
+        .unwrap();
+    assert_eq!("tree: OK\nfile: OK", output);
+
+    // Changing a file changes the digest.
+    fs::write(dir.path().join("tree/a"), b"b").unwrap();
+    let output = cmd!(b3sum_exe(), "--tree-digest", "--no-names", "tree")
+        .dir(dir.path())
+        .read()
+        .unwrap();
+    assert_ne!(expected_digest.to_hex().as_str(), output);
+    let output = cmd!(b3sum_exe(), "--check")
+        .stdin_bytes(checkfile.as_bytes())
+        .dir(dir.path())
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(&b"tree: FAILED\nfile: OK\n"[..], &output.stdout[..]);
+
+    // Regular files and stdin aren't directories.
+    for arg in ["file", "-"] {
+        let output = cmd!(b3sum_exe(), "--tree-digest", arg)
+            .dir(dir.path())
+            .stdin_bytes("")
+            .stdout_capture()
+            .stderr_capture()
+            .unchecked()
+            .run()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(output.stdout.is_empty());
+    }
+}
+
+#[test]
+fn test_json() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a"), b"foo").unwrap();
+    fs::write(dir.path().join("b\"c"), b"bar").unwrap();
+    let foo_hash = blake3::hash(b"foo");
+    let bar_hash = blake3::hash(b"bar");
+
+    let output = cmd!(b3sum_exe(), "--json", "a", "b\"c")
+        .dir(dir.path())
+        .read()
+        .unwrap();
+    let expected = format!(
+        "\
+[
+{{\"path\":\"a\",\"hash\":\"{}\",\"length\":32,\"seek\":0}},
+{{\"path\":\"b\\\"c\",\"hash\":\"{}\",\"length\":32,\"seek\":0}}
+]",
+        foo_hash, bar_hash,
+    );
+    assert_eq!(expected, output);
+
+    let output = cmd!(b3sum_exe(), "--json-lines", "-l", "3", "--seek", "1", "a")
+        .dir(dir.path())
+        .read()
+        .unwrap();
+    let mut expected_bytes = [0; 3];
+    let mut reader = blake3::Hasher::new().update(b"foo").finalize_xof();
+    reader.set_position(1);
+    reader.fill(&mut expected_bytes);
+    let expected = format!(
+        "{{\"path\":\"a\",\"hash\":\"{}\",\"length\":3,\"seek\":1}}",
+        hex::encode(expected_bytes),
+    );
+    assert_eq!(expected, output);
+
+    // Errors go in the output instead of stderr, but the exit status is still nonzero.
+    let output = cmd!(b3sum_exe(), "--json-lines", "a", "missing")
+        .dir(dir.path())
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(output.stderr.is_empty());
+    let stdout = std::str::from_utf8(&output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines[1].starts_with("{\"path\":\"missing\",\"error\":\""));
+
+    // No files at all is still a valid array.
+    let empty_dir = tempfile::tempdir().unwrap();
+    let output = cmd!(b3sum_exe(), "--json", "--recursive", empty_dir.path())
+        .read()
+        .unwrap();
+    assert_eq!("[\n]", output);
+}
+
+#[test]
+#[cfg(unix)]
+fn test_json_invalid_unicode_on_unix() {
+    use std::os::unix::ffi::OsStringExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let name = OsString::from_vec(b"abc\xffdef".to_vec());
+    let write_result = fs::write(dir.path().join(&name), b"");
+    if cfg!(target_os = "linux") {
+        write_result.expect("Linux should allow invalid Unicode");
+    } else if write_result.is_err() {
+        return;
+    }
+    let output = cmd!(b3sum_exe(), "--json-lines", &name)
+        .dir(dir.path())
+        .read()
+        .unwrap();
+    let expected = format!(
+        "{{\"path_hex\":\"616263ff646566\",\"hash\":\"{}\",\"length\":32,\"seek\":0}}",
+        blake3::hash(b""),
+    );
+    assert_eq!(expected, output);
+
+    // The hex path round-trips through --check.
+    fs::write(dir.path().join("checkfile"), &output).unwrap();
+    let output = cmd!(b3sum_exe(), "--check", "--json-lines", "checkfile")
+        .dir(dir.path())
+        .read()
+        .unwrap();
+    assert_eq!("abc\u{fffd}def: OK", output);
+}
+
+#[test]
+fn test_json_check() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a"), b"foo").unwrap();
+    fs::write(dir.path().join("b"), b"bar").unwrap();
+
+    for format in ["--json", "--json-lines"] {
+        let checkfile = cmd!(b3sum_exe(), format, "-l", "100", "--seek", "7", "a", "b")
+            .dir(dir.path())
+            .read()
+            .unwrap();
+        fs::write(dir.path().join("checkfile"), &checkfile).unwrap();
+        let output = cmd!(b3sum_exe(), "--check", format, "checkfile")
+            .dir(dir.path())
+            .read()
+            .unwrap();
+        assert_eq!("a: OK\nb: OK", output);
+
+        // Either format is accepted when checking.
+        let other_format = if format == "--json" {
+            "--json-lines"
+        } else {
+            "--json"
+        };
+        let output = cmd!(b3sum_exe(), "--check", other_format, "checkfile")
+            .dir(dir.path())
+            .read()
+            .unwrap();
+        assert_eq!("a: OK\nb: OK", output);
+    }
+
+    // Mismatches, missing files, error records, and invalid lines are each reported.
+    let checkfile = format!(
+        "\
+{{\"path\":\"a\",\"hash\":\"{}\"}}
+{{\"path\":\"b\",\"hash\":\"{}\",\"some_future_field\":[]}}
+{{\"path\":\"missing\",\"hash\":\"{}\"}}
+{{\"path\":\"c\",\"error\":\"No such file\"}}
+not json
+{{\"path\":\"a\",\"hash\":\"{}\",\"length\":33}}
+",
+        blake3::hash(b"foo"),
+        blake3::hash(b"baz"),
+        blake3::hash(b"foo"),
+        blake3::hash(b"foo"),
+    );
+    fs::write(dir.path().join("checkfile"), checkfile).unwrap();
+    let output = cmd!(
+        b3sum_exe(),
+        "--check",
+        "--warn",
+        "--json-lines",
+        "checkfile"
+    )
+    .dir(dir.path())
+    .stdout_capture()
+    .stderr_capture()
+    .unchecked()
+    .run()
+    .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = std::str::from_utf8(&output.stdout).unwrap();
+    let stderr = std::str::from_utf8(&output.stderr).unwrap();
+    let stdout_lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(stdout_lines.len(), 4);
+    assert_eq!(stdout_lines[0], "a: OK");
+    assert_eq!(stdout_lines[1], "b: FAILED");
+    assert!(stdout_lines[2].starts_with("missing: FAILED ("));
+    assert_eq!(stdout_lines[3], "c: FAILED (No such file)");
+    let stderr_lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(stderr_lines.len(), 6);
+    assert!(stderr_lines[0].starts_with("b3sum: checkfile: 5: "));
+    assert_eq!(
+        stderr_lines[1],
+        "b3sum: checkfile: 6: Hash doesn't match length"
+    );
+    assert_eq!(
+        stderr_lines[2],
+        "b3sum: WARNING: 2 lines are improperly formatted"
+    );
+    assert_eq!(stderr_lines[3], "b3sum: WARNING: 1 listed file is missing");
+    assert_eq!(
+        stderr_lines[4],
+        "b3sum: WARNING: 1 listed file could not be read"
+    );
+    assert_eq!(
+        stderr_lines[5],
+        "b3sum: WARNING: 1 computed checksum did NOT match"
+    );
+
+    // An invalid array is improperly formatted, not unreadable.
+    fs::write(dir.path().join("checkfile"), "[{\"path\":").unwrap();
+    let output = cmd!(b3sum_exe(), "--check", "--json", "checkfile")
+        .dir(dir.path())
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+    let expected_stderr = "\
+        b3sum: checkfile: no properly formatted checksum lines found\n\
+        b3sum: WARNING: 1 line is improperly formatted\n";
+    assert_eq!(expected_stderr.as_bytes(), &output.stderr[..]);
+}
+
+#[test]
+fn test_check_summary_and_exit_codes() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("a"), b"a").unwrap();
+    fs::write(dir.path().join("b"), b"b").unwrap();
+    let a_hash = blake3::hash(b"a");
+    let b_hash = blake3::hash(b"b");
+    let run = |checkfile: String, args: &[&str]| {
+        let output = cmd(b3sum_exe(), ["--check"].iter().chain(args))
+            .stdin_bytes(checkfile)
+            .dir(dir.path())
+            .stdout_capture()
+            .stderr_capture()
+            .unchecked()
+            .run()
+            .unwrap();
+        (
+            output.status.code().unwrap(),
+            String::from_utf8(output.stdout).unwrap(),
+            String::from_utf8(output.stderr).unwrap(),
+        )
+    };
+
+    // Improperly formatted lines are counted, but they only fail with --strict, or if there are
+    // no properly formatted lines at all.
+    let checkfile = format!("{a_hash}  a\nnot a checksum line\n");
+    let (code, stdout, stderr) = run(checkfile.clone(), &[]);
+    assert_eq!(code, 0);
+    assert_eq!(stdout, "a: OK\n");
+    assert_eq!(stderr, "b3sum: WARNING: 1 line is improperly formatted\n");
+    let (code, _, stderr) = run(checkfile.clone(), &["--strict", "--warn"]);
+    assert_eq!(code, 4);
+    assert!(stderr.starts_with("b3sum: -: 2: "));
+    assert!(stderr.ends_with("b3sum: WARNING: 1 line is improperly formatted\n"));
+    let (code, _, stderr) = run("not a checksum line\n".into(), &[]);
+    assert_eq!(code, 4);
+    assert_eq!(
+        stderr,
+        "b3sum: -: no properly formatted checksum lines found\n\
+         b3sum: WARNING: 1 line is improperly formatted\n",
+    );
+
+    // Missing files are an incomplete check, and mismatches take precedence over that.
+    let checkfile = format!("{a_hash}  a\n{a_hash}  missing\n");
+    let (code, _, stderr) = run(checkfile.clone(), &[]);
+    assert_eq!(code, 3);
+    assert_eq!(stderr, "b3sum: WARNING: 1 listed file is missing\n");
+    let (code, stdout, stderr) = run(checkfile.clone(), &["--ignore-missing", "--quiet"]);
+    assert_eq!(code, 0);
+    assert_eq!(stdout, "");
+    assert_eq!(stderr, "");
+    let checkfile = format!("{a_hash}  a\n{a_hash}  missing\n{a_hash}  b\n");
+    let (code, _, stderr) = run(checkfile, &[]);
+    assert_eq!(code, 1);
+    assert_eq!(
+        stderr,
+        "b3sum: WARNING: 1 listed file is missing\n\
+         b3sum: WARNING: 1 computed checksum did NOT match\n",
+    );
+
+    // With --ignore-missing, a checkfile where nothing was verified is still incomplete.
+    let checkfile = format!("{b_hash}  missing1\n{b_hash}  missing2\n");
+    let (code, stdout, stderr) = run(checkfile, &["--ignore-missing"]);
+    assert_eq!(code, 3);
+    assert_eq!(stdout, "");
+    assert_eq!(
+        stderr,
+        "b3sum: -: no file was verified\n\
+         b3sum: 2 missing files ignored\n",
+    );
+
+    // A checkfile that can't be opened is an incomplete check too, and we keep going.
+    let output = cmd!(b3sum_exe(), "--check", "no_such_checkfile", "-")
+        .stdin_bytes(format!("{a_hash}  a\n"))
+        .dir(dir.path())
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(std::str::from_utf8(&output.stdout).unwrap(), "a: OK\n");
+}
+
+#[test]
+fn test_cache() {
+    let dir = tempfile::tempdir().unwrap();
+    let set_old_mtime = |name: &str| {
+        let old = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+        let file = fs::File::options()
+            .write(true)
+            .open(dir.path().join(name))
+            .unwrap();
+        file.set_modified(old).unwrap();
+    };
+    fs::write(dir.path().join("a"), b"foo").unwrap();
+    set_old_mtime("a");
+    let foo_hash = blake3::hash(b"foo");
+
+    for _ in 0..2 {
+        let output = cmd!(b3sum_exe(), "--cache", "cache", "a")
+            .dir(dir.path())
+            .read()
+            .unwrap();
+        assert_eq!(format!("{foo_hash}  a"), output);
+    }
+    assert!(
+        fs::read(dir.path().join("cache"))
+            .unwrap()
+            .starts_with(b"B3SC")
+    );
+
+    // Longer outputs and other modes don't get mixed up with cached hashes.
+    let output = cmd!(
+        b3sum_exe(),
+        "--cache",
+        "cache",
+        "--no-names",
+        "-l",
+        "64",
+        "a"
+    )
+    .dir(dir.path())
+    .read()
+    .unwrap();
+    let mut expected = [0; 64];
+    blake3::Hasher::new()
+        .update(b"foo")
+        .finalize_xof()
+        .fill(&mut expected);
+    assert_eq!(hex::encode(expected), output);
+    let output = cmd!(
+        b3sum_exe(),
+        "--cache",
+        "cache",
+        "--no-names",
+        "--seek",
+        "8",
+        "-l",
+        "4",
+        "a"
+    )
+    .dir(dir.path())
+    .read()
+    .unwrap();
+    assert_eq!(hex::encode(&foo_hash.as_bytes()[8..12]), output);
+    let output = cmd!(
+        b3sum_exe(),
+        "--cache",
+        "cache",
+        "--derive-key",
+        "ctx",
+        "--no-names",
+        "a"
+    )
+    .dir(dir.path())
+    .read()
+    .unwrap();
+    let expected = blake3::Hasher::new_derive_key("ctx")
+        .update(b"foo")
+        .finalize();
+    assert_eq!(expected.to_hex().as_str(), output);
+
+    // Changed files are hashed again, with or without --rehash.
+    fs::write(dir.path().join("a"), b"bar").unwrap();
+    set_old_mtime("a");
+    for extra_args in [&[][..], &["--rehash"], &["--paranoid"]] {
+        let output = cmd(
+            b3sum_exe(),
+            ["--cache", "cache", "a"].iter().chain(extra_args),
+        )
+        .dir(dir.path())
+        .read()
+        .unwrap();
+        assert_eq!(format!("{}  a", blake3::hash(b"bar")), output);
+    }
+
+    // --check uses the cache too.
+    let checkfile = format!("{}  a\n", blake3::hash(b"bar"));
+    let output = cmd!(b3sum_exe(), "--check", "--cache", "cache")
+        .stdin_bytes(checkfile)
+        .dir(dir.path())
+        .read()
+        .unwrap();
+    assert_eq!("a: OK", output);
+
+    // An invalid cache is reported and replaced.
+    fs::write(dir.path().join("cache"), b"garbage").unwrap();
+    let output = cmd!(b3sum_exe(), "--cache", "cache", "a")
+        .dir(dir.path())
+        .stdout_capture()
+        .stderr_capture()
+        .run()
+        .unwrap();
+    assert_eq!(
+        "b3sum: cache: ignoring invalid cache: not a b3sum cache file\n",
+        std::str::from_utf8(&output.stderr).unwrap(),
+    );
+    assert!(
+        fs::read(dir.path().join("cache"))
+            .unwrap()
+            .starts_with(b"B3SC")
+    );
+
+    // --rehash requires --cache.
+    let output = cmd!(b3sum_exe(), "--rehash", "a")
+        .dir(dir.path())
+        .stdout_capture()
+        .stderr_capture()
+        .unchecked()
+        .run()
+        .unwrap();
+    assert!(!output.status.success());
+}
+
 #[test]
 fn test_globbing() {
     // On Unix, globbing is provided by the shell. On Windows, globbing is
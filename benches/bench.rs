@@ -621,3 +621,45 @@ fn bench_xof_32_blocks(b: &mut Bencher) {
 fn bench_xof_64_blocks(b: &mut Bencher) {
     bench_xof(b, 64 * BLOCK_LEN);
 }
+
+const BATCH_SIZE: usize = 64;
+
+fn bench_batch(b: &mut Bencher, len: usize) {
+    let mut input = RandomInput::new(b, BATCH_SIZE * len);
+    let mut out = [blake3::Hash::from_bytes([0; OUT_LEN]); BATCH_SIZE];
+    b.iter(|| {
+        let inputs: ArrayVec<&[u8], BATCH_SIZE> = input.get().chunks_exact(len).collect();
+        blake3::hash_batch(&inputs, &mut out);
+    });
+}
+
+// The same inputs as bench_batch, hashed one at a time, for comparison.
+fn bench_batch_loop(b: &mut Bencher, len: usize) {
+    let mut input = RandomInput::new(b, BATCH_SIZE * len);
+    let mut out = [blake3::Hash::from_bytes([0; OUT_LEN]); BATCH_SIZE];
+    b.iter(|| {
+        for (input, hash) in input.get().chunks_exact(len).zip(&mut out) {
+            *hash = blake3::hash(input);
+        }
+    });
+}
+
+#[bench]
+fn bench_batch_0032_bytes(b: &mut Bencher) {
+    bench_batch(b, 32);
+}
+
+#[bench]
+fn bench_batch_0064_bytes(b: &mut Bencher) {
+    bench_batch(b, 64);
+}
+
+#[bench]
+fn bench_batch_loop_0032_bytes(b: &mut Bencher) {
+    bench_batch_loop(b, 32);
+}
+
+#[bench]
+fn bench_batch_loop_0064_bytes(b: &mut Bencher) {
+    bench_batch_loop(b, 64);
+}
@@ -0,0 +1,357 @@
+//! `const fn` versions of [`hash`](crate::hash), [`keyed_hash`](crate::keyed_hash),
+//! [`derive_key`](crate::derive_key), and [`Hash::from_hex`].
+//!
+//! These functions can be evaluated at compile time, for example to embed the expected hash of a
+//! bundled asset or a protocol constant, so that a mistake is a compile error rather than a
+//! runtime failure. They always use the portable implementation of the compression function,
+//! with no SIMD and no multithreading, so at runtime they're much slower than the regular
+//! functions. Compile-time evaluation is slower still, and hashing more than a few kilobytes in a
+//! `const` can noticeably slow down your build.
+//!
+//! The outputs are the same as the regular functions.
+//!
+//! # Example
+//!
+//! ```
+//! const FOO_HASH: blake3::Hash = blake3::const_fn::hash(b"foo");
+//! assert_eq!(FOO_HASH, blake3::hash(b"foo"));
+//!
+//! // The hash_from_hex! macro parses a hex hash at compile time. An invalid hex string is a
+//! // compile error.
+//! const EXPECTED: blake3::Hash =
+//!     blake3::hash_from_hex!("04e0bb39f30b1a3feb89f536c93be15055482df748674b00d26e5a75777702e9");
+//! assert_eq!(FOO_HASH, EXPECTED);
+//! ```
+
+use crate::portable::compress_words;
+use crate::{
+    BLOCK_LEN, CHUNK_END, CHUNK_LEN, CHUNK_START, CVWords, DERIVE_KEY_CONTEXT, DERIVE_KEY_MATERIAL,
+    Hash, HexError, HexErrorInner, IV, KEY_LEN, KEYED_HASH, MAX_DEPTH, OUT_LEN, PARENT, ROOT,
+};
+
+/// The `const fn` version of [`hash`](crate::hash).
+pub const fn hash(input: &[u8]) -> Hash {
+    Hash::from_bytes(hash_all_at_once(input, IV, 0))
+}
+
+/// The `const fn` version of [`keyed_hash`](crate::keyed_hash).
+pub const fn keyed_hash(key: &[u8; KEY_LEN], input: &[u8]) -> Hash {
+    Hash::from_bytes(hash_all_at_once(
+        input,
+        &words_from_le_bytes_32(key),
+        KEYED_HASH,
+    ))
+}
+
+/// The `const fn` version of [`derive_key`](crate::derive_key).
+pub const fn derive_key(context: &str, key_material: &[u8]) -> [u8; OUT_LEN] {
+    let context_key = hash_all_at_once(context.as_bytes(), IV, DERIVE_KEY_CONTEXT);
+    hash_all_at_once(
+        key_material,
+        &words_from_le_bytes_32(&context_key),
+        DERIVE_KEY_MATERIAL,
+    )
+}
+
+/// The `const fn` version of [`Hash::from_hex`]. To parse a hex string at compile time and get a
+/// compile error if it's invalid, see the [`hash_from_hex!`](crate::hash_from_hex!) macro.
+pub const fn from_hex(hex: &[u8]) -> Result<Hash, HexError> {
+    match bytes_from_hex::<OUT_LEN>(hex) {
+        Ok(bytes) => Ok(Hash::from_bytes(bytes)),
+        Err(e) => Err(e),
+    }
+}
+
+// Shared with Digest::from_hex.
+pub(crate) const fn bytes_from_hex<const N: usize>(hex: &[u8]) -> Result<[u8; N], HexError> {
+    const fn hex_val(byte: u8) -> Result<u8, HexError> {
+        match byte {
+            b'A'..=b'F' => Ok(byte - b'A' + 10),
+            b'a'..=b'f' => Ok(byte - b'a' + 10),
+            b'0'..=b'9' => Ok(byte - b'0'),
+            _ => Err(HexError(HexErrorInner::InvalidByte(byte))),
+        }
+    }
+    if hex.len() != N * 2 {
+        return Err(HexError(HexErrorInner::InvalidLen {
+            expected: N * 2,
+            received: hex.len(),
+        }));
+    }
+    let mut bytes = [0; N];
+    let mut i = 0;
+    while i < N {
+        let high = match hex_val(hex[2 * i]) {
+            Ok(val) => val,
+            Err(e) => return Err(e),
+        };
+        let low = match hex_val(hex[2 * i + 1]) {
+            Ok(val) => val,
+            Err(e) => return Err(e),
+        };
+        bytes[i] = 16 * high + low;
+        i += 1;
+    }
+    Ok(bytes)
+}
+
+/// Parse a hex string into a [`Hash`](struct@Hash) at compile time.
+///
+/// The argument must be a string constant, usually a literal. If it isn't exactly 64 hex
+/// characters, that's a compile error. See the [`const_fn`](crate::const_fn) module.
+///
+/// # Example
+///
+/// ```
+/// const FOO_HASH: blake3::Hash =
+///     blake3::hash_from_hex!("04e0bb39f30b1a3feb89f536c93be15055482df748674b00d26e5a75777702e9");
+/// assert_eq!(FOO_HASH, blake3::hash(b"foo"));
+/// ```
+///
+/// ```compile_fail
+/// // One character too short.
+/// blake3::hash_from_hex!("04e0bb39f30b1a3feb89f536c93be15055482df748674b00d26e5a75777702e");
+/// ```
+#[macro_export]
+macro_rules! hash_from_hex {
+    ($hex:expr) => {
+        const { $crate::const_fn::__from_hex_or_panic($hex) }
+    };
+}
+
+// Used by the hash_from_hex! macro. Inside a const block, these panics become compile errors.
+#[doc(hidden)]
+pub const fn __from_hex_or_panic(hex: &str) -> Hash {
+    match from_hex(hex.as_bytes()) {
+        Ok(hash) => hash,
+        Err(HexError(HexErrorInner::InvalidLen { .. })) => {
+            panic!("a BLAKE3 hash must be 64 hex characters")
+        }
+        Err(HexError(HexErrorInner::InvalidByte(_))) => panic!("invalid hex character"),
+    }
+}
+
+// Like the crate's Output type, but with the block stored as words, and without a Platform.
+struct Output {
+    input_chaining_value: CVWords,
+    block_words: [u32; 16],
+    block_len: u8,
+    counter: u64,
+    flags: u8,
+}
+
+impl Output {
+    const fn chaining_value(&self) -> CVWords {
+        let state = compress_words(
+            &self.input_chaining_value,
+            &self.block_words,
+            self.block_len,
+            self.counter,
+            self.flags,
+        );
+        first_8_words_xor(&state)
+    }
+
+    const fn root_hash(&self) -> [u8; OUT_LEN] {
+        let state = compress_words(
+            &self.input_chaining_value,
+            &self.block_words,
+            self.block_len,
+            0,
+            self.flags | ROOT,
+        );
+        le_bytes_from_words_32(&first_8_words_xor(&state))
+    }
+}
+
+const fn first_8_words_xor(state: &[u32; 16]) -> CVWords {
+    let mut cv = [0; 8];
+    let mut i = 0;
+    while i < 8 {
+        cv[i] = state[i] ^ state[i + 8];
+        i += 1;
+    }
+    cv
+}
+
+// The block of `input` starting at `start`, zero-padded, as words.
+const fn block_words(input: &[u8], start: usize, len: usize) -> [u32; 16] {
+    let mut block = [0; BLOCK_LEN];
+    let mut i = 0;
+    while i < len {
+        block[i] = input[start + i];
+        i += 1;
+    }
+    let mut words = [0; 16];
+    let mut i = 0;
+    while i < 16 {
+        words[i] = u32::from_le_bytes([
+            block[4 * i],
+            block[4 * i + 1],
+            block[4 * i + 2],
+            block[4 * i + 3],
+        ]);
+        i += 1;
+    }
+    words
+}
+
+// The chunk of `input` starting at `start` and `len` bytes long. The last block isn't compressed
+// yet, because it might be the root.
+const fn chunk_output(
+    input: &[u8],
+    start: usize,
+    len: usize,
+    key: &CVWords,
+    chunk_counter: u64,
+    flags: u8,
+) -> Output {
+    let mut cv = *key;
+    let mut block_start = 0;
+    let mut start_flag = CHUNK_START;
+    while len - block_start > BLOCK_LEN {
+        let state = compress_words(
+            &cv,
+            &block_words(input, start + block_start, BLOCK_LEN),
+            BLOCK_LEN as u8,
+            chunk_counter,
+            flags | start_flag,
+        );
+        cv = first_8_words_xor(&state);
+        block_start += BLOCK_LEN;
+        start_flag = 0;
+    }
+    let block_len = len - block_start;
+    Output {
+        input_chaining_value: cv,
+        block_words: block_words(input, start + block_start, block_len),
+        block_len: block_len as u8,
+        counter: chunk_counter,
+        flags: flags | start_flag | CHUNK_END,
+    }
+}
+
+const fn parent_output(
+    left_child: &CVWords,
+    right_child: &CVWords,
+    key: &CVWords,
+    flags: u8,
+) -> Output {
+    let mut block_words = [0; 16];
+    let mut i = 0;
+    while i < 8 {
+        block_words[i] = left_child[i];
+        block_words[i + 8] = right_child[i];
+        i += 1;
+    }
+    Output {
+        input_chaining_value: *key,
+        block_words,
+        block_len: BLOCK_LEN as u8,
+        counter: 0,
+        flags: flags | PARENT,
+    }
+}
+
+// This follows the reference implementation: each completed chunk's chaining value is merged
+// into the stack as soon as it's known, except for the last chunk, which might be the root.
+const fn hash_all_at_once(input: &[u8], key: &CVWords, flags: u8) -> [u8; OUT_LEN] {
+    let mut cv_stack = [[0; 8]; MAX_DEPTH];
+    let mut cv_stack_len = 0;
+    let mut chunk_start = 0;
+    let mut chunk_counter = 0;
+    while input.len() - chunk_start > CHUNK_LEN {
+        let output = chunk_output(input, chunk_start, CHUNK_LEN, key, chunk_counter, flags);
+        let mut cv = output.chaining_value();
+        chunk_counter += 1;
+        // Merge completed subtrees, one for each trailing 0-bit in the new number of chunks.
+        let mut total_chunks = chunk_counter;
+        while total_chunks & 1 == 0 {
+            cv_stack_len -= 1;
+            cv = parent_output(&cv_stack[cv_stack_len], &cv, key, flags).chaining_value();
+            total_chunks >>= 1;
+        }
+        cv_stack[cv_stack_len] = cv;
+        cv_stack_len += 1;
+        chunk_start += CHUNK_LEN;
+    }
+    let mut output = chunk_output(
+        input,
+        chunk_start,
+        input.len() - chunk_start,
+        key,
+        chunk_counter,
+        flags,
+    );
+    while cv_stack_len > 0 {
+        cv_stack_len -= 1;
+        output = parent_output(
+            &cv_stack[cv_stack_len],
+            &output.chaining_value(),
+            key,
+            flags,
+        );
+    }
+    output.root_hash()
+}
+
+const fn words_from_le_bytes_32(bytes: &[u8; 32]) -> CVWords {
+    let mut words = [0; 8];
+    let mut i = 0;
+    while i < 8 {
+        words[i] = u32::from_le_bytes([
+            bytes[4 * i],
+            bytes[4 * i + 1],
+            bytes[4 * i + 2],
+            bytes[4 * i + 3],
+        ]);
+        i += 1;
+    }
+    words
+}
+
+const fn le_bytes_from_words_32(words: &CVWords) -> [u8; 32] {
+    let mut bytes = [0; 32];
+    let mut i = 0;
+    while i < 8 {
+        let word = words[i].to_le_bytes();
+        bytes[4 * i] = word[0];
+        bytes[4 * i + 1] = word[1];
+        bytes[4 * i + 2] = word[2];
+        bytes[4 * i + 3] = word[3];
+        i += 1;
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test::{TEST_CASES, TEST_CASES_MAX, paint_test_input};
+
+    #[test]
+    fn test_compare_runtime() {
+        const KEY: &[u8; 32] = b"whats the Elvish word for friend";
+        const CONTEXT: &str = "BLAKE3 2019-12-27 16:29:52 test vectors context";
+        let mut input_buf = [0; TEST_CASES_MAX];
+        paint_test_input(&mut input_buf);
+        for &len in TEST_CASES {
+            let input = &input_buf[..len];
+            assert_eq!(super::hash(input), crate::hash(input), "len {len}");
+            assert_eq!(super::keyed_hash(KEY, input), crate::keyed_hash(KEY, input));
+            assert_eq!(
+                super::derive_key(CONTEXT, input),
+                crate::derive_key(CONTEXT, input),
+            );
+        }
+    }
+
+    #[test]
+    fn test_const_context() {
+        const HASH: crate::Hash = super::hash(&[42; 3 * crate::CHUNK_LEN + 1]);
+        assert_eq!(HASH, crate::hash(&[42; 3 * crate::CHUNK_LEN + 1]));
+        const PARSED: crate::Hash =
+            hash_from_hex!("04e0bb39f30b1a3feb89f536c93be15055482df748674b00d26e5a75777702e9");
+        assert_eq!(PARSED, crate::hash(b"foo"));
+        assert_eq!(super::from_hex(HASH.to_hex().as_bytes()).unwrap(), HASH,);
+    }
+}
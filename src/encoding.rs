@@ -0,0 +1,364 @@
+//! Text encodings for [`Hash`] other than hex: base32, base64url, and multibase.
+//!
+//! All of these are allocation-free. The base32 and base64url encodings follow RFC 4648 without
+//! padding, and the encoders produce lowercase base32 and URL-safe base64. The decoders reject
+//! non-canonical inputs (nonzero trailing bits), so that each hash has exactly one encoding in a
+//! given base, apart from the case of base32 letters.
+
+use crate::multihash;
+use crate::{Hash, OUT_LEN};
+use arrayvec::ArrayString;
+use core::fmt;
+
+const BASE32_ALPHABET: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+const BASE64URL_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+const HEX_ALPHABET: &[u8; 16] = b"0123456789abcdef";
+
+const BASE32_LEN: usize = encoded_len(OUT_LEN, 5);
+const BASE64URL_LEN: usize = encoded_len(OUT_LEN, 6);
+// A one-character multibase prefix, followed by a base32 multihash.
+const MULTIBASE_LEN: usize = 1 + encoded_len(multihash::HASH_LEN, 5);
+
+impl Hash {
+    /// Encode a `Hash` in lowercase base32 (RFC 4648, without padding). The returned
+    /// [`ArrayString`] is a fixed size and doesn't allocate memory on the heap.
+    ///
+    /// [`ArrayString`]: https://docs.rs/arrayvec/0.5.1/arrayvec/struct.ArrayString.html
+    pub fn to_base32(&self) -> ArrayString<BASE32_LEN> {
+        encode_to_array_string(&self.0, 5, BASE32_ALPHABET)
+    }
+
+    /// Decode a `Hash` from base32 (RFC 4648, without padding). Both uppercase and lowercase
+    /// letters are supported.
+    pub fn from_base32(base32: impl AsRef<[u8]>) -> Result<Self, DecodeError> {
+        let mut bytes = [0; OUT_LEN];
+        decode_exact(base32.as_ref(), 5, base32_value, &mut bytes)?;
+        Ok(Self(bytes))
+    }
+
+    /// Encode a `Hash` in URL-safe base64 (RFC 4648 section 5, without padding). The returned
+    /// [`ArrayString`] is a fixed size and doesn't allocate memory on the heap.
+    ///
+    /// [`ArrayString`]: https://docs.rs/arrayvec/0.5.1/arrayvec/struct.ArrayString.html
+    pub fn to_base64url(&self) -> ArrayString<BASE64URL_LEN> {
+        encode_to_array_string(&self.0, 6, BASE64URL_ALPHABET)
+    }
+
+    /// Decode a `Hash` from URL-safe base64 (RFC 4648 section 5, without padding).
+    pub fn from_base64url(base64url: impl AsRef<[u8]>) -> Result<Self, DecodeError> {
+        let mut bytes = [0; OUT_LEN];
+        decode_exact(base64url.as_ref(), 6, base64url_value, &mut bytes)?;
+        Ok(Self(bytes))
+    }
+
+    /// Encode a `Hash` as a [multihash](https://github.com/multiformats/multihash), using the
+    /// BLAKE3 code 0x1e. For other digest lengths, see the [`multihash`] module.
+    pub fn to_multihash(&self) -> [u8; multihash::HASH_LEN] {
+        let mut out = [0; multihash::HASH_LEN];
+        multihash::encode(&self.0, &mut out);
+        out
+    }
+
+    /// Decode a `Hash` from a BLAKE3 multihash. The digest must be 32 bytes long.
+    pub fn from_multihash(multihash: &[u8]) -> Result<Self, DecodeError> {
+        let digest = multihash::decode(multihash)?;
+        match digest.try_into() {
+            Ok(bytes) => Ok(Self(bytes)),
+            Err(_) => Err(DecodeError(DecodeErrorInner::WrongDigestLen(digest.len()))),
+        }
+    }
+
+    /// Encode a `Hash` as a [multibase](https://github.com/multiformats/multibase) multihash,
+    /// that is, the character `b` followed by the multihash in lowercase base32.
+    pub fn to_multibase(&self) -> ArrayString<MULTIBASE_LEN> {
+        let mut s = ArrayString::new();
+        s.push('b');
+        s.push_str(&encode_to_array_string::<{ MULTIBASE_LEN - 1 }>(
+            &self.to_multihash(),
+            5,
+            BASE32_ALPHABET,
+        ));
+        s
+    }
+
+    /// Decode a `Hash` from a multibase multihash. The supported bases are base32 (prefix `b` or
+    /// `B`), base64url (prefix `u`), and hex (prefix `f` or `F`).
+    pub fn from_multibase(multibase: impl AsRef<[u8]>) -> Result<Self, DecodeError> {
+        let Some((&prefix, encoded)) = multibase.as_ref().split_first() else {
+            return Err(DecodeError(DecodeErrorInner::InvalidLen(0)));
+        };
+        let (bits_per_char, value): (u32, fn(u8) -> Option<u8>) = match prefix {
+            b'b' | b'B' => (5, base32_value),
+            b'u' => (6, base64url_value),
+            b'f' | b'F' => (4, hex_value),
+            _ => return Err(DecodeError(DecodeErrorInner::UnsupportedBase(prefix))),
+        };
+        let mut multihash = [0; multihash::HASH_LEN];
+        decode_exact(encoded, bits_per_char, value, &mut multihash)?;
+        Self::from_multihash(&multihash)
+    }
+}
+
+/// The error type for [`Hash::from_base32`], [`Hash::from_base64url`],
+/// [`Hash::from_multihash`], [`Hash::from_multibase`], and [`multihash::decode`].
+#[derive(Clone, Debug)]
+pub struct DecodeError(DecodeErrorInner);
+
+#[derive(Clone, Debug)]
+enum DecodeErrorInner {
+    InvalidByte(u8),
+    InvalidLen(usize),
+    NonCanonical,
+    UnsupportedBase(u8),
+    WrongCode(u64),
+    WrongDigestLen(usize),
+    DigestLenMismatch { expected: u64, found: usize },
+    InvalidVarint,
+    Truncated,
+}
+
+impl DecodeError {
+    pub(crate) fn wrong_code(code: u64) -> Self {
+        Self(DecodeErrorInner::WrongCode(code))
+    }
+
+    pub(crate) fn digest_len_mismatch(expected: u64, found: usize) -> Self {
+        Self(DecodeErrorInner::DigestLenMismatch { expected, found })
+    }
+
+    pub(crate) fn invalid_varint() -> Self {
+        Self(DecodeErrorInner::InvalidVarint)
+    }
+
+    pub(crate) fn truncated() -> Self {
+        Self(DecodeErrorInner::Truncated)
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            DecodeErrorInner::InvalidByte(byte) => {
+                if byte < 128 {
+                    write!(f, "invalid character: {:?}", byte as char)
+                } else {
+                    write!(f, "invalid character: 0x{:x}", byte)
+                }
+            }
+            DecodeErrorInner::InvalidLen(len) => write!(f, "invalid encoded length: {}", len),
+            DecodeErrorInner::NonCanonical => write!(f, "non-canonical encoding"),
+            DecodeErrorInner::UnsupportedBase(prefix) => {
+                if prefix < 128 {
+                    write!(f, "unsupported multibase prefix: {:?}", prefix as char)
+                } else {
+                    write!(f, "unsupported multibase prefix: 0x{:x}", prefix)
+                }
+            }
+            DecodeErrorInner::WrongCode(code) => {
+                write!(f, "expected multihash code 0x1e, found 0x{:x}", code)
+            }
+            DecodeErrorInner::WrongDigestLen(len) => {
+                write!(f, "expected a 32-byte digest, found {} bytes", len)
+            }
+            DecodeErrorInner::DigestLenMismatch { expected, found } => write!(
+                f,
+                "multihash digest length is {} bytes, but {} bytes follow",
+                expected, found
+            ),
+            DecodeErrorInner::InvalidVarint => write!(f, "invalid multihash varint"),
+            DecodeErrorInner::Truncated => write!(f, "truncated multihash"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
+
+// The number of characters needed to encode `len` bytes, without padding.
+const fn encoded_len(len: usize, bits_per_char: u32) -> usize {
+    (len * 8).div_ceil(bits_per_char as usize)
+}
+
+fn encode_to_array_string<const N: usize>(
+    input: &[u8],
+    bits_per_char: u32,
+    alphabet: &[u8],
+) -> ArrayString<N> {
+    debug_assert_eq!(N, encoded_len(input.len(), bits_per_char));
+    let mut s = ArrayString::new();
+    let mask = (1 << bits_per_char) - 1;
+    let mut acc: u32 = 0;
+    let mut acc_bits = 0;
+    for &byte in input {
+        acc = (acc << 8) | byte as u32;
+        acc_bits += 8;
+        while acc_bits >= bits_per_char {
+            acc_bits -= bits_per_char;
+            s.push(alphabet[((acc >> acc_bits) & mask) as usize] as char);
+        }
+        acc &= (1 << acc_bits) - 1;
+    }
+    if acc_bits > 0 {
+        s.push(alphabet[((acc << (bits_per_char - acc_bits)) & mask) as usize] as char);
+    }
+    s
+}
+
+// Decode `input` into exactly `out.len()` bytes.
+fn decode_exact(
+    input: &[u8],
+    bits_per_char: u32,
+    value: fn(u8) -> Option<u8>,
+    out: &mut [u8],
+) -> Result<(), DecodeError> {
+    if input.len() != encoded_len(out.len(), bits_per_char) {
+        return Err(DecodeError(DecodeErrorInner::InvalidLen(input.len())));
+    }
+    let mut acc: u32 = 0;
+    let mut acc_bits = 0;
+    let mut out_len = 0;
+    for &c in input {
+        let Some(v) = value(c) else {
+            return Err(DecodeError(DecodeErrorInner::InvalidByte(c)));
+        };
+        acc = (acc << bits_per_char) | v as u32;
+        acc_bits += bits_per_char;
+        if acc_bits >= 8 {
+            acc_bits -= 8;
+            out[out_len] = (acc >> acc_bits) as u8;
+            out_len += 1;
+            acc &= (1 << acc_bits) - 1;
+        }
+    }
+    debug_assert_eq!(out_len, out.len());
+    // Whatever is left over is padding, and it must be zero.
+    if acc != 0 {
+        return Err(DecodeError(DecodeErrorInner::NonCanonical));
+    }
+    Ok(())
+}
+
+fn base32_value(c: u8) -> Option<u8> {
+    match c {
+        b'a'..=b'z' => Some(c - b'a'),
+        b'A'..=b'Z' => Some(c - b'A'),
+        b'2'..=b'7' => Some(c - b'2' + 26),
+        _ => None,
+    }
+}
+
+fn base64url_value(c: u8) -> Option<u8> {
+    BASE64URL_ALPHABET
+        .iter()
+        .position(|&a| a == c)
+        .map(|i| i as u8)
+}
+
+fn hex_value(c: u8) -> Option<u8> {
+    match c {
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => HEX_ALPHABET.iter().position(|&a| a == c).map(|i| i as u8),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FOO_HEX: &str = "04e0bb39f30b1a3feb89f536c93be15055482df748674b00d26e5a75777702e9";
+
+    #[test]
+    fn test_known_encodings() {
+        // Computed independently with Python's base64 module.
+        let hash = crate::hash(b"foo");
+        assert_eq!(hash.to_hex().as_str(), FOO_HEX);
+        let base32 = "atqlwoptbmnd724j6u3mso7bkbkuqlpxjbtuwagsnznhk53xaluq";
+        let base64url = "BOC7OfMLGj_rifU2yTvhUFVILfdIZ0sA0m5adXd3Auk";
+        let multibase = "bdyqajyf3hhzqwgr75oe7knwjhpqvavkifx3uqz2ladjg4wtvo53qf2i";
+        assert_eq!(hash.to_base32().as_str(), base32);
+        assert_eq!(hash.to_base64url().as_str(), base64url);
+        assert_eq!(hash.to_multibase().as_str(), multibase);
+        assert_eq!(hash.to_multihash()[..2], [0x1e, 0x20]);
+        assert_eq!(hash.to_multihash()[2..], hash.as_bytes()[..]);
+
+        assert_eq!(Hash::from_base32(base32).unwrap(), hash);
+        assert_eq!(
+            Hash::from_base32(base32.to_ascii_uppercase()).unwrap(),
+            hash
+        );
+        assert_eq!(Hash::from_base64url(base64url).unwrap(), hash);
+        assert_eq!(Hash::from_multihash(&hash.to_multihash()).unwrap(), hash);
+        assert_eq!(Hash::from_multibase(multibase).unwrap(), hash);
+        assert_eq!(
+            Hash::from_multibase(multibase.to_ascii_uppercase()).unwrap(),
+            hash
+        );
+        let mut hex_multibase = ArrayString::<69>::new();
+        hex_multibase.push_str("f1e20");
+        hex_multibase.push_str(FOO_HEX);
+        assert_eq!(Hash::from_multibase(hex_multibase.as_str()).unwrap(), hash);
+        let mut base64_multibase = ArrayString::<47>::new();
+        base64_multibase.push('u');
+        base64_multibase.push_str(&encode_to_array_string::<46>(
+            &hash.to_multihash(),
+            6,
+            BASE64URL_ALPHABET,
+        ));
+        assert_eq!(
+            Hash::from_multibase(base64_multibase.as_str()).unwrap(),
+            hash
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut input = [0; 256];
+        crate::test::paint_test_input(&mut input);
+        for i in 0..256 {
+            let hash = crate::hash(&input[..i]);
+            assert_eq!(Hash::from_base32(hash.to_base32().as_str()).unwrap(), hash);
+            assert_eq!(
+                Hash::from_base64url(hash.to_base64url().as_str()).unwrap(),
+                hash
+            );
+            assert_eq!(
+                Hash::from_multibase(hash.to_multibase().as_str()).unwrap(),
+                hash
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let hash = crate::hash(b"foo");
+        let base32 = hash.to_base32();
+        // Wrong lengths.
+        assert!(Hash::from_base32(&base32[1..]).is_err());
+        assert!(Hash::from_base64url(&base32[..]).is_err());
+        assert!(Hash::from_multibase("").is_err());
+        // Invalid characters.
+        let mut bad = [0; BASE32_LEN];
+        bad.copy_from_slice(base32.as_bytes());
+        bad[0] = b'1';
+        assert!(Hash::from_base32(bad).is_err());
+        // The last base32 character of a 32-byte value carries 1 bit of data and 4 bits of
+        // padding. Changing the padding bits gives a non-canonical encoding.
+        assert_eq!(base32.as_bytes()[BASE32_LEN - 1], b'q');
+        bad.copy_from_slice(base32.as_bytes());
+        bad[BASE32_LEN - 1] = b'r';
+        assert!(Hash::from_base32(bad).is_err());
+        // Unsupported multibase prefixes, and multihashes that aren't 32-byte BLAKE3.
+        let mut multibase = [0; MULTIBASE_LEN];
+        multibase.copy_from_slice(hash.to_multibase().as_bytes());
+        multibase[0] = b'z';
+        assert!(Hash::from_multibase(multibase).is_err());
+        let mut multihash = hash.to_multihash();
+        multihash[0] = 0x12;
+        assert!(Hash::from_multihash(&multihash).is_err());
+        let mut multihash = hash.to_multihash();
+        multihash[1] = 0x1f;
+        assert!(Hash::from_multihash(&multihash).is_err());
+        assert!(Hash::from_multihash(&hash.to_multihash()[..33]).is_err());
+    }
+}
@@ -0,0 +1,344 @@
+//! An in-memory buffer that keeps its BLAKE3 tree up to date as it changes
+//!
+//! BLAKE3 is a Merkle tree, so after a small write to a large input, only the chunks that changed
+//! and the parent nodes above them need to be hashed again. [`HashTree`] owns a buffer of bytes and
+//! caches the chaining value of every chunk and every complete subtree, the same values that
+//! [`HasherExt::finalize_non_root`](crate::hazmat::HasherExt::finalize_non_root) and
+//! [`hazmat::merge_subtrees_non_root`](crate::hazmat::merge_subtrees_non_root) would compute. After
+//! [`write_at`](HashTree::write_at), [`append`](HashTree::append), or
+//! [`truncate`](HashTree::truncate), it rehashes the chunks that changed and the parent nodes on
+//! their paths to the root, and no others. [`root`](HashTree::root) is always equal to
+//! [`hash`](crate::hash) of the current contents.
+//!
+//! The cache takes about 64 bytes per [`CHUNK_LEN`] bytes of contents, about 6%. This module
+//! requires the `std` Cargo feature, which is enabled by default.
+//!
+//! # Example
+//!
+//! ```
+//! use blake3::CHUNK_LEN;
+//! use blake3::hash_tree::HashTree;
+//!
+//! let mut tree = HashTree::from(vec![0; 1000 * CHUNK_LEN]);
+//! assert_eq!(tree.root(), blake3::hash(tree.as_bytes()));
+//!
+//! // This rehashes 2 chunks and the 11 parent nodes above them, not all 1000 chunks.
+//! tree.write_at(500 * CHUNK_LEN as u64 - 3, b"hello world");
+//! assert_eq!(tree.root(), blake3::hash(tree.as_bytes()));
+//!
+//! tree.append(b"more");
+//! tree.truncate(10);
+//! assert_eq!(tree.root(), blake3::hash(&[0; 10]));
+//! ```
+
+use crate::hazmat::{ChainingValue, Mode, merge_subtrees_non_root, merge_subtrees_root};
+use crate::platform::{MAX_SIMD_DEGREE, MAX_SIMD_DEGREE_OR_2, Platform};
+use crate::{CHUNK_LEN, Hash, IV, OUT_LEN};
+use std::fmt;
+
+/// A buffer of bytes with a cached BLAKE3 tree. See the [module level docs](index.html).
+#[derive(Clone)]
+pub struct HashTree {
+    contents: Vec<u8>,
+    // levels[k] holds the chaining values of the complete subtrees of 2^k chunks, in order. The
+    // final chunk counts as complete even if it's short, because the shape of the tree depends
+    // only on the number of chunks.
+    levels: Vec<Vec<ChainingValue>>,
+    platform: Platform,
+}
+
+impl HashTree {
+    /// Construct a new, empty `HashTree`.
+    pub fn new() -> Self {
+        Self::from(Vec::new())
+    }
+
+    /// The current contents.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.contents
+    }
+
+    /// Return the contents, dropping the cached tree.
+    pub fn into_vec(self) -> Vec<u8> {
+        self.contents
+    }
+
+    /// The length of the contents in bytes.
+    pub fn len(&self) -> u64 {
+        self.contents.len() as u64
+    }
+
+    /// Whether the contents are empty.
+    pub fn is_empty(&self) -> bool {
+        self.contents.is_empty()
+    }
+
+    /// Overwrite the contents starting at `offset` with `bytes`, growing the contents if the write
+    /// goes past the end.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `offset` is greater than [`len`](Self::len). Writes can extend the contents, but
+    /// they can't leave a gap.
+    pub fn write_at(&mut self, offset: u64, bytes: &[u8]) -> &mut Self {
+        assert!(
+            offset <= self.len(),
+            "write offset {offset} is past the end ({})",
+            self.len(),
+        );
+        if bytes.is_empty() {
+            return self;
+        }
+        let start = offset as usize;
+        let end = start + bytes.len();
+        if end > self.contents.len() {
+            self.contents.resize(end, 0);
+        }
+        self.contents[start..end].copy_from_slice(bytes);
+        self.rehash(start / CHUNK_LEN, end.div_ceil(CHUNK_LEN));
+        self
+    }
+
+    /// Add bytes to the end of the contents.
+    pub fn append(&mut self, bytes: &[u8]) -> &mut Self {
+        self.write_at(self.len(), bytes)
+    }
+
+    /// Shorten the contents to `len` bytes. If `len` is greater than or equal to the current
+    /// length, this has no effect.
+    pub fn truncate(&mut self, len: u64) -> &mut Self {
+        if len >= self.len() {
+            return self;
+        }
+        self.contents.truncate(len as usize);
+        let num_chunks = self.contents.len().div_ceil(CHUNK_LEN);
+        // Only a final chunk that's been cut short needs to be rehashed. Subtrees to the left of it
+        // haven't changed, and the rest are gone.
+        let first_dirty = if self.contents.len() % CHUNK_LEN == 0 {
+            num_chunks
+        } else {
+            num_chunks - 1
+        };
+        self.rehash(first_dirty, num_chunks);
+        self
+    }
+
+    /// The root hash of the current contents. This is always equal to [`hash`](crate::hash) of
+    /// [`as_bytes`](Self::as_bytes), and it takes `O(log n)` parent node compressions.
+    pub fn root(&self) -> Hash {
+        if self.contents.len() <= CHUNK_LEN {
+            return crate::hash(&self.contents);
+        }
+        let num_chunks = self.levels[0].len();
+        let left_chunks = left_subtree_chunks(num_chunks);
+        merge_subtrees_root(
+            &self.subtree_cv(0, left_chunks),
+            &self.subtree_cv(left_chunks, num_chunks - left_chunks),
+            Mode::Hash,
+        )
+    }
+
+    // The chaining value of the subtree of `num_chunks` chunks starting at chunk `start`. Complete
+    // subtrees are cached, and only the right edge of the tree needs merging.
+    fn subtree_cv(&self, start: usize, num_chunks: usize) -> ChainingValue {
+        if num_chunks.is_power_of_two() {
+            let level = num_chunks.trailing_zeros();
+            return self.levels[level as usize][start >> level];
+        }
+        let left_chunks = left_subtree_chunks(num_chunks);
+        merge_subtrees_non_root(
+            &self.subtree_cv(start, left_chunks),
+            &self.subtree_cv(start + left_chunks, num_chunks - left_chunks),
+            Mode::Hash,
+        )
+    }
+
+    // Resize the cached levels to match the contents, and recompute the chunks from `first` to
+    // `end` (exclusive) and every subtree above them.
+    fn rehash(&mut self, mut first: usize, mut end: usize) {
+        let mut count = self.contents.len().div_ceil(CHUNK_LEN);
+        let mut level = 0;
+        while count > 0 {
+            if self.levels.len() == level {
+                self.levels.push(Vec::new());
+            }
+            self.levels[level].resize(count, [0; OUT_LEN]);
+            end = end.min(count);
+            if first < end {
+                if level == 0 {
+                    self.hash_chunks(first, end);
+                } else {
+                    self.hash_parents(level, first, end);
+                }
+            }
+            first /= 2;
+            end = end.div_ceil(2);
+            count /= 2;
+            level += 1;
+        }
+        self.levels.truncate(level);
+    }
+
+    fn hash_chunks(&mut self, first: usize, end: usize) {
+        for batch_start in (first..end).step_by(MAX_SIMD_DEGREE) {
+            let batch_end = end.min(batch_start + MAX_SIMD_DEGREE);
+            let input = &self.contents[batch_start * CHUNK_LEN..];
+            let input = &input[..input.len().min((batch_end - batch_start) * CHUNK_LEN)];
+            let out = self.levels[0][batch_start..batch_end].as_flattened_mut();
+            let num_cvs = crate::compress_chunks_parallel(
+                input,
+                IV,
+                batch_start as u64,
+                0,
+                self.platform,
+                out,
+            );
+            debug_assert_eq!(num_cvs, batch_end - batch_start);
+        }
+    }
+
+    fn hash_parents(&mut self, level: usize, first: usize, end: usize) {
+        let (children, parents) = self.levels.split_at_mut(level);
+        let children = &children[level - 1];
+        let parents = &mut parents[0];
+        for batch_start in (first..end).step_by(MAX_SIMD_DEGREE_OR_2) {
+            let batch_end = end.min(batch_start + MAX_SIMD_DEGREE_OR_2);
+            let num_cvs = crate::compress_parents_parallel(
+                children[2 * batch_start..2 * batch_end].as_flattened(),
+                IV,
+                0,
+                self.platform,
+                parents[batch_start..batch_end].as_flattened_mut(),
+            );
+            debug_assert_eq!(num_cvs, batch_end - batch_start);
+        }
+    }
+}
+
+// The number of chunks in the left subtree of a tree of `num_chunks` chunks, the largest power of
+// two strictly less than `num_chunks`.
+fn left_subtree_chunks(num_chunks: usize) -> usize {
+    debug_assert!(num_chunks > 1);
+    (num_chunks / 2 + num_chunks % 2).next_power_of_two()
+}
+
+impl Default for HashTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<Vec<u8>> for HashTree {
+    fn from(contents: Vec<u8>) -> Self {
+        let mut tree = Self {
+            contents,
+            levels: Vec::new(),
+            platform: Platform::detect(),
+        };
+        let num_chunks = tree.contents.len().div_ceil(CHUNK_LEN);
+        tree.rehash(0, num_chunks);
+        tree
+    }
+}
+
+impl fmt::Debug for HashTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Don't print the contents or the cached tree, which could be a lot of output.
+        f.debug_struct("HashTree")
+            .field("len", &self.contents.len())
+            .field("root", &self.root())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::{TEST_CASES, TEST_CASES_MAX, paint_test_input};
+
+    #[test]
+    fn test_left_subtree_chunks() {
+        for num_chunks in 2..100 {
+            assert_eq!(
+                left_subtree_chunks(num_chunks) * CHUNK_LEN,
+                crate::hazmat::left_subtree_len((num_chunks * CHUNK_LEN) as u64) as usize,
+            );
+        }
+    }
+
+    #[test]
+    fn test_from_vec() {
+        let mut input = [0; TEST_CASES_MAX];
+        paint_test_input(&mut input);
+        for &case in TEST_CASES {
+            let tree = HashTree::from(input[..case].to_vec());
+            assert_eq!(tree.root(), crate::hash(&input[..case]), "len {case}");
+            assert_eq!(tree.as_bytes(), &input[..case]);
+        }
+    }
+
+    #[test]
+    fn test_write_at() {
+        let mut input = vec![0; 40 * CHUNK_LEN + 7];
+        paint_test_input(&mut input);
+        let mut expected = input.clone();
+        let mut tree = HashTree::from(input);
+        // Small writes inside one chunk, writes across chunk boundaries, and writes at the end.
+        let writes: &[(usize, usize)] = &[
+            (0, 1),
+            (5 * CHUNK_LEN - 1, 2),
+            (17 * CHUNK_LEN, CHUNK_LEN),
+            (3 * CHUNK_LEN + 100, 20 * CHUNK_LEN),
+            (40 * CHUNK_LEN, 7),
+            (40 * CHUNK_LEN + 5, 10),
+            (expected.len(), 3 * CHUNK_LEN),
+        ];
+        for (i, &(offset, len)) in writes.iter().enumerate() {
+            let bytes = vec![i as u8 + 1; len];
+            tree.write_at(offset as u64, &bytes);
+            if offset + len > expected.len() {
+                expected.resize(offset + len, 0);
+            }
+            expected[offset..][..len].copy_from_slice(&bytes);
+            assert_eq!(tree.as_bytes(), &expected[..]);
+            assert_eq!(tree.root(), crate::hash(&expected), "write {i}");
+        }
+        // An empty write changes nothing.
+        tree.write_at(tree.len(), &[]);
+        assert_eq!(tree.root(), crate::hash(&expected));
+    }
+
+    #[test]
+    fn test_append_and_truncate() {
+        let mut input = [0; TEST_CASES_MAX];
+        paint_test_input(&mut input);
+        let mut tree = HashTree::new();
+        assert_eq!(tree.root(), crate::hash(b""));
+        // Grow through all the test cases in steps, then shrink back down.
+        let mut len = 0;
+        for &case in TEST_CASES {
+            tree.append(&input[len..case]);
+            len = case;
+            assert_eq!(tree.root(), crate::hash(&input[..len]), "append to {len}");
+        }
+        for &case in TEST_CASES.iter().rev() {
+            tree.truncate(case as u64);
+            assert_eq!(tree.len(), case as u64);
+            assert_eq!(
+                tree.root(),
+                crate::hash(&input[..case]),
+                "truncate to {case}"
+            );
+        }
+        assert!(tree.is_empty());
+        // Truncating to a longer length does nothing.
+        tree.append(b"foo").truncate(100);
+        assert_eq!(tree.root(), crate::hash(b"foo"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_write_past_end_should_panic() {
+        HashTree::from(vec![0; 10]).write_at(11, b"x");
+    }
+}
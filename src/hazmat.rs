@@ -4,7 +4,9 @@
 //! which work directly with the interior hashes ("chaining values") of BLAKE3 chunks and subtrees.
 //! For example, you could use these functions to implement a BitTorrent-like protocol using the
 //! BLAKE3 tree structure, or to hash an input that's distributed across different machines. These
-//! use cases are advanced, and most applications don't need this module. Also:
+//! use cases are advanced, and most applications don't need this module. (For verified streaming
+//! in particular, the [`verified`](crate::verified) module is built on top of this one and is much
+//! harder to misuse.) Also:
 //!
 //! <div class="warning">
 //!
@@ -35,6 +37,10 @@
 //! subtrees using [`merge_subtrees_non_root`] and finally (once at the very top)
 //! [`merge_subtrees_root`] or [`merge_subtrees_root_xof`].
 //!
+//! Those functions panic when they're used to build an invalid tree. If offsets and lengths come
+//! from somewhere you don't trust, use the `try_*` methods of [`HasherExt`] and
+//! [`validate_subtrees`], which return a [`TreeShapeError`] instead.
+//!
 //! # Examples
 //!
 //! Here's an example of computing all the interior hashes in a 3-chunk tree:
@@ -148,6 +154,19 @@
 //! right subtrees you're giving them, and they can't help you catch mistakes. The best way to
 //! catch mistakes with these is to compare your root output to the [`blake3::hash`](crate::hash)
 //! of the same input.
+//!
+//! # Range proofs
+//!
+//! [`prove`] and [`verify`] build and check Merkle inclusion proofs for chunk ranges, using the
+//! tree shape described above. These let someone who knows only the root hash of some content
+//! check a few chunks of it, without the rest of the content. They require the `std` Cargo
+//! feature.
+//!
+//! [`AppendLog`] builds on the same tree shape to commit to every chunk-aligned prefix of a
+//! growing log, and its [`ConsistencyProof`]s show that one published root extends another.
+//!
+//! [`SubtreeAssembler`] goes the other way: it collects subtrees that arrive out of order, for
+//! example from different peers, and computes the root once it has all of them.
 
 use crate::platform::Platform;
 use crate::{CHUNK_LEN, CVWords, Hasher, IV, KEY_LEN, OUT_LEN};
@@ -157,7 +176,8 @@ pub trait HasherExt {
     /// Similar to [`Hasher::new_derive_key`] but using a pre-hashed [`ContextKey`] from
     /// [`hash_derive_key_context`].
     ///
-    /// The [`hash_derive_key_context`] function is the _only_ valid source of the [`ContextKey`].
+    /// The [`hash_derive_key_context`] function (or [`hash_derive_key_context_raw`]) is the _only_
+    /// valid source of the [`ContextKey`].
     /// Any other source ([`hash`](crate::hash), [`keyed_hash`](crate::keyed_hash), arbitrary bytes
     /// from the caller) violates the security requirements.
     ///
@@ -206,7 +226,8 @@ pub trait HasherExt {
     /// never correct to use a non-zero input offset with [`finalize`](Hasher::finalize) or
     /// [`finalize_xof`](Hasher::finalize_xof). The `offset` must also be a multiple of
     /// `CHUNK_LEN`. Violating either of these rules will currently fail an assertion and panic,
-    /// but this is not guaranteed.
+    /// but this is not guaranteed. [`try_set_input_offset`](HasherExt::try_set_input_offset)
+    /// returns an error instead.
     fn set_input_offset(&mut self, offset: u64) -> &mut Self;
 
     /// Finalize the non-root hash ("chaining value") of the current chunk or subtree.
@@ -218,7 +239,31 @@ pub trait HasherExt {
     ///
     /// See the [module level examples](index.html#examples), particularly the discussion of valid
     /// tree structures.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the `Hasher` hasn't accepted any input, because empty subtrees are
+    /// never valid. [`try_finalize_non_root`](HasherExt::try_finalize_non_root) returns an error
+    /// instead.
     fn finalize_non_root(&self) -> ChainingValue;
+
+    /// As [`set_input_offset`](HasherExt::set_input_offset), but returning an error instead of
+    /// panicking if the `Hasher` has already accepted input or if `offset` isn't a multiple of
+    /// [`CHUNK_LEN`].
+    ///
+    /// The `try_*` methods are for offsets and lengths that come from somewhere you don't trust,
+    /// like a network peer, where a panic would be a denial of service. See also
+    /// [`validate_subtrees`].
+    fn try_set_input_offset(&mut self, offset: u64) -> Result<&mut Self, TreeShapeError>;
+
+    /// As [`update`](Hasher::update), but returning an error instead of panicking if the subtree
+    /// would be longer than [`max_subtree_len`] allows at its input offset. If this returns an
+    /// error, none of `input` has been hashed.
+    fn try_update(&mut self, input: &[u8]) -> Result<&mut Self, TreeShapeError>;
+
+    /// As [`finalize_non_root`](HasherExt::finalize_non_root), but returning an error instead of
+    /// panicking if the subtree is empty.
+    fn try_finalize_non_root(&self) -> Result<ChainingValue, TreeShapeError>;
 }
 
 impl HasherExt for Hasher {
@@ -244,13 +289,126 @@ impl HasherExt for Hasher {
         assert_ne!(self.count(), 0, "empty subtrees are never valid");
         self.final_output().chaining_value()
     }
+
+    fn try_set_input_offset(&mut self, offset: u64) -> Result<&mut Hasher, TreeShapeError> {
+        if self.count() != 0 {
+            return Err(TreeShapeError::AlreadyUpdated);
+        }
+        if offset % CHUNK_LEN as u64 != 0 {
+            return Err(TreeShapeError::UnalignedOffset { offset });
+        }
+        Ok(self.set_input_offset(offset))
+    }
+
+    fn try_update(&mut self, input: &[u8]) -> Result<&mut Hasher, TreeShapeError> {
+        let offset = self.initial_chunk_counter * CHUNK_LEN as u64;
+        if let Some(max_len) = max_subtree_len(offset) {
+            // update enforces the same maximum, but don't underflow if the count is past it.
+            let remaining = max_len.checked_sub(self.count());
+            if remaining.is_none_or(|remaining| input.len() as u64 > remaining) {
+                return Err(TreeShapeError::SubtreeTooLong {
+                    offset,
+                    len: self.count().saturating_add(input.len() as u64),
+                    max_len,
+                });
+            }
+        }
+        Ok(self.update(input))
+    }
+
+    fn try_finalize_non_root(&self) -> Result<ChainingValue, TreeShapeError> {
+        if self.count() == 0 {
+            let offset = self.initial_chunk_counter * CHUNK_LEN as u64;
+            return Err(TreeShapeError::EmptySubtree { offset });
+        }
+        Ok(self.finalize_non_root())
+    }
+}
+
+/// The error type for the fallible methods of [`HasherExt`] and for [`validate_subtrees`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TreeShapeError {
+    /// [`try_set_input_offset`](HasherExt::try_set_input_offset) was called on a `Hasher` that
+    /// has already accepted input.
+    AlreadyUpdated,
+    /// An input offset isn't a multiple of [`CHUNK_LEN`].
+    UnalignedOffset {
+        /// The offset of the subtree.
+        offset: u64,
+    },
+    /// A subtree is empty. Empty subtrees are never valid.
+    EmptySubtree {
+        /// The offset of the subtree.
+        offset: u64,
+    },
+    /// A subtree is longer than [`max_subtree_len`] allows at its offset.
+    SubtreeTooLong {
+        /// The offset of the subtree.
+        offset: u64,
+        /// The length of the subtree.
+        len: u64,
+        /// The maximum length of a subtree at this offset.
+        max_len: u64,
+    },
+    /// A subtree other than the last one isn't a power-of-two number of chunks. Only subtrees
+    /// along the right edge of the tree can be shorter than their maximum length, and the last
+    /// subtree is the only one of those in a list.
+    IncompleteSubtree {
+        /// The offset of the subtree.
+        offset: u64,
+        /// The length of the subtree.
+        len: u64,
+    },
+    /// A subtree doesn't start where the previous one ended (or at zero, for the first one).
+    NotContiguous {
+        /// The offset of the subtree.
+        offset: u64,
+        /// Where the subtree should have started.
+        expected: u64,
+    },
 }
 
+impl core::fmt::Display for TreeShapeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            TreeShapeError::AlreadyUpdated => write!(f, "hasher has already accepted input"),
+            TreeShapeError::UnalignedOffset { offset } => write!(
+                f,
+                "offset ({offset}) must be a chunk boundary (divisible by {CHUNK_LEN})"
+            ),
+            TreeShapeError::EmptySubtree { offset } => {
+                write!(f, "empty subtree at offset {offset}")
+            }
+            TreeShapeError::SubtreeTooLong {
+                offset,
+                len,
+                max_len,
+            } => write!(
+                f,
+                "the subtree starting at {offset} contains at most {max_len} bytes (found {len})"
+            ),
+            TreeShapeError::IncompleteSubtree { offset, len } => write!(
+                f,
+                "the subtree starting at {offset} isn't the last, so it must be a power-of-two \
+                 number of chunks (found {len} bytes)"
+            ),
+            TreeShapeError::NotContiguous { offset, expected } => {
+                write!(f, "expected a subtree at offset {expected}, found {offset}")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for TreeShapeError {}
+
 /// The maximum length of a subtree in bytes, given its starting offset in bytes
 ///
 /// If you try to hash more than this many bytes as one subtree, you'll end up merging parent nodes
 /// that shouldn't be merged, and your output will be garbage. [`Hasher::update`] will currently
-/// panic in this case, but this is not guaranteed.
+/// panic in this case, but this is not guaranteed. [`HasherExt::try_update`] returns an error
+/// instead.
 ///
 /// For input offset zero (the default), there is no maximum length, and this function returns
 /// `None`. For all other offsets it returns `Some`. Note that valid offsets must be a multiple of
@@ -377,8 +535,9 @@ fn test_max_subtree_len() {
 #[inline(always)]
 pub fn left_subtree_len(input_len: u64) -> u64 {
     debug_assert!(input_len > CHUNK_LEN as u64);
-    // Note that .next_power_of_two() is greater than *or equal*.
-    ((input_len + 1) / 2).next_power_of_two()
+    // Note that .next_power_of_two() is greater than *or equal*. Rounding up the half this way,
+    // rather than with (input_len + 1) / 2, doesn't overflow for u64::MAX.
+    (input_len / 2 + input_len % 2).next_power_of_two()
 }
 
 #[test]
@@ -390,6 +549,64 @@ fn test_left_subtree_len() {
         assert_eq!(left_subtree_len(input_len), input_len / 2);
         assert_eq!(left_subtree_len(input_len + 1), input_len);
     }
+    assert_eq!(left_subtree_len(u64::MAX), 1 << 63);
+}
+
+/// Check that a list of `(offset, len)` subtrees splits an input into valid BLAKE3 subtrees.
+///
+/// The subtrees must be in order, starting at offset zero, with each one starting where the
+/// previous one ended. Each subtree must be non-empty and no longer than [`max_subtree_len`]
+/// allows at its offset, and every subtree except the last must be a power-of-two number of
+/// chunks. In other words, every subtree is a node of the tree for the whole input, and their
+/// chaining values can be merged with [`merge_subtrees_non_root`] and friends. An empty list is
+/// valid, and it represents the empty input. On success, this returns the total length.
+///
+/// This is useful for checking a split proposed by an untrusted peer, before hashing anything.
+///
+/// # Example
+///
+/// ```
+/// use blake3::CHUNK_LEN;
+/// use blake3::hazmat::{TreeShapeError, validate_subtrees};
+///
+/// let chunk = CHUNK_LEN as u64;
+/// assert_eq!(validate_subtrees(&[(0, 4 * chunk), (4 * chunk, 3 * chunk)]), Ok(7 * chunk));
+/// // Chunks 2 and 3 aren't a subtree, because chunk 1 is their left neighbor.
+/// assert_eq!(
+///     validate_subtrees(&[(0, chunk), (chunk, 3 * chunk)]),
+///     Err(TreeShapeError::SubtreeTooLong {
+///         offset: chunk,
+///         len: 3 * chunk,
+///         max_len: chunk,
+///     }),
+/// );
+/// ```
+pub fn validate_subtrees(subtrees: &[(u64, u64)]) -> Result<u64, TreeShapeError> {
+    let mut expected = 0;
+    for (i, &(offset, len)) in subtrees.iter().enumerate() {
+        if offset != expected {
+            return Err(TreeShapeError::NotContiguous { offset, expected });
+        }
+        if len == 0 {
+            return Err(TreeShapeError::EmptySubtree { offset });
+        }
+        // The whole input can't be longer than 2^64 - 1 bytes.
+        let max_len = max_subtree_len(offset).unwrap_or(u64::MAX);
+        if len > max_len || offset.checked_add(len).is_none() {
+            return Err(TreeShapeError::SubtreeTooLong {
+                offset,
+                len,
+                max_len: core::cmp::min(max_len, u64::MAX - offset),
+            });
+        }
+        let is_last = i == subtrees.len() - 1;
+        let is_complete = len % CHUNK_LEN as u64 == 0 && (len / CHUNK_LEN as u64).is_power_of_two();
+        if !is_last && !is_complete {
+            return Err(TreeShapeError::IncompleteSubtree { offset, len });
+        }
+        expected = offset + len;
+    }
+    Ok(expected)
 }
 
 /// The `mode` argument to [`merge_subtrees_root`] and friends
@@ -558,13 +775,1115 @@ pub type ContextKey = [u8; KEY_LEN];
 /// assert_eq!(derived_key, blake3::derive_key("foo", b"bar"));
 /// ```
 pub fn hash_derive_key_context(context: &str) -> ContextKey {
-    crate::hash_all_at_once::<crate::join::SerialJoin>(
-        context.as_bytes(),
-        IV,
-        crate::DERIVE_KEY_CONTEXT,
+    hash_derive_key_context_raw(context.as_bytes())
+}
+
+/// As [`hash_derive_key_context`], but with a context string of raw bytes. See
+/// [`derive_key_raw`](crate::derive_key_raw).
+pub fn hash_derive_key_context_raw(context: &[u8]) -> ContextKey {
+    crate::hash_all_at_once::<crate::join::SerialJoin>(context, IV, crate::DERIVE_KEY_CONTEXT)
+        .root_hash()
+        .0
+}
+
+/// A Merkle inclusion proof for a range of chunks, produced by [`prove`] and checked by
+/// [`verify`].
+///
+/// The proof holds the content length, which determines the shape of the tree (see
+/// [`left_subtree_len`]), and the chaining values of the subtrees next to the path from the range
+/// to the root. These are the subtrees that don't overlap the range, in left-to-right order, and
+/// there are at most two for each level of the tree. A verifier who knows the root [`Hash`] can
+/// recompute it from those chaining values and the content of the range, without any of the rest
+/// of the content.
+///
+/// Note that the content length is only authenticated by the root hash when the range includes
+/// the final chunk. A proof with a modified length can't make any range verify with the wrong
+/// bytes, but it might make the content look shorter or longer than it is.
+///
+/// [`Hash`]: struct@crate::Hash
+#[cfg(feature = "std")]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RangeProof {
+    content_len: u64,
+    siblings: Vec<ChainingValue>,
+}
+
+#[cfg(feature = "std")]
+impl RangeProof {
+    /// Construct a proof from its parts, for example after receiving them over the network.
+    pub fn new(content_len: u64, siblings: Vec<ChainingValue>) -> Self {
+        Self {
+            content_len,
+            siblings,
+        }
+    }
+
+    /// The length of the whole content.
+    pub fn content_len(&self) -> u64 {
+        self.content_len
+    }
+
+    /// The chaining values of the subtrees that don't overlap the range, in left-to-right order.
+    pub fn siblings(&self) -> &[ChainingValue] {
+        &self.siblings
+    }
+}
+
+/// The error type for [`verify`] and [`verify_consistency`].
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ProofError {
+    /// The offset isn't a multiple of [`CHUNK_LEN`], the data doesn't end at a chunk boundary or
+    /// at the end of the content, or the data is empty (which is only valid for empty content).
+    InvalidRange {
+        /// The offset of the data within the content.
+        offset: u64,
+        /// The length of the data.
+        len: u64,
+    },
+    /// The proof has too few or too many chaining values for the range and the content length.
+    WrongSiblingCount,
+    /// The data and the proof don't match the root hash.
+    Mismatch,
+    /// The lengths in a [`ConsistencyProof`] are invalid. The old length must be nonzero, and it
+    /// can't be greater than the new length. If it's less than the new length, it must be a
+    /// multiple of [`CHUNK_LEN`].
+    InvalidLengths {
+        /// The length of the old prefix.
+        old_len: u64,
+        /// The length of the new prefix.
+        new_len: u64,
+    },
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Display for ProofError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            ProofError::InvalidRange { offset, len } => {
+                write!(f, "invalid proof range: {len} bytes at offset {offset}")
+            }
+            ProofError::WrongSiblingCount => write!(f, "wrong number of chaining values in proof"),
+            ProofError::Mismatch => write!(f, "proof mismatch"),
+            ProofError::InvalidLengths { old_len, new_len } => {
+                write!(
+                    f,
+                    "invalid consistency proof lengths: {old_len} and {new_len}"
+                )
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ProofError {}
+
+// Ranges start at a chunk boundary and end at a chunk boundary or at the end of the content, so
+// every chunk is either entirely inside a range or entirely outside it. The empty range is only
+// allowed for the empty content, which still has one (empty) chunk.
+#[cfg(feature = "std")]
+fn is_valid_range(content_len: u64, start: u64, end: u64) -> bool {
+    start % CHUNK_LEN as u64 == 0
+        && (end % CHUNK_LEN as u64 == 0 || end == content_len)
+        && end <= content_len
+        && (start < end || content_len == 0)
+}
+
+#[cfg(feature = "std")]
+fn subtree_cv(subtree: &[u8], offset: u64) -> ChainingValue {
+    Hasher::new()
+        .set_input_offset(offset)
+        .update(subtree)
+        .finalize_non_root()
+}
+
+/// Build a [`RangeProof`] for `range` within `input`, in the default [`hash`](crate::hash)
+/// mode.
+///
+/// The range must start at a multiple of [`CHUNK_LEN`] and end at a multiple of `CHUNK_LEN` or at
+/// the end of the input, and it must not be empty, unless the input is empty too. The data for
+/// [`verify`] is `&input[range]`.
+///
+/// This hashes all of `input`, but the subtrees outside the range are hashed in large pieces, so
+/// it's about as fast as [`hash`](crate::hash).
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), blake3::hazmat::ProofError> {
+/// use blake3::CHUNK_LEN;
+/// use blake3::hazmat::{prove, verify};
+///
+/// let content = vec![0xab; 100 * CHUNK_LEN + 42];
+/// let root_hash = blake3::hash(&content);
+///
+/// // The storage side proves that it still has chunk 37.
+/// let range = 37 * CHUNK_LEN..38 * CHUNK_LEN;
+/// let proof = prove(&content, range.start as u64..range.end as u64);
+///
+/// // An auditor who only knows the root hash checks the chunk against the proof.
+/// verify(&root_hash, range.start as u64, &content[range.clone()], &proof)?;
+///
+/// // Corrupt data is rejected.
+/// let mut bad_chunk = content[range.clone()].to_vec();
+/// bad_chunk[0] ^= 1;
+/// assert!(verify(&root_hash, range.start as u64, &bad_chunk, &proof).is_err());
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Panics
+///
+/// Panics if the range doesn't follow the rules above.
+#[cfg(feature = "std")]
+pub fn prove(input: &[u8], range: core::ops::Range<u64>) -> RangeProof {
+    let content_len = input.len() as u64;
+    assert!(
+        is_valid_range(content_len, range.start, range.end),
+        "invalid proof range {range:?} for input length {content_len}",
+    );
+    let mut siblings = Vec::new();
+    if range != (0..content_len) {
+        let left_len = left_subtree_len(content_len) as usize;
+        prove_subtree(&input[..left_len], 0, &range, &mut siblings);
+        prove_subtree(&input[left_len..], left_len as u64, &range, &mut siblings);
+    }
+    RangeProof {
+        content_len,
+        siblings,
+    }
+}
+
+#[cfg(feature = "std")]
+fn prove_subtree(
+    subtree: &[u8],
+    start: u64,
+    range: &core::ops::Range<u64>,
+    siblings: &mut Vec<ChainingValue>,
+) {
+    let end = start + subtree.len() as u64;
+    if end <= range.start || range.end <= start {
+        siblings.push(subtree_cv(subtree, start));
+    } else if start < range.start || range.end < end {
+        // This subtree partially overlaps the range, so it must be a parent node.
+        let left_len = left_subtree_len(subtree.len() as u64) as usize;
+        prove_subtree(&subtree[..left_len], start, range, siblings);
+        prove_subtree(
+            &subtree[left_len..],
+            start + left_len as u64,
+            range,
+            siblings,
+        );
+    }
+    // Otherwise this subtree is inside the range, and the verifier hashes it from the data.
+}
+
+/// Check that `data`, found at `offset` within some content, matches the `root` hash of that
+/// content, using a [`RangeProof`] from [`prove`].
+///
+/// The offset and data length must follow the same rules as the range given to [`prove`], using
+/// the content length from the proof. See [`prove`] for an example.
+#[cfg(feature = "std")]
+pub fn verify(
+    root: &crate::Hash,
+    offset: u64,
+    data: &[u8],
+    proof: &RangeProof,
+) -> Result<(), ProofError> {
+    let content_len = proof.content_len;
+    let range = match offset.checked_add(data.len() as u64) {
+        Some(end) if is_valid_range(content_len, offset, end) => offset..end,
+        _ => {
+            return Err(ProofError::InvalidRange {
+                offset,
+                len: data.len() as u64,
+            });
+        }
+    };
+    let mut siblings = proof.siblings.iter();
+    let computed = if range == (0..content_len) {
+        crate::hash(data)
+    } else {
+        let left_len = left_subtree_len(content_len);
+        let left_cv = verify_subtree(0, left_len, data, &range, &mut siblings)?;
+        let right_cv = verify_subtree(
+            left_len,
+            content_len - left_len,
+            data,
+            &range,
+            &mut siblings,
+        )?;
+        merge_subtrees_root(&left_cv, &right_cv, Mode::Hash)
+    };
+    if siblings.next().is_some() {
+        return Err(ProofError::WrongSiblingCount);
+    }
+    if computed != *root {
+        return Err(ProofError::Mismatch);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+fn verify_subtree(
+    start: u64,
+    len: u64,
+    data: &[u8],
+    range: &core::ops::Range<u64>,
+    siblings: &mut core::slice::Iter<ChainingValue>,
+) -> Result<ChainingValue, ProofError> {
+    let end = start + len;
+    if end <= range.start || range.end <= start {
+        siblings
+            .next()
+            .copied()
+            .ok_or(ProofError::WrongSiblingCount)
+    } else if start < range.start || range.end < end {
+        let left_len = left_subtree_len(len);
+        let left_cv = verify_subtree(start, left_len, data, range, siblings)?;
+        let right_cv = verify_subtree(start + left_len, len - left_len, data, range, siblings)?;
+        Ok(merge_subtrees_non_root(&left_cv, &right_cv, Mode::Hash))
+    } else {
+        let subtree = &data[(start - range.start) as usize..(end - range.start) as usize];
+        Ok(subtree_cv(subtree, start))
+    }
+}
+
+/// An append-only log that can commit to any chunk-aligned prefix of its contents, with
+/// [`ConsistencyProof`]s between prefixes.
+///
+/// This is for logs that publish a root hash from time to time, like [Certificate
+/// Transparency](https://certificate.transparency.dev/), where readers need to check that each
+/// published root extends the previous one. The root hash of every prefix is the regular BLAKE3
+/// [`hash`](crate::hash) of that prefix. The log keeps the chaining value of every complete
+/// subtree, about 64 bytes per [`CHUNK_LEN`] bytes of content, but not the content itself. Prefix
+/// roots and proofs only need `O(log n)` of those chaining values.
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), blake3::hazmat::ProofError> {
+/// use blake3::CHUNK_LEN;
+/// use blake3::hazmat::{AppendLog, verify_consistency};
+///
+/// let mut log = AppendLog::new();
+/// log.append(&[1; 10 * CHUNK_LEN]);
+/// let old_len = log.len();
+/// let old_root = log.root();
+///
+/// log.append(&[2; 7 * CHUNK_LEN + 42]);
+/// let new_root = log.root();
+/// let mut content = vec![1; 10 * CHUNK_LEN];
+/// content.extend_from_slice(&[2; 7 * CHUNK_LEN + 42]);
+/// assert_eq!(new_root, blake3::hash(&content));
+/// assert_eq!(log.prefix_root(old_len), old_root);
+///
+/// // A reader who trusts both roots checks that the new log extends the old one.
+/// let proof = log.consistency_proof(old_len, log.len());
+/// verify_consistency(&old_root, &new_root, &proof)?;
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub struct AppendLog {
+    len: u64,
+    // levels[k] holds the chaining values of the complete subtrees of 2^k chunks, in order.
+    levels: Vec<Vec<ChainingValue>>,
+    // The root of a one-chunk prefix isn't derived from the chunk's chaining value, so we keep the
+    // first chunk's bytes.
+    first_chunk: [u8; CHUNK_LEN],
+    // The bytes of the incomplete chunk at the end of the log, if any.
+    buf: [u8; CHUNK_LEN],
+    buf_len: usize,
+}
+
+#[cfg(feature = "std")]
+impl AppendLog {
+    /// Construct a new, empty `AppendLog`.
+    pub fn new() -> Self {
+        Self {
+            len: 0,
+            levels: Vec::new(),
+            first_chunk: [0; CHUNK_LEN],
+            buf: [0; CHUNK_LEN],
+            buf_len: 0,
+        }
+    }
+
+    /// The total number of bytes appended so far.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether nothing has been appended yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Append bytes to the log.
+    pub fn append(&mut self, mut input: &[u8]) -> &mut Self {
+        while !input.is_empty() {
+            // Don't hash a full buffer until more input arrives. It's easier to keep the last
+            // chunk in one place, whether or not it's complete.
+            if self.buf_len == CHUNK_LEN {
+                self.push_chunk();
+            }
+            let take = core::cmp::min(CHUNK_LEN - self.buf_len, input.len());
+            self.buf[self.buf_len..][..take].copy_from_slice(&input[..take]);
+            self.buf_len += take;
+            self.len += take as u64;
+            input = &input[take..];
+        }
+        self
+    }
+
+    fn push_chunk(&mut self) {
+        let chunk_index = self.complete_chunks();
+        if chunk_index == 0 {
+            self.first_chunk = self.buf;
+        }
+        let mut cv = subtree_cv(&self.buf, chunk_index * CHUNK_LEN as u64);
+        self.buf_len = 0;
+        // Merge completed subtrees, one for each trailing 1-bit in the old number of chunks.
+        let mut level = 0;
+        loop {
+            if self.levels.len() == level {
+                self.levels.push(Vec::new());
+            }
+            self.levels[level].push(cv);
+            let len = self.levels[level].len();
+            if len % 2 == 1 {
+                break;
+            }
+            cv = merge_subtrees_non_root(
+                &self.levels[level][len - 2],
+                &self.levels[level][len - 1],
+                Mode::Hash,
+            );
+            level += 1;
+        }
+    }
+
+    // The number of chunks that have been pushed into self.levels.
+    fn complete_chunks(&self) -> u64 {
+        self.levels.first().map_or(0, |chunks| chunks.len() as u64)
+    }
+
+    fn is_valid_prefix_len(&self, len: u64) -> bool {
+        len == self.len || (len % CHUNK_LEN as u64 == 0 && len < self.len)
+    }
+
+    // The chaining value of the subtree from `start` to `end` in a tree of some prefix of the log.
+    // Complete subtrees come from self.levels, and the right edge of the tree is merged here.
+    fn node_cv(&self, start: u64, end: u64) -> ChainingValue {
+        let len = end - start;
+        if len % CHUNK_LEN as u64 == 0
+            && (len / CHUNK_LEN as u64).is_power_of_two()
+            && end <= self.complete_chunks() * CHUNK_LEN as u64
+        {
+            let level = (len / CHUNK_LEN as u64).trailing_zeros() as usize;
+            return self.levels[level][(start / len) as usize];
+        }
+        if len <= CHUNK_LEN as u64 {
+            // The incomplete final chunk.
+            debug_assert_eq!(end, self.len);
+            return subtree_cv(&self.buf[..self.buf_len], start);
+        }
+        let left_len = left_subtree_len(len);
+        merge_subtrees_non_root(
+            &self.node_cv(start, start + left_len),
+            &self.node_cv(start + left_len, end),
+            Mode::Hash,
+        )
+    }
+
+    /// The root hash of the whole log. This is the same as [`hash`](crate::hash) of all the
+    /// bytes appended so far.
+    pub fn root(&self) -> crate::Hash {
+        self.prefix_root(self.len)
+    }
+
+    /// The root hash of the first `len` bytes of the log. This is the same as
+    /// [`hash`](crate::hash) of those bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` is greater than [`len`](Self::len), or if it's less than that but not a
+    /// multiple of [`CHUNK_LEN`].
+    pub fn prefix_root(&self, len: u64) -> crate::Hash {
+        assert!(
+            self.is_valid_prefix_len(len),
+            "invalid prefix length {len} for log length {}",
+            self.len,
+        );
+        if len <= CHUNK_LEN as u64 {
+            let first_chunk = if self.complete_chunks() > 0 {
+                &self.first_chunk
+            } else {
+                &self.buf
+            };
+            return crate::hash(&first_chunk[..len as usize]);
+        }
+        let left_len = left_subtree_len(len);
+        merge_subtrees_root(
+            &self.node_cv(0, left_len),
+            &self.node_cv(left_len, len),
+            Mode::Hash,
+        )
+    }
+
+    /// Build a [`ConsistencyProof`] that the prefix of length `new_len` extends the prefix of
+    /// length `old_len`. Check it with [`verify_consistency`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `old_len` is zero or greater than `new_len`, if `old_len` is less than `new_len`
+    /// but isn't a multiple of [`CHUNK_LEN`], or if `new_len` isn't a valid argument to
+    /// [`prefix_root`](Self::prefix_root).
+    pub fn consistency_proof(&self, old_len: u64, new_len: u64) -> ConsistencyProof {
+        assert!(
+            self.is_valid_prefix_len(new_len) && is_valid_old_len(old_len, new_len),
+            "invalid consistency proof lengths {old_len} and {new_len} for log length {}",
+            self.len,
+        );
+        let mut chaining_values = Vec::new();
+        let mut first_chunk = Vec::new();
+        if old_len < new_len {
+            if old_len == CHUNK_LEN as u64 {
+                first_chunk.extend_from_slice(&self.first_chunk);
+            }
+            for (start, end) in old_pieces(old_len) {
+                chaining_values.push(self.node_cv(start, end));
+            }
+            let left_len = left_subtree_len(new_len);
+            self.push_new_subtrees(0, left_len, old_len, &mut chaining_values);
+            self.push_new_subtrees(left_len, new_len, old_len, &mut chaining_values);
+        }
+        ConsistencyProof {
+            old_len,
+            new_len,
+            chaining_values,
+            first_chunk,
+        }
+    }
+
+    // Push the chaining values of the subtrees in the new tree that are entirely after the old
+    // prefix and next to the path from the root to the end of the old prefix.
+    fn push_new_subtrees(
+        &self,
+        start: u64,
+        end: u64,
+        old_len: u64,
+        chaining_values: &mut Vec<ChainingValue>,
+    ) {
+        if old_len <= start {
+            chaining_values.push(self.node_cv(start, end));
+        } else if old_len < end {
+            let left_len = left_subtree_len(end - start);
+            self.push_new_subtrees(start, start + left_len, old_len, chaining_values);
+            self.push_new_subtrees(start + left_len, end, old_len, chaining_values);
+        }
+        // Otherwise this subtree is part of the old prefix, and its chaining value is derived from
+        // the old pieces.
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for AppendLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Debug for AppendLog {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        // Don't print the chaining values, which could be a lot of output.
+        f.debug_struct("AppendLog")
+            .field("len", &self.len)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A proof that one prefix of an [`AppendLog`] extends another, produced by
+/// [`AppendLog::consistency_proof`] and checked by [`verify_consistency`].
+///
+/// Given the root hashes of the old and new prefixes, the proof holds just enough chaining values
+/// to recompute both of them: the subtrees that make up the old prefix, and the subtrees of the
+/// new prefix that come after it and are next to the path from the root to the end of the old
+/// prefix. There are `O(log n)` of these. When the old prefix is exactly one chunk, its root hash
+/// can't be recomputed from a chaining value, and the proof holds the bytes of that chunk instead.
+#[cfg(feature = "std")]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConsistencyProof {
+    old_len: u64,
+    new_len: u64,
+    chaining_values: Vec<ChainingValue>,
+    first_chunk: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl ConsistencyProof {
+    /// Construct a proof from its parts, for example after receiving them over the network.
+    pub fn new(
+        old_len: u64,
+        new_len: u64,
+        chaining_values: Vec<ChainingValue>,
+        first_chunk: Vec<u8>,
+    ) -> Self {
+        Self {
+            old_len,
+            new_len,
+            chaining_values,
+            first_chunk,
+        }
+    }
+
+    /// The length of the old prefix.
+    pub fn old_len(&self) -> u64 {
+        self.old_len
+    }
+
+    /// The length of the new prefix.
+    pub fn new_len(&self) -> u64 {
+        self.new_len
+    }
+
+    /// The chaining values of the subtrees that make up the old prefix, followed by those of the
+    /// new subtrees after it, in left-to-right order.
+    pub fn chaining_values(&self) -> &[ChainingValue] {
+        &self.chaining_values
+    }
+
+    /// The bytes of the old prefix, if it's exactly one chunk, and otherwise empty.
+    pub fn first_chunk(&self) -> &[u8] {
+        &self.first_chunk
+    }
+}
+
+#[cfg(feature = "std")]
+fn is_valid_old_len(old_len: u64, new_len: u64) -> bool {
+    0 < old_len && (old_len == new_len || (old_len < new_len && old_len % CHUNK_LEN as u64 == 0))
+}
+
+// The subtrees that a consistency proof uses to rebuild the old prefix, as (start, end) pairs.
+// These are the largest complete subtrees in the old prefix, one for each 1-bit in its number of
+// chunks. When there's only one of them, we use its two children instead, so that the root node
+// can be recomputed. A one-chunk prefix uses the chunk's bytes and has no pieces.
+#[cfg(feature = "std")]
+fn old_pieces(old_len: u64) -> arrayvec::ArrayVec<(u64, u64), 64> {
+    let mut pieces = arrayvec::ArrayVec::new();
+    let num_chunks = old_len / CHUNK_LEN as u64;
+    if num_chunks.is_power_of_two() {
+        if num_chunks > 1 {
+            pieces.push((0, old_len / 2));
+            pieces.push((old_len / 2, old_len));
+        }
+        return pieces;
+    }
+    let mut start = 0;
+    for bit in (0..64).rev() {
+        if num_chunks & (1 << bit) != 0 {
+            let size = (1 << bit) * CHUNK_LEN as u64;
+            pieces.push((start, start + size));
+            start += size;
+        }
+    }
+    pieces
+}
+
+// The chaining value of a subtree of the old prefix, merged from the pieces it contains.
+#[cfg(feature = "std")]
+fn subtree_cv_from_pieces(
+    start: u64,
+    end: u64,
+    pieces: &[(u64, u64, ChainingValue)],
+) -> ChainingValue {
+    if let Some((_, _, cv)) = pieces.iter().find(|&&(s, e, _)| (s, e) == (start, end)) {
+        return *cv;
+    }
+    let left_len = left_subtree_len(end - start);
+    merge_subtrees_non_root(
+        &subtree_cv_from_pieces(start, start + left_len, pieces),
+        &subtree_cv_from_pieces(start + left_len, end, pieces),
+        Mode::Hash,
     )
-    .root_hash()
-    .0
+}
+
+/// Check a [`ConsistencyProof`] that the log with root hash `new_root` extends the log with root
+/// hash `old_root`.
+///
+/// The proof says what the two prefix lengths are. Callers should check that those lengths match
+/// the ones they expect, since a valid proof between two other prefixes of the same log would also
+/// pass. See [`AppendLog`] for an example.
+#[cfg(feature = "std")]
+pub fn verify_consistency(
+    old_root: &crate::Hash,
+    new_root: &crate::Hash,
+    proof: &ConsistencyProof,
+) -> Result<(), ProofError> {
+    let (old_len, new_len) = (proof.old_len, proof.new_len);
+    if !is_valid_old_len(old_len, new_len) {
+        return Err(ProofError::InvalidLengths { old_len, new_len });
+    }
+    if old_len == new_len {
+        if !proof.chaining_values.is_empty() || !proof.first_chunk.is_empty() {
+            return Err(ProofError::WrongSiblingCount);
+        }
+        return if old_root == new_root {
+            Ok(())
+        } else {
+            Err(ProofError::Mismatch)
+        };
+    }
+
+    // Rebuild the old root from the pieces of the old prefix.
+    let mut pieces = arrayvec::ArrayVec::<_, 64>::new();
+    let mut chaining_values = proof.chaining_values.iter();
+    for (start, end) in old_pieces(old_len) {
+        let cv = chaining_values
+            .next()
+            .ok_or(ProofError::WrongSiblingCount)?;
+        pieces.push((start, end, *cv));
+    }
+    let computed_old_root = if old_len == CHUNK_LEN as u64 {
+        if proof.first_chunk.len() != CHUNK_LEN {
+            return Err(ProofError::WrongSiblingCount);
+        }
+        pieces.push((0, old_len, subtree_cv(&proof.first_chunk, 0)));
+        crate::hash(&proof.first_chunk)
+    } else {
+        if !proof.first_chunk.is_empty() {
+            return Err(ProofError::WrongSiblingCount);
+        }
+        let left_len = left_subtree_len(old_len);
+        merge_subtrees_root(
+            &subtree_cv_from_pieces(0, left_len, &pieces),
+            &subtree_cv_from_pieces(left_len, old_len, &pieces),
+            Mode::Hash,
+        )
+    };
+
+    // Rebuild the new root from the old pieces and the new subtrees.
+    let left_len = left_subtree_len(new_len);
+    let left_cv = new_subtree_cv(0, left_len, old_len, &pieces, &mut chaining_values)?;
+    let right_cv = new_subtree_cv(left_len, new_len, old_len, &pieces, &mut chaining_values)?;
+    let computed_new_root = merge_subtrees_root(&left_cv, &right_cv, Mode::Hash);
+    if chaining_values.next().is_some() {
+        return Err(ProofError::WrongSiblingCount);
+    }
+    if computed_old_root != *old_root || computed_new_root != *new_root {
+        return Err(ProofError::Mismatch);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+fn new_subtree_cv(
+    start: u64,
+    end: u64,
+    old_len: u64,
+    pieces: &[(u64, u64, ChainingValue)],
+    chaining_values: &mut core::slice::Iter<ChainingValue>,
+) -> Result<ChainingValue, ProofError> {
+    if old_len <= start {
+        chaining_values
+            .next()
+            .copied()
+            .ok_or(ProofError::WrongSiblingCount)
+    } else if old_len < end {
+        let left_len = left_subtree_len(end - start);
+        let left_cv = new_subtree_cv(start, start + left_len, old_len, pieces, chaining_values)?;
+        let right_cv = new_subtree_cv(start + left_len, end, old_len, pieces, chaining_values)?;
+        Ok(merge_subtrees_non_root(&left_cv, &right_cv, Mode::Hash))
+    } else {
+        Ok(subtree_cv_from_pieces(start, end, pieces))
+    }
+}
+
+/// The error type for [`SubtreeAssembler`].
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AssembleError {
+    /// A piece was empty, didn't start at a multiple of [`CHUNK_LEN`], or was longer than
+    /// [`max_subtree_len`] allows at its offset. Either way, it isn't a subtree of any valid tree.
+    Misaligned {
+        /// The offset of the piece.
+        offset: u64,
+        /// The length of the piece.
+        len: u64,
+    },
+    /// A piece overlapped one that was added before.
+    Overlap {
+        /// The offset of the piece.
+        offset: u64,
+    },
+    /// A piece or a call to [`set_total_len`](SubtreeAssembler::set_total_len) contradicted the
+    /// total length. Only the final subtree can be shorter than a power-of-two number of chunks,
+    /// so a short piece also sets the total length.
+    LengthMismatch {
+        /// The total length that was already known.
+        expected: u64,
+        /// The end of the piece, or the length given to `set_total_len`.
+        found: u64,
+    },
+    /// The total length isn't known yet, or some part of the input is still missing.
+    Incomplete,
+    /// A single chaining value covers the whole input. The root node has to be finalized
+    /// differently, so it needs the input bytes or the chaining values of its two children.
+    RootChainingValue,
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            AssembleError::Misaligned { offset, len } => {
+                write!(f, "invalid subtree: {len} bytes at offset {offset}")
+            }
+            AssembleError::Overlap { offset } => {
+                write!(f, "overlapping subtree at offset {offset}")
+            }
+            AssembleError::LengthMismatch { expected, found } => {
+                write!(f, "expected total length {expected}, found {found}")
+            }
+            AssembleError::Incomplete => write!(f, "incomplete input"),
+            AssembleError::RootChainingValue => {
+                write!(f, "can't finalize the root from a single chaining value")
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AssembleError {}
+
+// What a SubtreeAssembler knows about a subtree it's holding.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+struct AssembledSubtree {
+    len: u64,
+    cv: ChainingValue,
+    // If this subtree turns out to be the whole input, this is what we need to finalize the root.
+    root: AssembledRoot,
+}
+
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+enum AssembledRoot {
+    // A subtree of one chunk, at offset zero.
+    Chunk(Vec<u8>),
+    // A subtree of more than one chunk.
+    Children(ChainingValue, ChainingValue),
+    // A subtree added with add_chaining_value, which can't be the root.
+    Unknown,
+}
+
+/// Assemble the root of a tree from subtrees that arrive in any order.
+///
+/// This is for downloading an input in pieces from different sources, for example from several
+/// peers in a BitTorrent-like protocol, where pieces arrive out of order. Each piece is either the
+/// bytes of a subtree ([`add_bytes`](Self::add_bytes)) or its chaining value
+/// ([`add_chaining_value`](Self::add_chaining_value)), at a given offset. The assembler checks
+/// that every piece is a valid subtree and that pieces don't overlap, and it merges sibling
+/// subtrees as soon as both are present, so that it only holds chaining values along the gaps in
+/// the input. Once the total length is known and the whole input is covered,
+/// [`finalize`](Self::finalize) returns the root hash.
+///
+/// The total length is known after [`set_total_len`](Self::set_total_len), or after adding a
+/// piece that's shorter than a power-of-two number of chunks, which can only be the final subtree.
+/// If the input is a power-of-two number of chunks, you have to call `set_total_len`.
+///
+/// The chaining values of pieces come from [`HasherExt::finalize_non_root`] or
+/// [`merge_subtrees_non_root`], with the same [`Mode`] as the assembler. A chaining value alone
+/// can't be finalized as the root, so if a single piece is the whole input, it has to be added with
+/// `add_bytes`. (If the input is more than one chunk, it's always enough to add its two halves.)
+///
+/// # Example
+///
+/// ```
+/// # fn main() -> Result<(), blake3::hazmat::AssembleError> {
+/// use blake3::CHUNK_LEN;
+/// use blake3::hazmat::{HasherExt, Mode, SubtreeAssembler};
+///
+/// let input = vec![0xab; 6 * CHUNK_LEN + 42];
+/// let mut assembler = SubtreeAssembler::new(Mode::Hash);
+///
+/// // The final piece arrives first, which tells the assembler the total length.
+/// assembler.add_bytes(6 * CHUNK_LEN as u64, &input[6 * CHUNK_LEN..])?;
+/// // A peer sends the chaining value of chunks 4 and 5 instead of their bytes.
+/// let cv = blake3::Hasher::new()
+///     .set_input_offset(4 * CHUNK_LEN as u64)
+///     .update(&input[4 * CHUNK_LEN..6 * CHUNK_LEN])
+///     .finalize_non_root();
+/// assembler.add_chaining_value(4 * CHUNK_LEN as u64, 2 * CHUNK_LEN as u64, &cv)?;
+/// assert!(!assembler.is_complete());
+/// assembler.add_bytes(0, &input[..4 * CHUNK_LEN])?;
+///
+/// assert!(assembler.is_complete());
+/// assert_eq!(assembler.finalize()?, blake3::hash(&input));
+/// # Ok(())
+/// # }
+/// ```
+#[cfg(feature = "std")]
+#[derive(Clone, Debug)]
+pub struct SubtreeAssembler<'a> {
+    mode: Mode<'a>,
+    len: Option<u64>,
+    // Subtrees by offset. They never overlap, and sibling pairs are always merged.
+    subtrees: std::collections::BTreeMap<u64, AssembledSubtree>,
+}
+
+#[cfg(feature = "std")]
+impl<'a> SubtreeAssembler<'a> {
+    /// Construct a new, empty `SubtreeAssembler`. All the pieces must be hashed with the same
+    /// `mode`.
+    pub fn new(mode: Mode<'a>) -> Self {
+        Self {
+            mode,
+            len: None,
+            subtrees: std::collections::BTreeMap::new(),
+        }
+    }
+
+    /// The total length of the input, if it's known yet.
+    pub fn total_len(&self) -> Option<u64> {
+        self.len
+    }
+
+    /// Set the total length of the input.
+    ///
+    /// This returns an error if the total length is already known and different, or if a piece
+    /// that's already been added ends after `len`.
+    pub fn set_total_len(&mut self, len: u64) -> Result<(), AssembleError> {
+        if let Some(expected) = self.len {
+            if expected != len {
+                return Err(AssembleError::LengthMismatch {
+                    expected,
+                    found: len,
+                });
+            }
+            return Ok(());
+        }
+        if let Some((&offset, last)) = self.subtrees.last_key_value() {
+            if offset + last.len > len {
+                return Err(AssembleError::LengthMismatch {
+                    expected: offset + last.len,
+                    found: len,
+                });
+            }
+        }
+        self.len = Some(len);
+        if let Some((offset, last)) = self.subtrees.pop_last() {
+            self.insert(offset, last);
+        }
+        Ok(())
+    }
+
+    /// Add the bytes of a subtree starting at `offset`.
+    ///
+    /// This hashes the bytes, so it's best to add large pieces at once. See
+    /// [`add_chaining_value`](Self::add_chaining_value) for the rules about which pieces are
+    /// valid.
+    pub fn add_bytes(&mut self, offset: u64, bytes: &[u8]) -> Result<(), AssembleError> {
+        let len = bytes.len() as u64;
+        self.check_new_subtree(offset, len)?;
+        let (cv, root) = if len <= CHUNK_LEN as u64 {
+            let cv = self.hasher_at(offset).update(bytes).finalize_non_root();
+            let root = if offset == 0 {
+                AssembledRoot::Chunk(bytes.to_vec())
+            } else {
+                AssembledRoot::Unknown
+            };
+            (cv, root)
+        } else {
+            let left_len = left_subtree_len(len);
+            let (left, right) = bytes.split_at(left_len as usize);
+            let left_cv = self.hasher_at(offset).update(left).finalize_non_root();
+            let right_cv = self
+                .hasher_at(offset + left_len)
+                .update(right)
+                .finalize_non_root();
+            let cv = merge_subtrees_non_root(&left_cv, &right_cv, self.mode);
+            (cv, AssembledRoot::Children(left_cv, right_cv))
+        };
+        self.insert(offset, AssembledSubtree { len, cv, root });
+        Ok(())
+    }
+
+    /// Add the chaining value of the `len`-byte subtree starting at `offset`.
+    ///
+    /// A valid subtree starts at a multiple of [`CHUNK_LEN`], and it isn't empty. Its length is
+    /// either a power-of-two number of chunks, or it's the final subtree of the input and no
+    /// longer than [`max_subtree_len`] of its offset. This returns an error if the piece isn't a
+    /// valid subtree, if it overlaps a piece that's already been added, or if it contradicts the
+    /// total length.
+    pub fn add_chaining_value(
+        &mut self,
+        offset: u64,
+        len: u64,
+        cv: &ChainingValue,
+    ) -> Result<(), AssembleError> {
+        self.check_new_subtree(offset, len)?;
+        let subtree = AssembledSubtree {
+            len,
+            cv: *cv,
+            root: AssembledRoot::Unknown,
+        };
+        self.insert(offset, subtree);
+        Ok(())
+    }
+
+    /// Whether the total length is known and every part of the input has been added.
+    pub fn is_complete(&self) -> bool {
+        let Some(len) = self.len else {
+            return false;
+        };
+        let mut covered = 0;
+        for (&offset, subtree) in &self.subtrees {
+            if offset != covered {
+                return false;
+            }
+            covered += subtree.len;
+        }
+        covered == len
+    }
+
+    /// Return the root hash of the input.
+    ///
+    /// This returns an error if the input isn't [complete](Self::is_complete), or if it's
+    /// covered by a single chaining value.
+    pub fn finalize(&self) -> Result<crate::Hash, AssembleError> {
+        match self.root_node()? {
+            AssembledRoot::Chunk(bytes) => Ok(self.hasher_at(0).update(&bytes).finalize()),
+            AssembledRoot::Children(left, right) => {
+                Ok(merge_subtrees_root(&left, &right, self.mode))
+            }
+            AssembledRoot::Unknown => Err(AssembleError::RootChainingValue),
+        }
+    }
+
+    /// Return a root [`OutputReader`](crate::OutputReader) for the input. The same errors apply
+    /// as for [`finalize`](Self::finalize).
+    pub fn finalize_xof(&self) -> Result<crate::OutputReader, AssembleError> {
+        match self.root_node()? {
+            AssembledRoot::Chunk(bytes) => Ok(self.hasher_at(0).update(&bytes).finalize_xof()),
+            AssembledRoot::Children(left, right) => {
+                Ok(merge_subtrees_root_xof(&left, &right, self.mode))
+            }
+            AssembledRoot::Unknown => Err(AssembleError::RootChainingValue),
+        }
+    }
+
+    fn hasher_at(&self, offset: u64) -> Hasher {
+        let mut hasher = match self.mode {
+            Mode::Hash => Hasher::new(),
+            Mode::KeyedHash(key) => Hasher::new_keyed(key),
+            Mode::DeriveKeyMaterial(context_key) => Hasher::new_from_context_key(context_key),
+        };
+        hasher.set_input_offset(offset);
+        hasher
+    }
+
+    fn check_new_subtree(&mut self, offset: u64, len: u64) -> Result<(), AssembleError> {
+        let is_valid = offset % CHUNK_LEN as u64 == 0
+            && len > 0
+            && max_subtree_len(offset).is_none_or(|max| len <= max);
+        let Some(end) = offset.checked_add(len).filter(|_| is_valid) else {
+            return Err(AssembleError::Misaligned { offset, len });
+        };
+        // Only the final subtree can be shorter than a power-of-two number of chunks.
+        let is_final = len % CHUNK_LEN as u64 != 0 || !(len / CHUNK_LEN as u64).is_power_of_two();
+        match self.len {
+            Some(expected) if end > expected || (is_final && end != expected) => {
+                return Err(AssembleError::LengthMismatch {
+                    expected,
+                    found: end,
+                });
+            }
+            _ => {}
+        }
+        if let Some((&prev_offset, prev)) = self.subtrees.range(..end).next_back() {
+            if prev_offset + prev.len > offset {
+                return Err(AssembleError::Overlap { offset });
+            }
+        }
+        if is_final && self.len.is_none() {
+            self.set_total_len(end)?;
+        }
+        Ok(())
+    }
+
+    // Insert a new subtree, merging it with its siblings as far up the tree as possible. Along the
+    // right edge of the tree, subtrees can only be merged once the total length is known, so
+    // set_total_len inserts the last subtree again.
+    fn insert(&mut self, mut offset: u64, mut subtree: AssembledSubtree) {
+        loop {
+            let end = offset + subtree.len;
+            let is_final = self.len == Some(end);
+            if offset == 0 && is_final {
+                break;
+            }
+            let is_complete = subtree.len % CHUNK_LEN as u64 == 0
+                && (subtree.len / CHUNK_LEN as u64).is_power_of_two();
+            let (sibling_offset, sibling_len, is_left) =
+                if is_complete && !is_final && (offset / subtree.len) % 2 == 0 {
+                    // The right sibling is the same size, unless it's the final subtree. If
+                    // its end doesn't fit in a u64, it can only be the final subtree.
+                    let sibling_end = match (end.checked_add(subtree.len), self.len) {
+                        (Some(sibling_end), Some(len)) => core::cmp::min(sibling_end, len),
+                        (Some(sibling_end), None) => sibling_end,
+                        (None, Some(len)) => len,
+                        (None, None) => break,
+                    };
+                    (end, sibling_end - end, true)
+                } else if is_complete || is_final {
+                    // The left sibling is the largest subtree that can end at this offset.
+                    let sibling_len = 1 << offset.trailing_zeros();
+                    (offset - sibling_len, sibling_len, false)
+                } else {
+                    break;
+                };
+            match self.subtrees.get(&sibling_offset) {
+                Some(sibling) if sibling.len == sibling_len => {}
+                _ => break,
+            }
+            let sibling = self.subtrees.remove(&sibling_offset).unwrap();
+            let (left, right) = if is_left {
+                (subtree.cv, sibling.cv)
+            } else {
+                (sibling.cv, subtree.cv)
+            };
+            offset = core::cmp::min(offset, sibling_offset);
+            subtree = AssembledSubtree {
+                len: subtree.len + sibling_len,
+                cv: merge_subtrees_non_root(&left, &right, self.mode),
+                root: AssembledRoot::Children(left, right),
+            };
+        }
+        self.subtrees.insert(offset, subtree);
+    }
+
+    // What we need to finalize the root node. Once the input is complete, every subtree has been
+    // merged into one.
+    fn root_node(&self) -> Result<AssembledRoot, AssembleError> {
+        if !self.is_complete() {
+            return Err(AssembleError::Incomplete);
+        }
+        match self.subtrees.first_key_value() {
+            // The empty input is a single empty chunk, and no pieces are needed.
+            None => Ok(AssembledRoot::Chunk(Vec::new())),
+            Some((_, subtree)) => {
+                debug_assert_eq!(self.subtrees.len(), 1);
+                Ok(subtree.root.clone())
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -611,6 +1930,148 @@ mod test {
             .finalize_xof();
     }
 
+    #[test]
+    fn test_try_methods() {
+        let chunk = CHUNK_LEN as u64;
+        assert_eq!(
+            Hasher::new().try_set_input_offset(1).err(),
+            Some(TreeShapeError::UnalignedOffset { offset: 1 }),
+        );
+        assert_eq!(
+            Hasher::new().update(b"x").try_set_input_offset(0).err(),
+            Some(TreeShapeError::AlreadyUpdated),
+        );
+        let mut hasher = Hasher::new();
+        hasher.try_set_input_offset(2 * chunk).unwrap();
+        assert_eq!(
+            hasher.try_finalize_non_root(),
+            Err(TreeShapeError::EmptySubtree { offset: 2 * chunk }),
+        );
+        hasher.try_update(&[1; CHUNK_LEN]).unwrap();
+        assert_eq!(
+            hasher.try_update(&[2; CHUNK_LEN + 1]).err(),
+            Some(TreeShapeError::SubtreeTooLong {
+                offset: 2 * chunk,
+                len: 2 * chunk + 1,
+                max_len: 2 * chunk,
+            }),
+        );
+        // The failed update didn't hash anything, and the subtree can still be filled exactly.
+        hasher.try_update(&[2; CHUNK_LEN]).unwrap();
+        let expected = Hasher::new()
+            .set_input_offset(2 * chunk)
+            .update(&[1; CHUNK_LEN])
+            .update(&[2; CHUNK_LEN])
+            .finalize_non_root();
+        assert_eq!(hasher.try_finalize_non_root(), Ok(expected));
+        assert_eq!(
+            hasher.try_update(b"x").err(),
+            Some(TreeShapeError::SubtreeTooLong {
+                offset: 2 * chunk,
+                len: 2 * chunk + 1,
+                max_len: 2 * chunk,
+            }),
+        );
+        // At offset zero there's no maximum.
+        Hasher::new()
+            .try_set_input_offset(0)
+            .unwrap()
+            .try_update(&[0; 10 * CHUNK_LEN])
+            .unwrap();
+    }
+
+    #[test]
+    fn test_validate_subtrees() {
+        let chunk = CHUNK_LEN as u64;
+        assert_eq!(validate_subtrees(&[]), Ok(0));
+        assert_eq!(validate_subtrees(&[(0, 1)]), Ok(1));
+        assert_eq!(validate_subtrees(&[(0, 5 * chunk + 1)]), Ok(5 * chunk + 1));
+        assert_eq!(
+            validate_subtrees(&[(0, chunk), (chunk, chunk), (2 * chunk, 2 * chunk - 1)]),
+            Ok(4 * chunk - 1),
+        );
+        assert_eq!(
+            validate_subtrees(&[
+                (0, 8 * chunk),
+                (8 * chunk, 4 * chunk),
+                (12 * chunk, 3 * chunk)
+            ]),
+            Ok(15 * chunk),
+        );
+        assert_eq!(
+            validate_subtrees(&[(0, 0)]),
+            Err(TreeShapeError::EmptySubtree { offset: 0 }),
+        );
+        assert_eq!(
+            validate_subtrees(&[(chunk, chunk)]),
+            Err(TreeShapeError::NotContiguous {
+                offset: chunk,
+                expected: 0,
+            }),
+        );
+        assert_eq!(
+            validate_subtrees(&[(0, chunk), (2 * chunk, chunk)]),
+            Err(TreeShapeError::NotContiguous {
+                offset: 2 * chunk,
+                expected: chunk,
+            }),
+        );
+        assert_eq!(
+            validate_subtrees(&[(0, 3 * chunk), (3 * chunk, chunk)]),
+            Err(TreeShapeError::IncompleteSubtree {
+                offset: 0,
+                len: 3 * chunk,
+            }),
+        );
+        assert_eq!(
+            validate_subtrees(&[(0, chunk - 1), (chunk - 1, 1)]),
+            Err(TreeShapeError::IncompleteSubtree {
+                offset: 0,
+                len: chunk - 1,
+            }),
+        );
+        assert_eq!(
+            validate_subtrees(&[(0, 2 * chunk), (2 * chunk, 4 * chunk)]),
+            Err(TreeShapeError::SubtreeTooLong {
+                offset: 2 * chunk,
+                len: 4 * chunk,
+                max_len: 2 * chunk,
+            }),
+        );
+        // Lengths that would overflow are errors, not panics.
+        let half = 1 << 63;
+        assert_eq!(
+            validate_subtrees(&[(0, half), (half, half)]),
+            Err(TreeShapeError::SubtreeTooLong {
+                offset: half,
+                len: half,
+                max_len: half - 1,
+            }),
+        );
+
+        // Every split along the canonical shape is valid, and hashing it gives the right root.
+        let mut input = [0; 7 * CHUNK_LEN + 1];
+        crate::test::paint_test_input(&mut input);
+        let subtrees = [
+            (0, 4 * chunk),
+            (4 * chunk, 2 * chunk),
+            (6 * chunk, chunk + 1),
+        ];
+        assert_eq!(validate_subtrees(&subtrees), Ok(input.len() as u64));
+        let cvs = subtrees.map(|(offset, len)| {
+            Hasher::new()
+                .try_set_input_offset(offset)
+                .unwrap()
+                .try_update(&input[offset as usize..][..len as usize])
+                .unwrap()
+                .try_finalize_non_root()
+                .unwrap()
+        });
+        let right = merge_subtrees_non_root(&cvs[1], &cvs[2], Mode::Hash);
+        let root = merge_subtrees_root(&cvs[0], &right, Mode::Hash);
+        assert_eq!(root, crate::hash(&input));
+    }
+
     #[test]
     fn test_grouped_hash() {
         const MAX_CHUNKS: usize = (crate::test::TEST_CASES_MAX + 1) / CHUNK_LEN;
@@ -712,5 +2173,426 @@ mod test {
             .finalize_non_root();
         let derived_key = merge_subtrees_root(&left, &right, Mode::DeriveKeyMaterial(&cx_key)).0;
         assert_eq!(expected, derived_key);
+        assert_eq!(cx_key, hash_derive_key_context_raw(context.as_bytes()));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_range_proofs() {
+        let mut input_buf = [0; crate::test::TEST_CASES_MAX];
+        crate::test::paint_test_input(&mut input_buf);
+        for &case in crate::test::TEST_CASES {
+            let input = &input_buf[..case];
+            let root = crate::hash(input);
+            let num_chunks = core::cmp::max(1, case.div_ceil(CHUNK_LEN));
+            for first in 0..num_chunks {
+                for last in first..num_chunks {
+                    let start = first * CHUNK_LEN;
+                    let end = core::cmp::min((last + 1) * CHUNK_LEN, case);
+                    let data = &input[start..end];
+                    let proof = prove(input, start as u64..end as u64);
+                    assert_eq!(proof.content_len(), case as u64);
+                    verify(&root, start as u64, data, &proof).unwrap();
+
+                    // Tampering with any sibling is caught.
+                    for i in 0..proof.siblings().len() {
+                        let mut siblings = proof.siblings().to_vec();
+                        siblings[i][0] ^= 1;
+                        let bad_proof = RangeProof::new(case as u64, siblings);
+                        assert_eq!(
+                            verify(&root, start as u64, data, &bad_proof),
+                            Err(ProofError::Mismatch),
+                        );
+                    }
+                    // So is a missing or an extra sibling.
+                    let mut siblings = proof.siblings().to_vec();
+                    siblings.push([0; OUT_LEN]);
+                    let bad_proof = RangeProof::new(case as u64, siblings.clone());
+                    assert_eq!(
+                        verify(&root, start as u64, data, &bad_proof),
+                        Err(ProofError::WrongSiblingCount),
+                    );
+                    if !proof.siblings().is_empty() {
+                        siblings.truncate(proof.siblings().len() - 1);
+                        let bad_proof = RangeProof::new(case as u64, siblings);
+                        assert_eq!(
+                            verify(&root, start as u64, data, &bad_proof),
+                            Err(ProofError::WrongSiblingCount),
+                        );
+                    }
+                    // And so is a data byte.
+                    if !data.is_empty() {
+                        let mut bad_data = data.to_vec();
+                        bad_data[data.len() / 2] ^= 1;
+                        assert_eq!(
+                            verify(&root, start as u64, &bad_data, &proof),
+                            Err(ProofError::Mismatch),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_range_proof_errors() {
+        let input = [42; 5 * CHUNK_LEN + 1];
+        let root = crate::hash(&input);
+        let proof = prove(&input, CHUNK_LEN as u64..3 * CHUNK_LEN as u64);
+        // Each sibling is at a different level of the tree.
+        assert_eq!(proof.siblings().len(), 3);
+        let data = &input[CHUNK_LEN..3 * CHUNK_LEN];
+        verify(&root, CHUNK_LEN as u64, data, &proof).unwrap();
+        // Not a chunk boundary.
+        assert_eq!(
+            verify(&root, 1, &data[1..], &proof),
+            Err(ProofError::InvalidRange {
+                offset: 1,
+                len: data.len() as u64 - 1,
+            }),
+        );
+        // Ends in the middle of a chunk.
+        assert!(matches!(
+            verify(&root, CHUNK_LEN as u64, &data[1..], &proof),
+            Err(ProofError::InvalidRange { .. }),
+        ));
+        // Empty.
+        assert!(matches!(
+            verify(&root, CHUNK_LEN as u64, &[], &proof),
+            Err(ProofError::InvalidRange { .. }),
+        ));
+        // Past the end of the content.
+        assert!(matches!(
+            verify(&root, 5 * CHUNK_LEN as u64, &[42; 2], &proof),
+            Err(ProofError::InvalidRange { .. }),
+        ));
+        assert!(matches!(
+            verify(&root, u64::MAX - 1, &[42; 2], &proof),
+            Err(ProofError::InvalidRange { .. }),
+        ));
+        // The right data at the wrong offset.
+        assert!(verify(&root, 2 * CHUNK_LEN as u64, data, &proof).is_err());
+        // The wrong root.
+        assert_eq!(
+            verify(&crate::hash(b"foo"), CHUNK_LEN as u64, data, &proof),
+            Err(ProofError::Mismatch),
+        );
+        // A forged content length as long as possible, which doesn't overflow.
+        let huge = RangeProof::new(u64::MAX, proof.siblings().to_vec());
+        assert_eq!(
+            verify(&root, CHUNK_LEN as u64, data, &huge),
+            Err(ProofError::WrongSiblingCount),
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    #[should_panic]
+    fn test_prove_unaligned_range_should_panic() {
+        prove(&[0; 3 * CHUNK_LEN], 1..CHUNK_LEN as u64);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_append_log() {
+        let mut input_buf = [0; crate::test::TEST_CASES_MAX];
+        crate::test::paint_test_input(&mut input_buf);
+        for &case in crate::test::TEST_CASES {
+            let input = &input_buf[..case];
+            // Append in uneven pieces, checking the root along the way.
+            let mut log = AppendLog::new();
+            assert_eq!(log.root(), crate::hash(b""));
+            let mut appended = 0;
+            for piece in input.chunks(700) {
+                log.append(piece);
+                appended += piece.len();
+                assert_eq!(log.len(), appended as u64);
+                assert_eq!(log.root(), crate::hash(&input[..appended]));
+            }
+            assert_eq!(log.is_empty(), case == 0);
+
+            let mut prefix_lens: Vec<u64> = (0..case as u64).step_by(CHUNK_LEN).collect();
+            prefix_lens.push(case as u64);
+            for &new_len in &prefix_lens {
+                let new_root = log.prefix_root(new_len);
+                assert_eq!(new_root, crate::hash(&input[..new_len as usize]));
+                for &old_len in &prefix_lens {
+                    if old_len == 0
+                        || old_len > new_len
+                        || (old_len < new_len && old_len % CHUNK_LEN as u64 != 0)
+                    {
+                        continue;
+                    }
+                    let old_root = log.prefix_root(old_len);
+                    let proof = log.consistency_proof(old_len, new_len);
+                    verify_consistency(&old_root, &new_root, &proof).unwrap();
+                    if old_len == new_len {
+                        continue;
+                    }
+                    // Swapping the roots, or tampering with any part of the proof, is caught.
+                    assert!(verify_consistency(&new_root, &old_root, &proof).is_err());
+                    for i in 0..proof.chaining_values().len() {
+                        let mut chaining_values = proof.chaining_values().to_vec();
+                        chaining_values[i][0] ^= 1;
+                        let bad_proof = ConsistencyProof::new(
+                            old_len,
+                            new_len,
+                            chaining_values,
+                            proof.first_chunk().to_vec(),
+                        );
+                        assert_eq!(
+                            verify_consistency(&old_root, &new_root, &bad_proof),
+                            Err(ProofError::Mismatch),
+                        );
+                    }
+                    let mut chaining_values = proof.chaining_values().to_vec();
+                    chaining_values.push([0; OUT_LEN]);
+                    let bad_proof = ConsistencyProof::new(
+                        old_len,
+                        new_len,
+                        chaining_values,
+                        proof.first_chunk().to_vec(),
+                    );
+                    assert_eq!(
+                        verify_consistency(&old_root, &new_root, &bad_proof),
+                        Err(ProofError::WrongSiblingCount),
+                    );
+                    if !proof.first_chunk().is_empty() {
+                        let mut first_chunk = proof.first_chunk().to_vec();
+                        first_chunk[0] ^= 1;
+                        let bad_proof = ConsistencyProof::new(
+                            old_len,
+                            new_len,
+                            proof.chaining_values().to_vec(),
+                            first_chunk,
+                        );
+                        assert_eq!(
+                            verify_consistency(&old_root, &new_root, &bad_proof),
+                            Err(ProofError::Mismatch),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_consistency_proof_errors() {
+        let mut log = AppendLog::new();
+        log.append(&[42; 5 * CHUNK_LEN + 1]);
+        let old_root = log.prefix_root(3 * CHUNK_LEN as u64);
+        let new_root = log.root();
+        let proof = log.consistency_proof(3 * CHUNK_LEN as u64, log.len());
+        verify_consistency(&old_root, &new_root, &proof).unwrap();
+        for (old_len, new_len) in [(0, 1), (1, 2), (2 * CHUNK_LEN as u64, CHUNK_LEN as u64)] {
+            let bad_proof =
+                ConsistencyProof::new(old_len, new_len, proof.chaining_values().to_vec(), vec![]);
+            assert_eq!(
+                verify_consistency(&old_root, &new_root, &bad_proof),
+                Err(ProofError::InvalidLengths { old_len, new_len }),
+            );
+        }
+        // Huge untrusted lengths fail verification without overflowing.
+        for (old_len, first_chunk) in [
+            (1 << 62, vec![]),
+            (CHUNK_LEN as u64, vec![0; CHUNK_LEN]),
+            (u64::MAX - (CHUNK_LEN as u64 - 1), vec![]),
+        ] {
+            let huge_proof = ConsistencyProof::new(
+                old_len,
+                u64::MAX,
+                proof.chaining_values().to_vec(),
+                first_chunk,
+            );
+            assert!(verify_consistency(&old_root, &new_root, &huge_proof).is_err());
+        }
+        // A valid proof for different lengths doesn't verify these roots.
+        let other_proof = log.consistency_proof(2 * CHUNK_LEN as u64, log.len());
+        assert_eq!(
+            verify_consistency(&old_root, &new_root, &other_proof),
+            Err(ProofError::Mismatch),
+        );
+        // Equal lengths need equal roots and an empty proof.
+        let same = log.consistency_proof(log.len(), log.len());
+        verify_consistency(&new_root, &new_root, &same).unwrap();
+        assert_eq!(
+            verify_consistency(&old_root, &new_root, &same),
+            Err(ProofError::Mismatch),
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    #[should_panic]
+    fn test_unaligned_prefix_root_should_panic() {
+        AppendLog::new()
+            .append(&[0; 3 * CHUNK_LEN])
+            .prefix_root(CHUNK_LEN as u64 + 1);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_subtree_assembler() {
+        let mut input_buf = [0; crate::test::TEST_CASES_MAX];
+        crate::test::paint_test_input(&mut input_buf);
+        let key = [42; KEY_LEN];
+        let context_key = hash_derive_key_context("BLAKE3 2025-01-01 subtree assembler test");
+        for &case in crate::test::TEST_CASES {
+            let input = &input_buf[..case];
+            for mode in [
+                Mode::Hash,
+                Mode::KeyedHash(&key),
+                Mode::DeriveKeyMaterial(&context_key),
+            ] {
+                let mut expected = SubtreeAssembler::new(mode).hasher_at(0);
+                expected.update(input);
+                let expected_hash = expected.finalize();
+                let mut expected_xof = [0; 100];
+                expected.finalize_xof().fill(&mut expected_xof);
+
+                // Split the input into its chunks, and add them in forward and reverse order.
+                // Even chunks go in as bytes and odd chunks as chaining values. Only the final
+                // chunk sets the length, if it's short.
+                let num_chunks = case.div_ceil(CHUNK_LEN).max(1);
+                let mut order: Vec<usize> = (0..num_chunks).collect();
+                for _ in 0..2 {
+                    let mut assembler = SubtreeAssembler::new(mode);
+                    for (step, &i) in order.iter().enumerate() {
+                        let offset = (i * CHUNK_LEN) as u64;
+                        let chunk = &input[i * CHUNK_LEN..case.min((i + 1) * CHUNK_LEN)];
+                        if chunk.is_empty() {
+                            assembler.set_total_len(0).unwrap();
+                        } else if i % 2 == 0 {
+                            assembler.add_bytes(offset, chunk).unwrap();
+                        } else {
+                            let cv = assembler
+                                .hasher_at(offset)
+                                .update(chunk)
+                                .finalize_non_root();
+                            assembler
+                                .add_chaining_value(offset, chunk.len() as u64, &cv)
+                                .unwrap();
+                        }
+                        if step + 1 < num_chunks {
+                            assert!(!assembler.is_complete());
+                            assert_eq!(assembler.finalize(), Err(AssembleError::Incomplete));
+                        }
+                    }
+                    if !assembler.is_complete() {
+                        assert_eq!(assembler.total_len(), None);
+                        assembler.set_total_len(case as u64).unwrap();
+                    }
+                    assert_eq!(assembler.total_len(), Some(case as u64));
+                    // Siblings are merged eagerly, so a complete input is a single subtree.
+                    assert_eq!(assembler.subtrees.len(), (case > 0) as usize);
+                    assert_eq!(assembler.finalize(), Ok(expected_hash));
+                    let mut xof = [0; 100];
+                    assembler.finalize_xof().unwrap().fill(&mut xof);
+                    assert_eq!(xof, expected_xof);
+
+                    order.reverse();
+                }
+
+                // The whole input as a single piece.
+                let mut assembler = SubtreeAssembler::new(mode);
+                if case > 0 {
+                    assembler.add_bytes(0, input).unwrap();
+                }
+                assembler.set_total_len(case as u64).unwrap();
+                assert_eq!(assembler.finalize(), Ok(expected_hash));
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_subtree_assembler_errors() {
+        let input = [0xab; 5 * CHUNK_LEN];
+        let chunk_len = CHUNK_LEN as u64;
+        let mut assembler = SubtreeAssembler::new(Mode::Hash);
+        // Misaligned pieces, and pieces that are too long for their offset.
+        assert_eq!(
+            assembler.add_bytes(1, &input[..10]),
+            Err(AssembleError::Misaligned { offset: 1, len: 10 }),
+        );
+        assert_eq!(
+            assembler.add_bytes(chunk_len, &[]),
+            Err(AssembleError::Misaligned {
+                offset: chunk_len,
+                len: 0
+            }),
+        );
+        assert_eq!(
+            assembler.add_bytes(chunk_len, &input[..2 * CHUNK_LEN]),
+            Err(AssembleError::Misaligned {
+                offset: chunk_len,
+                len: 2 * chunk_len
+            }),
+        );
+        // Overlapping pieces.
+        assembler.add_bytes(0, &input[..2 * CHUNK_LEN]).unwrap();
+        assert_eq!(
+            assembler.add_bytes(chunk_len, &input[..CHUNK_LEN]),
+            Err(AssembleError::Overlap { offset: chunk_len }),
+        );
+        assert_eq!(
+            assembler.add_bytes(0, &input[..4 * CHUNK_LEN]),
+            Err(AssembleError::Overlap { offset: 0 }),
+        );
+        // A short piece sets the length, and pieces after it are errors.
+        assembler
+            .add_bytes(4 * chunk_len, &input[..CHUNK_LEN - 1])
+            .unwrap();
+        assert_eq!(assembler.total_len(), Some(5 * chunk_len - 1));
+        assert_eq!(
+            assembler.add_bytes(8 * chunk_len, &input[..1]),
+            Err(AssembleError::LengthMismatch {
+                expected: 5 * chunk_len - 1,
+                found: 8 * chunk_len + 1,
+            }),
+        );
+        assert_eq!(
+            assembler.set_total_len(5 * chunk_len),
+            Err(AssembleError::LengthMismatch {
+                expected: 5 * chunk_len - 1,
+                found: 5 * chunk_len,
+            }),
+        );
+        // The length can't be set shorter than pieces that are already there.
+        let mut assembler = SubtreeAssembler::new(Mode::Hash);
+        assembler
+            .add_bytes(2 * chunk_len, &input[..CHUNK_LEN])
+            .unwrap();
+        assert_eq!(
+            assembler.set_total_len(2 * chunk_len),
+            Err(AssembleError::LengthMismatch {
+                expected: 3 * chunk_len,
+                found: 2 * chunk_len,
+            }),
+        );
+        // Pieces near the end of the u64 range don't overflow when looking for their siblings.
+        let mut assembler = SubtreeAssembler::new(Mode::Hash);
+        let cv = [0; 32];
+        assembler.add_chaining_value(1 << 63, 1 << 62, &cv).unwrap();
+        assembler.set_total_len(u64::MAX).unwrap();
+        assert!(!assembler.is_complete());
+        let last_offset = (1 << 63) + (1 << 62);
+        assembler
+            .add_chaining_value(last_offset, u64::MAX - last_offset, &cv)
+            .unwrap();
+        assembler.add_chaining_value(0, 1 << 63, &cv).unwrap();
+        assert!(assembler.is_complete());
+        assembler.finalize().unwrap();
+        // A chaining value can't be the root.
+        let mut assembler = SubtreeAssembler::new(Mode::Hash);
+        let cv = Hasher::new()
+            .update(&input[..2 * CHUNK_LEN])
+            .finalize_non_root();
+        assembler.add_chaining_value(0, 2 * chunk_len, &cv).unwrap();
+        assembler.set_total_len(2 * chunk_len).unwrap();
+        assert!(assembler.is_complete());
+        assert_eq!(assembler.finalize(), Err(AssembleError::RootChainingValue));
     }
 }
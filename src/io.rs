@@ -1,12 +1,17 @@
 //! Helper functions for efficient IO.
 
-#[cfg(feature = "mmap")]
+use crate::{CHUNK_LEN, CVBytes, CVWords, Hasher, hazmat::HasherExt, join::Join};
+use std::cmp;
 use std::fs::File;
 use std::io;
 
 #[cfg(feature = "mmap")]
 const MINIMUM_MMAP_SIZE: u64 = 16 * 1024; // 16 KiB
 
+// The largest subtree that update_file_with_join reads into a single buffer. Each leaf is read by
+// one call to the Join implementation, so this is also the unit of multithreading.
+const POSITIONAL_READ_LEN: u64 = 1 << 20; // 1 MiB
+
 pub(crate) fn copy_wide(mut reader: impl io::Read, hasher: &mut crate::Hasher) -> io::Result<u64> {
     let mut buffer = [0; 65536];
     let mut total = 0;
@@ -24,6 +29,208 @@ pub(crate) fn copy_wide(mut reader: impl io::Read, hasher: &mut crate::Hasher) -
     }
 }
 
+// The async versions of copy_wide use the same buffer size, but on the heap, so that it doesn't
+// bloat the size of every future that awaits them.
+#[cfg(feature = "tokio")]
+pub(crate) async fn copy_wide_tokio(
+    mut reader: impl tokio::io::AsyncRead + Unpin,
+    hasher: &mut crate::Hasher,
+) -> io::Result<u64> {
+    use std::pin::Pin;
+    let mut buffer = vec![0; 65536];
+    let mut total = 0;
+    loop {
+        let mut read_buf = tokio::io::ReadBuf::new(&mut buffer);
+        let result =
+            std::future::poll_fn(|cx| Pin::new(&mut reader).poll_read(cx, &mut read_buf)).await;
+        match result {
+            Ok(()) if read_buf.filled().is_empty() => return Ok(total),
+            Ok(()) => {
+                let filled = read_buf.filled();
+                hasher.update(filled);
+                total += filled.len() as u64;
+            }
+            // see test_update_reader_interrupted
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(feature = "futures-io")]
+pub(crate) async fn copy_wide_futures(
+    mut reader: impl futures_io::AsyncRead + Unpin,
+    hasher: &mut crate::Hasher,
+) -> io::Result<u64> {
+    use std::pin::Pin;
+    let mut buffer = vec![0; 65536];
+    let mut total = 0;
+    loop {
+        match std::future::poll_fn(|cx| Pin::new(&mut reader).poll_read(cx, &mut buffer)).await {
+            Ok(0) => return Ok(total),
+            Ok(n) => {
+                hasher.update(&buffer[..n]);
+                total += n as u64;
+            }
+            // see test_update_reader_interrupted
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    // Unlike pread, seek_read moves the file cursor, but we never use the cursor here.
+    while !buf.is_empty() {
+        match std::os::windows::fs::FileExt::seek_read(file, buf, offset) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+// Hash a subtree of the file with positional reads. The subtree is a power-of-2 number of chunks,
+// and `tree_offset` is its offset in the tree, which might be different from its offset in the
+// file. Subtrees longer than POSITIONAL_READ_LEN are split in half with the Join implementation.
+#[cfg(any(unix, windows))]
+fn subtree_cv_at<J: Join>(
+    file: &File,
+    file_offset: u64,
+    tree_offset: u64,
+    len: u64,
+    key: &CVWords,
+    flags: u8,
+) -> io::Result<CVBytes> {
+    debug_assert!(len.is_power_of_two() && len >= CHUNK_LEN as u64);
+    if len <= POSITIONAL_READ_LEN {
+        let mut buf = vec![0; len as usize];
+        read_exact_at(file, &mut buf, file_offset)?;
+        let mut hasher = Hasher::new_internal(key, flags);
+        hasher.set_input_offset(tree_offset).update(&buf);
+        return Ok(hasher.finalize_non_root());
+    }
+    let half = len / 2;
+    let (left_cv, right_cv) = J::join(
+        || subtree_cv_at::<J>(file, file_offset, tree_offset, half, key, flags),
+        || {
+            subtree_cv_at::<J>(
+                file,
+                file_offset + half,
+                tree_offset + half,
+                half,
+                key,
+                flags,
+            )
+        },
+    );
+    let output =
+        crate::parent_node_output(&left_cv?, &right_cv?, key, flags, crate::Platform::detect());
+    Ok(output.chaining_value())
+}
+
+// As copy_wide, but reading a regular file with positional reads, and hashing subtrees in parallel
+// using the Join implementation. The file cursor is ignored. The subtree loop here mirrors
+// Hasher::update_with_join.
+pub(crate) fn update_file_with_join<J: Join>(hasher: &mut Hasher, file: &File) -> io::Result<()> {
+    let metadata = file.metadata()?;
+    // Positional reads don't make sense for pipes and devices, and some platforms don't have them
+    // at all. Fall back to ordinary reads in those cases, and also for files that fit in a single
+    // read, where there's no parallelism to be had. That includes virtual files like /proc/version,
+    // which report a length of zero.
+    if !metadata.is_file() || metadata.len() <= POSITIONAL_READ_LEN || cfg!(not(any(unix, windows)))
+    {
+        copy_wide(file, hasher)?;
+        return Ok(());
+    }
+    #[cfg(any(unix, windows))]
+    {
+        let file_len = metadata.len();
+        let input_offset = hasher.initial_chunk_counter * CHUNK_LEN as u64;
+        if let Some(max) = crate::hazmat::max_subtree_len(input_offset) {
+            let remaining = max - hasher.count();
+            assert!(
+                file_len <= remaining,
+                "the subtree starting at {input_offset} contains at most {max} bytes (found {file_len})",
+            );
+        }
+        let key = hasher.key;
+        let flags = hasher.chunk_state.flags;
+        let mut position = 0;
+        let mut chunk_buf = [0; CHUNK_LEN];
+        // If we have some partial chunk bytes in the hasher, finish that chunk first.
+        if hasher.chunk_state.count() > 0 {
+            let want = (CHUNK_LEN - hasher.chunk_state.count()) as u64;
+            let take = cmp::min(want, file_len) as usize;
+            read_exact_at(file, &mut chunk_buf[..take], 0)?;
+            hasher.update(&chunk_buf[..take]);
+            position = take as u64;
+            // update() leaves a full chunk in the chunk state, because it doesn't know whether
+            // more input is coming. We know there is, so finalize it like update_with_join does.
+            if position < file_len {
+                let chunk_state = &hasher.chunk_state;
+                debug_assert_eq!(chunk_state.count(), CHUNK_LEN);
+                let chunk_cv = chunk_state.output().chaining_value();
+                let next_chunk_state = crate::ChunkState::new(
+                    &key,
+                    chunk_state.chunk_counter + 1,
+                    flags,
+                    chunk_state.platform,
+                );
+                hasher.push_cv(&chunk_cv, hasher.chunk_state.chunk_counter);
+                hasher.chunk_state = next_chunk_state;
+            }
+        }
+        // As in update_with_join, hash the largest whole subtree we can, as long as there's more
+        // than one chunk left. The last chunk goes into the chunk state.
+        while file_len - position > CHUNK_LEN as u64 {
+            debug_assert_eq!(hasher.chunk_state.count(), 0, "no partial chunk data");
+            let mut subtree_len = ((file_len - position) / 2 + 1).next_power_of_two();
+            let chunk_counter = hasher.chunk_state.chunk_counter;
+            let count_so_far = chunk_counter * CHUNK_LEN as u64;
+            while (subtree_len - 1) & count_so_far != 0 {
+                subtree_len /= 2;
+            }
+            let subtree_chunks = subtree_len / CHUNK_LEN as u64;
+            if subtree_chunks == 1 {
+                let cv =
+                    subtree_cv_at::<J>(file, position, count_so_far, subtree_len, &key, flags)?;
+                hasher.push_cv(&cv, chunk_counter);
+            } else {
+                // Push two CVs, so that the lazily merged CV stack doesn't merge the root.
+                let half = subtree_len / 2;
+                let (left_cv, right_cv) = J::join(
+                    || subtree_cv_at::<J>(file, position, count_so_far, half, &key, flags),
+                    || {
+                        let (file_offset, tree_offset) = (position + half, count_so_far + half);
+                        subtree_cv_at::<J>(file, file_offset, tree_offset, half, &key, flags)
+                    },
+                );
+                hasher.push_cv(&left_cv?, chunk_counter);
+                hasher.push_cv(&right_cv?, chunk_counter + subtree_chunks / 2);
+            }
+            hasher.chunk_state.chunk_counter += subtree_chunks;
+            position += subtree_len;
+        }
+        // What remains is 1 chunk or less.
+        let rest = (file_len - position) as usize;
+        read_exact_at(file, &mut chunk_buf[..rest], position)?;
+        hasher.update(&chunk_buf[..rest]);
+    }
+    Ok(())
+}
+
 // Try to `mmap` a file, unless it's short enough that ordinary reads are faster, currently 16 KiB.
 // Return `Ok(None)` if mapping fails or if we don't attempt it. Only return `Err` for unexpected
 // failures that could leave the `File` in a bad state.
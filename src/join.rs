@@ -1,20 +1,58 @@
-//! The multi-threading abstractions used by `Hasher::update_with_join`.
+//! The multi-threading abstractions used by [`Hasher::update_with_join`].
 //!
-//! Different implementations of the `Join` trait determine whether
-//! `Hasher::update_with_join` performs multi-threading on sufficiently large
-//! inputs. The `SerialJoin` implementation is single-threaded, and the
-//! `RayonJoin` implementation (gated by the `rayon` feature) is multi-threaded.
-//! Interfaces other than `Hasher::update_with_join`, like [`hash`](crate::hash)
-//! and [`Hasher::update`](crate::Hasher::update), always use `SerialJoin`
-//! internally.
+//! Different implementations of the [`Join`] trait determine whether
+//! [`Hasher::update_with_join`] performs multi-threading on sufficiently large
+//! inputs. The [`SerialJoin`] implementation is single-threaded, the
+//! [`ScopedThreadJoin`] implementation (gated by the `std` feature) uses
+//! short-lived [`std::thread::scope`] threads, and the `RayonJoin`
+//! implementation (gated by the `rayon` feature) uses the global Rayon thread
+//! pool. Interfaces other than [`Hasher::update_with_join`], like
+//! [`hash`](crate::hash) and [`Hasher::update`], always use `SerialJoin`
+//! internally. [`OutputReader::fill_with_join`] uses the same trait to fill
+//! long extended outputs in parallel.
 //!
-//! The `Join` trait is an almost exact copy of the [`rayon::join`] API, and
-//! `RayonJoin` is the only non-trivial implementation. Previously this trait
-//! was public, but currently it's been re-privatized, as it's both 1) of no
-//! value to most callers and 2) a pretty big implementation detail to commit
-//! to.
+//! The `Join` trait is an almost exact copy of the [`rayon::join`] API. If your
+//! application already has its own thread pool, you can implement `Join` for
+//! it and pass it to [`Hasher::update_with_join`], without depending on Rayon.
+//! Implementations may run `oper_a` and `oper_b` in either order, on any
+//! thread, or in parallel, but they must run each exactly once and return both
+//! results. The output of the hash doesn't depend on the implementation.
+//!
+//! # Example
+//!
+//! ```
+//! use blake3::join::Join;
+//!
+//! // A Join implementation that delegates to some other thread pool. Here it's
+//! // just a serial loop, but it could be anything with a fork-join API.
+//! enum MyPoolJoin {}
+//!
+//! impl Join for MyPoolJoin {
+//!     fn join<A, B, RA, RB>(oper_a: A, oper_b: B) -> (RA, RB)
+//!     where
+//!         A: FnOnce() -> RA + Send,
+//!         B: FnOnce() -> RB + Send,
+//!         RA: Send,
+//!         RB: Send,
+//!     {
+//!         let b = oper_b();
+//!         (oper_a(), b)
+//!     }
+//! }
+//!
+//! let input = vec![0xab; 1_000_000];
+//! let mut hasher = blake3::Hasher::new();
+//! hasher.update_with_join::<MyPoolJoin>(&input);
+//! assert_eq!(hasher.finalize(), blake3::hash(&input));
+//! ```
 //!
 //! [`rayon::join`]: https://docs.rs/rayon/1.3.0/rayon/fn.join.html
+//! [`Hasher::update_with_join`]: crate::Hasher::update_with_join
+//! [`Hasher::update`]: crate::Hasher::update
+//! [`OutputReader::fill_with_join`]: crate::OutputReader::fill_with_join
+
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 /// The trait that abstracts over single-threaded and multi-threaded recursion.
 ///
@@ -49,6 +87,78 @@ impl Join for SerialJoin {
     }
 }
 
+/// A dependency-free implementation of `Join` based on [`std::thread::scope`].
+/// The right side is executed on a new scoped thread, and the left side is
+/// executed on the calling thread, in parallel. This implementation is gated by
+/// the `std` feature, which is on by default.
+///
+/// Spawning a thread is much more expensive than submitting a task to a thread
+/// pool, so this implementation is only worthwhile for large inputs, on the
+/// order of megabytes. To avoid oversubscribing the machine, the number of
+/// helper threads that can be running at once, across all callers in the
+/// process, is limited to [`std::thread::available_parallelism`] minus one.
+/// When that limit is reached, `join` runs both sides serially on the calling
+/// thread instead.
+///
+/// See the [`join` module docs](index.html) for more details.
+#[cfg(feature = "std")]
+pub enum ScopedThreadJoin {}
+
+#[cfg(feature = "std")]
+static SCOPED_THREADS_RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+// Releases a helper thread slot when it's dropped, even if the helper thread panics.
+#[cfg(feature = "std")]
+struct ScopedThreadSlot;
+
+#[cfg(feature = "std")]
+impl ScopedThreadSlot {
+    fn try_acquire() -> Option<Self> {
+        static MAX_THREADS: std::sync::OnceLock<usize> = std::sync::OnceLock::new();
+        let max = *MAX_THREADS
+            .get_or_init(|| std::thread::available_parallelism().map_or(0, |n| n.get() - 1));
+        SCOPED_THREADS_RUNNING
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |running| {
+                (running < max).then_some(running + 1)
+            })
+            .ok()
+            .map(|_| ScopedThreadSlot)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for ScopedThreadSlot {
+    fn drop(&mut self) {
+        SCOPED_THREADS_RUNNING.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(feature = "std")]
+impl Join for ScopedThreadJoin {
+    fn join<A, B, RA, RB>(oper_a: A, oper_b: B) -> (RA, RB)
+    where
+        A: FnOnce() -> RA + Send,
+        B: FnOnce() -> RB + Send,
+        RA: Send,
+        RB: Send,
+    {
+        let Some(slot) = ScopedThreadSlot::try_acquire() else {
+            return (oper_a(), oper_b());
+        };
+        std::thread::scope(|scope| {
+            let handle = scope.spawn(move || {
+                let _slot = slot;
+                oper_b()
+            });
+            let result_a = oper_a();
+            match handle.join() {
+                Ok(result_b) => (result_a, result_b),
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        })
+    }
+}
+
 /// The Rayon-based implementation of `Join`. The left and right sides are
 /// executed on the Rayon thread pool, potentially in parallel. This
 /// implementation is gated by the `rayon` feature, which is off by default.
@@ -82,6 +192,29 @@ mod test {
         assert_eq!((2, 4), SerialJoin::join(oper_a, oper_b));
     }
 
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_scoped_thread_join() {
+        let oper_a = || 1 + 1;
+        let oper_b = || 2 + 2;
+        assert_eq!((2, 4), ScopedThreadJoin::join(oper_a, oper_b));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_scoped_thread_join_panic() {
+        let result = std::panic::catch_unwind(|| {
+            ScopedThreadJoin::join(|| 1, || -> i32 { panic!("oper_b") })
+        });
+        assert!(result.is_err());
+        // Whether or not oper_b ran on a helper thread, its slot was released. (Other tests might
+        // be running concurrently, so we can't assert that the count is zero.)
+        let result = std::panic::catch_unwind(|| {
+            ScopedThreadJoin::join(|| -> i32 { panic!("oper_a") }, || 2)
+        });
+        assert!(result.is_err());
+    }
+
     #[test]
     #[cfg(feature = "rayon")]
     fn test_rayon_join() {
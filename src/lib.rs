@@ -34,8 +34,9 @@
 //!
 //! The `std` feature (the only feature enabled by default) enables the
 //! [`Write`] implementation and the [`update_reader`](Hasher::update_reader)
-//! method for [`Hasher`], and also the [`Read`] and [`Seek`] implementations
-//! for [`OutputReader`].
+//! method for [`Hasher`], the [`Read`] and [`Seek`] implementations for
+//! [`OutputReader`], the [`verified`] module for verified streaming, and the
+//! [`tree_digest`] module for hashing directory trees.
 //!
 //! The `rayon` feature (disabled by default, but enabled for [docs.rs]) adds
 //! the [`update_rayon`](Hasher::update_rayon) and (in combination with `mmap`
@@ -48,6 +49,15 @@
 //! [`update_mmap_rayon`](Hasher::update_mmap_rayon) helper methods for
 //! memory-mapped IO.
 //!
+//! The `tokio` and `futures-io` features (disabled by default, but enabled for
+//! [docs.rs]) add the async counterparts of the `std` integrations, for
+//! [Tokio](https://docs.rs/tokio) and for the
+//! [`futures-io`](https://docs.rs/futures-io) traits respectively: the
+//! [`update_async_reader`](Hasher::update_async_reader) and
+//! [`update_futures_reader`](Hasher::update_futures_reader) methods, `AsyncWrite`
+//! for [`Hasher`], and `AsyncRead` and `AsyncSeek` for [`OutputReader`]. Both
+//! imply `std`.
+//!
 //! The `zeroize` feature (disabled by default, but enabled for [docs.rs])
 //! implements
 //! [`Zeroize`](https://docs.rs/zeroize/latest/zeroize/trait.Zeroize.html) for
@@ -56,7 +66,8 @@
 //! The `serde` feature (disabled by default, but enabled for [docs.rs]) implements
 //! [`serde::Serialize`](https://docs.rs/serde/latest/serde/trait.Serialize.html) and
 //! [`serde::Deserialize`](https://docs.rs/serde/latest/serde/trait.Deserialize.html)
-//! for [`Hash`](struct@Hash).
+//! for [`Hash`](struct@Hash) and [`HasherState`]. It also adds the [`serde_hex`]
+//! module, for representing hashes as hex strings in human-readable formats.
 //!
 //! The NEON implementation is enabled by default for AArch64 but requires the
 //! `neon` feature for other ARM targets. Not all ARMv7 CPUs support NEON, and
@@ -68,7 +79,8 @@
 //! support is assumed. This may become the default in the future.
 //!
 //! The `traits-preview` feature enables implementations of traits from the
-//! RustCrypto [`digest`] crate, and re-exports that crate as `traits::digest`.
+//! RustCrypto [`digest`] and [`cipher`] crates, and re-exports those crates as
+//! `traits::digest` and `traits::cipher`.
 //! However, the traits aren't stable, and they're expected to change in
 //! incompatible ways before that crate reaches 1.0. For that reason, this crate
 //! makes no SemVer guarantees for this feature, and callers who use it should
@@ -83,6 +95,7 @@
 //! [`Write`]: https://doc.rust-lang.org/std/io/trait.Write.html
 //! [`Seek`]: https://doc.rust-lang.org/std/io/trait.Seek.html
 //! [`digest`]: https://crates.io/crates/digest
+//! [`cipher`]: https://crates.io/crates/cipher
 //! [`signature`]: https://crates.io/crates/signature
 
 #![cfg_attr(not(feature = "std"), no_std)]
@@ -94,6 +107,8 @@ mod test;
 #[deprecated(since = "1.8.0", note = "use the hazmat module instead")]
 pub mod guts;
 
+pub mod const_fn;
+
 pub mod hazmat;
 
 /// Undocumented and unstable, for benchmarks only.
@@ -103,8 +118,7 @@ pub mod platform;
 // Platform-specific implementations of the compression function. These
 // BLAKE3-specific cfg flags are set in build.rs.
 #[cfg(blake3_avx2_rust)]
-#[path = "rust_avx2.rs"]
-mod avx2;
+use rust_avx2 as avx2;
 #[cfg(blake3_avx2_ffi)]
 #[path = "ffi_avx2.rs"]
 mod avx2;
@@ -116,18 +130,29 @@ mod avx512;
 mod neon;
 mod portable;
 #[cfg(blake3_sse2_rust)]
-#[path = "rust_sse2.rs"]
-mod sse2;
+use rust_sse2 as sse2;
 #[cfg(blake3_sse2_ffi)]
 #[path = "ffi_sse2.rs"]
 mod sse2;
 #[cfg(blake3_sse41_rust)]
-#[path = "rust_sse41.rs"]
-mod sse41;
+use rust_sse41 as sse41;
 #[cfg(blake3_sse41_ffi)]
 #[path = "ffi_sse41.rs"]
 mod sse41;
 
+// The Rust intrinsics implementations don't need a C compiler, so build them on every x86 target.
+// When the assembly implementations above provide hash_many, these still provide xof_many and
+// compress_many, which the assembly doesn't have.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[cfg_attr(not(blake3_avx2_rust), allow(dead_code))]
+mod rust_avx2;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[cfg_attr(not(blake3_sse2_rust), allow(dead_code))]
+mod rust_sse2;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[cfg_attr(not(blake3_sse41_rust), allow(dead_code))]
+mod rust_sse41;
+
 #[cfg(blake3_wasm32_simd)]
 #[path = "wasm32_simd.rs"]
 mod wasm32_simd;
@@ -135,9 +160,31 @@ mod wasm32_simd;
 #[cfg(feature = "traits-preview")]
 pub mod traits;
 
+#[cfg(feature = "serde")]
+pub mod serde_hex;
+
+#[cfg(feature = "std")]
+pub mod hash_tree;
+
+#[cfg(feature = "std")]
+pub mod tree_digest;
+
+#[cfg(feature = "std")]
+pub mod verified;
+
+mod encoding;
 #[cfg(feature = "std")]
 mod io;
-mod join;
+pub mod join;
+pub mod multihash;
+mod sized_digest;
+mod state;
+pub mod transcript;
+
+pub use encoding::DecodeError;
+pub use sized_digest::Digest;
+pub use state::{HasherState, StateError};
+pub use transcript::Transcript;
 
 use arrayref::{array_mut_ref, array_ref};
 use arrayvec::{ArrayString, ArrayVec};
@@ -203,12 +250,12 @@ const DERIVE_KEY_CONTEXT: u8 = 1 << 5;
 const DERIVE_KEY_MATERIAL: u8 = 1 << 6;
 
 #[inline]
-fn counter_low(counter: u64) -> u32 {
+const fn counter_low(counter: u64) -> u32 {
     counter as u32
 }
 
 #[inline]
-fn counter_high(counter: u64) -> u32 {
+const fn counter_high(counter: u64) -> u32 {
     (counter >> 32) as u32
 }
 
@@ -276,6 +323,9 @@ impl Hash {
     /// equality checking, so if you need to compare hashes, prefer the `Hash`
     /// type.
     ///
+    /// For other encodings, see [`to_base32`](Self::to_base32),
+    /// [`to_base64url`](Self::to_base64url), and [`to_multibase`](Self::to_multibase).
+    ///
     /// [`ArrayString`]: https://docs.rs/arrayvec/0.5.1/arrayvec/struct.ArrayString.html
     pub fn to_hex(&self) -> ArrayString<{ 2 * OUT_LEN }> {
         let mut s = ArrayString::new();
@@ -295,25 +345,10 @@ impl Hash {
     /// error.
     ///
     /// Note that `Hash` also implements `FromStr`, so `Hash::from_hex("...")`
-    /// is equivalent to `"...".parse()`.
+    /// is equivalent to `"...".parse()`. To decode a hash at compile time, see
+    /// the [`hash_from_hex!`] macro and [`const_fn::from_hex`].
     pub fn from_hex(hex: impl AsRef<[u8]>) -> Result<Self, HexError> {
-        fn hex_val(byte: u8) -> Result<u8, HexError> {
-            match byte {
-                b'A'..=b'F' => Ok(byte - b'A' + 10),
-                b'a'..=b'f' => Ok(byte - b'a' + 10),
-                b'0'..=b'9' => Ok(byte - b'0'),
-                _ => Err(HexError(HexErrorInner::InvalidByte(byte))),
-            }
-        }
-        let hex_bytes: &[u8] = hex.as_ref();
-        if hex_bytes.len() != OUT_LEN * 2 {
-            return Err(HexError(HexErrorInner::InvalidLen(hex_bytes.len())));
-        }
-        let mut hash_bytes: [u8; OUT_LEN] = [0; OUT_LEN];
-        for i in 0..OUT_LEN {
-            hash_bytes[i] = 16 * hex_val(hex_bytes[2 * i])? + hex_val(hex_bytes[2 * i + 1])?;
-        }
-        Ok(Hash::from(hash_bytes))
+        const_fn::from_hex(hex.as_ref())
     }
 }
 
@@ -396,7 +431,7 @@ impl fmt::Debug for Hash {
     }
 }
 
-/// The error type for [`Hash::from_hex`].
+/// The error type for [`Hash::from_hex`] and [`Digest::from_hex`].
 ///
 /// The `.to_string()` representation of this error currently distinguishes between bad length
 /// errors and bad character errors. This is to help with logging and debugging, but it isn't a
@@ -407,7 +442,7 @@ pub struct HexError(HexErrorInner);
 #[derive(Clone, Debug)]
 enum HexErrorInner {
     InvalidByte(u8),
-    InvalidLen(usize),
+    InvalidLen { expected: usize, received: usize },
 }
 
 impl fmt::Display for HexError {
@@ -420,8 +455,8 @@ impl fmt::Display for HexError {
                     write!(f, "invalid hex character: 0x{:x}", byte)
                 }
             }
-            HexErrorInner::InvalidLen(len) => {
-                write!(f, "expected 64 hex bytes, received {}", len)
+            HexErrorInner::InvalidLen { expected, received } => {
+                write!(f, "expected {} hex bytes, received {}", expected, received)
             }
         }
     }
@@ -473,8 +508,36 @@ impl Output {
             self.flags | ROOT,
         )
     }
+
+    // Fill whole output blocks starting at `counter` (not self.counter). Each block depends only
+    // on its counter, so we can split the output in half and fill the halves independently.
+    fn root_output_blocks_with_join<J: join::Join>(&self, counter: u64, out: &mut [u8]) {
+        debug_assert_eq!(0, out.len() % BLOCK_LEN, "whole blocks only");
+        if out.len() <= XOF_JOIN_LEAF_LEN {
+            self.platform.xof_many(
+                &self.input_chaining_value,
+                &self.block,
+                self.block_len,
+                counter,
+                self.flags | ROOT,
+                out,
+            );
+            return;
+        }
+        let left_len = out.len() / BLOCK_LEN / 2 * BLOCK_LEN;
+        let right_counter = counter + (left_len / BLOCK_LEN) as u64;
+        let (left, right) = out.split_at_mut(left_len);
+        J::join(
+            || self.root_output_blocks_with_join::<J>(counter, left),
+            || self.root_output_blocks_with_join::<J>(right_counter, right),
+        );
+    }
 }
 
+// The smallest piece of extended output that OutputReader::fill_with_join() hands to a single
+// xof_many() call. Below this, splitting the work costs more than it saves.
+const XOF_JOIN_LEAF_LEN: usize = 256 * BLOCK_LEN;
+
 #[cfg(feature = "zeroize")]
 impl Zeroize for Output {
     fn zeroize(&mut self) {
@@ -995,18 +1058,344 @@ pub fn keyed_hash(key: &[u8; KEY_LEN], input: &[u8]) -> Hash {
 /// # assert_eq!(key1, key2);
 /// ```
 ///
-/// For output sizes other than 32 bytes, see [`Hasher::finalize_xof`], and [`OutputReader`].
+/// For output sizes other than 32 bytes, see [`derive_key_into`] and [`derive_key_xof`]. For
+/// context strings that aren't UTF-8, see [`derive_key_raw`], [`derive_key_into_raw`], and
+/// [`derive_key_xof_raw`].
 ///
 /// This function is always single-threaded. For multithreading support, see
 /// [`Hasher::update_rayon`](struct.Hasher.html#method.update_rayon).
 ///
 /// [Argon2]: https://en.wikipedia.org/wiki/Argon2
 pub fn derive_key(context: &str, key_material: &[u8]) -> [u8; OUT_LEN] {
-    let context_key = hazmat::hash_derive_key_context(context);
+    derive_key_raw(context.as_bytes(), key_material)
+}
+
+/// As [`derive_key`], but with a context string of raw bytes.
+///
+/// This is for contexts that come from somewhere other than Rust source code, like a wire format,
+/// and might not be UTF-8. For a context that is UTF-8, this gives the same result as
+/// `derive_key`. It's equivalent to `blake3_hasher_init_derive_key_raw` in the C API. The same
+/// security requirement applies: **the context string should be hardcoded, globally unique, and
+/// application-specific.**
+///
+/// ```
+/// # const CONTEXT: &str = "example.com 2019-12-25 16:18:03 session tokens v1";
+/// let key = blake3::derive_key_raw(CONTEXT.as_bytes(), b"key material");
+/// assert_eq!(key, blake3::derive_key(CONTEXT, b"key material"));
+/// ```
+pub fn derive_key_raw(context: &[u8], key_material: &[u8]) -> [u8; OUT_LEN] {
+    derive_key_output(context, key_material).root_hash().0
+}
+
+/// As [`derive_key`], but returning an [`OutputReader`] for a derived key of any length.
+///
+/// The first 32 bytes of output are the same as the output of `derive_key`. Shorter outputs are
+/// prefixes of longer ones, so deriving several keys of different lengths from the same context
+/// string and key material gives related keys. Use a different context string for each key
+/// instead.
+///
+/// ```
+/// # const CONTEXT: &str = "example.com 2019-12-25 16:18:03 session tokens v1";
+/// let mut key = [0; 64];
+/// blake3::derive_key_xof(CONTEXT, b"key material").fill(&mut key);
+/// assert_eq!(key[..32], blake3::derive_key(CONTEXT, b"key material"));
+/// ```
+pub fn derive_key_xof(context: &str, key_material: &[u8]) -> OutputReader {
+    derive_key_xof_raw(context.as_bytes(), key_material)
+}
+
+/// As [`derive_key_xof`], but with a context string of raw bytes. See [`derive_key_raw`].
+pub fn derive_key_xof_raw(context: &[u8], key_material: &[u8]) -> OutputReader {
+    OutputReader::new(derive_key_output(context, key_material))
+}
+
+/// As [`derive_key`], but filling an output buffer of any length. This is equivalent to
+/// `derive_key_xof(context, key_material).fill(out)`. See [`derive_key_xof`].
+pub fn derive_key_into(context: &str, key_material: &[u8], out: &mut [u8]) {
+    derive_key_into_raw(context.as_bytes(), key_material, out);
+}
+
+/// As [`derive_key_into`], but with a context string of raw bytes. See [`derive_key_raw`].
+pub fn derive_key_into_raw(context: &[u8], key_material: &[u8], out: &mut [u8]) {
+    derive_key_xof_raw(context, key_material).fill(out);
+}
+
+fn derive_key_output(context: &[u8], key_material: &[u8]) -> Output {
+    let context_key = hazmat::hash_derive_key_context_raw(context);
     let context_key_words = platform::words_from_le_bytes_32(&context_key);
     hash_all_at_once::<join::SerialJoin>(key_material, &context_key_words, DERIVE_KEY_MATERIAL)
-        .root_hash()
-        .0
+}
+
+/// Hash many independent inputs at once, setting `out[i]` to `hash(inputs[i])`.
+///
+/// BLAKE3 normally gets its SIMD parallelism from the chunks of a single long input, so hashing
+/// lots of short inputs one at a time leaves most of the SIMD width unused. This function instead
+/// hashes different inputs in different SIMD lanes. Inputs up to 4 KiB are grouped by the shape
+/// of their tree, which depends on their length rounded up to a multiple of 64 bytes, and each
+/// group is hashed in parallel, one input per lane. Inputs longer than 4 KiB are hashed one at a
+/// time, as with [`hash`], since they get SIMD parallelism from their own chunks.
+///
+/// The outputs are the same as calling [`hash`] on each input, regardless of the order or lengths
+/// of the inputs.
+///
+/// # Panics
+///
+/// Panics if `inputs` and `out` have different lengths.
+///
+/// # Example
+///
+/// ```
+/// let inputs: [&[u8]; 3] = [b"foo", &[0; 64], &[1; 200]];
+/// let mut hashes = [blake3::Hash::from_bytes([0; 32]); 3];
+/// blake3::hash_batch(&inputs, &mut hashes);
+/// for (input, hash) in inputs.iter().zip(&hashes) {
+///     assert_eq!(*hash, blake3::hash(input));
+/// }
+/// ```
+pub fn hash_batch(inputs: &[&[u8]], out: &mut [Hash]) {
+    assert_eq!(inputs.len(), out.len(), "inputs and outputs must match");
+    hash_batch_inner(inputs, IV, 0, &mut |i, cv| out[i] = Hash(cv));
+}
+
+/// The batch version of [`keyed_hash`], setting `out[i]` to `keyed_hash(key, inputs[i])`.
+///
+/// See [`hash_batch`] for which inputs benefit from batching.
+///
+/// # Panics
+///
+/// Panics if `inputs` and `out` have different lengths.
+pub fn keyed_hash_batch(key: &[u8; KEY_LEN], inputs: &[&[u8]], out: &mut [Hash]) {
+    assert_eq!(inputs.len(), out.len(), "inputs and outputs must match");
+    let key_words = platform::words_from_le_bytes_32(key);
+    hash_batch_inner(inputs, &key_words, KEYED_HASH, &mut |i, cv| {
+        out[i] = Hash(cv)
+    });
+}
+
+/// The batch version of [`derive_key`], setting `out[i]` to `derive_key(context,
+/// key_materials[i])`. The context string is only hashed once.
+///
+/// See [`hash_batch`] for which inputs benefit from batching.
+///
+/// # Panics
+///
+/// Panics if `key_materials` and `out` have different lengths.
+pub fn derive_key_batch(context: &str, key_materials: &[&[u8]], out: &mut [[u8; OUT_LEN]]) {
+    assert_eq!(
+        key_materials.len(),
+        out.len(),
+        "inputs and outputs must match"
+    );
+    let context_key = hazmat::hash_derive_key_context(context);
+    let context_key_words = platform::words_from_le_bytes_32(&context_key);
+    hash_batch_inner(
+        key_materials,
+        &context_key_words,
+        DERIVE_KEY_MATERIAL,
+        &mut |i, cv| out[i] = cv,
+    );
+}
+
+// Inputs up to this many chunks long are hashed in SIMD lanes by hash_batch. Longer inputs go
+// through hash_all_at_once(), which gets its parallelism from their own chunks.
+const BATCH_MAX_CHUNKS: usize = 4;
+
+// Inputs in hash_batch are bucketed by the shape of their tree: the number of chunks, and the
+// number of full blocks in the last chunk before its final block.
+const BATCH_BUCKETS: usize = BATCH_MAX_CHUNKS * (CHUNK_LEN / BLOCK_LEN);
+
+// Compute the root hash of each input, and report it along with the input's index. Inputs of up
+// to BATCH_MAX_CHUNKS chunks are bucketed by the shape of their tree. The inputs in a bucket only
+// differ in the length of their final block, so every step of hashing them can be done for the
+// whole bucket at once, one input per SIMD lane. A bucket is hashed as soon as it fills every
+// lane, and the rest are hashed at the end, so this only makes one pass over the inputs.
+fn hash_batch_inner(
+    inputs: &[&[u8]],
+    key: &CVWords,
+    flags: u8,
+    write: &mut dyn FnMut(usize, CVBytes),
+) {
+    let batch = Batch {
+        inputs,
+        key,
+        flags,
+        platform: Platform::detect(),
+    };
+    let mut buckets: [ArrayVec<usize, MAX_SIMD_DEGREE>; BATCH_BUCKETS] =
+        core::array::from_fn(|_| ArrayVec::new());
+    for (i, input) in inputs.iter().enumerate() {
+        if input.len() > BATCH_MAX_CHUNKS * CHUNK_LEN {
+            write(
+                i,
+                hash_all_at_once::<join::SerialJoin>(input, key, flags)
+                    .root_hash()
+                    .0,
+            );
+            continue;
+        }
+        let bucket = &mut buckets[Batch::shape(input.len())];
+        bucket.push(i);
+        if bucket.is_full() {
+            batch.hash_lanes(bucket, write);
+            bucket.clear();
+        }
+    }
+    for bucket in &buckets {
+        if !bucket.is_empty() {
+            batch.hash_lanes(bucket, write);
+        }
+    }
+}
+
+// The inputs and parameters shared by every step of hash_batch_inner().
+struct Batch<'a> {
+    inputs: &'a [&'a [u8]],
+    key: &'a CVWords,
+    flags: u8,
+    platform: Platform,
+}
+
+impl Batch<'_> {
+    // The bucket index for an input of this length, from its number of chunks and the number of
+    // full blocks in its last chunk before the final block. The final block can be full, and it's
+    // empty for an empty input.
+    fn shape(len: usize) -> usize {
+        let chunks = cmp::max(1, len.div_ceil(CHUNK_LEN));
+        let last_chunk_len = len - (chunks - 1) * CHUNK_LEN;
+        let blocks = last_chunk_len.saturating_sub(1) / BLOCK_LEN;
+        (chunks - 1) * (CHUNK_LEN / BLOCK_LEN) + blocks
+    }
+
+    // Hash a bucket of inputs with the same shape, and write their root hashes.
+    fn hash_lanes(&self, indices: &[usize], write: &mut dyn FnMut(usize, CVBytes)) {
+        let shape = Self::shape(self.inputs[indices[0]].len());
+        let chunks = shape / (CHUNK_LEN / BLOCK_LEN) + 1;
+        let blocks = shape % (CHUNK_LEN / BLOCK_LEN);
+        let last_chunk = chunks - 1;
+        let lanes = indices.len();
+        // The CV of each chunk in each lane, grouped by chunk like hash_many() writes them.
+        let mut cvs = [[0; MAX_SIMD_DEGREE * OUT_LEN]; BATCH_MAX_CHUNKS];
+
+        // Every chunk but the last is full. Chunks with the same index have the same counter, so
+        // they're hashed together.
+        for (chunk, chunk_cvs) in cvs[..last_chunk].iter_mut().enumerate() {
+            self.hash_many::<CHUNK_LEN>(indices, chunk, CHUNK_END, chunk_cvs);
+        }
+
+        // hash_many() takes its inputs as fixed-size arrays, so each number of full blocks in the
+        // last chunk needs its own instantiation.
+        macro_rules! hash_many_blocks {
+            ($($blocks:literal)*) => {
+                match blocks {
+                    0 => {
+                        let key_bytes = platform::le_bytes_from_words_32(self.key);
+                        for cv in cvs[last_chunk].chunks_exact_mut(OUT_LEN) {
+                            cv.copy_from_slice(&key_bytes);
+                        }
+                    }
+                    $($blocks => self.hash_many::<{ $blocks * BLOCK_LEN }>(
+                        indices,
+                        last_chunk,
+                        0,
+                        &mut cvs[last_chunk],
+                    ),)*
+                    _ => unreachable!(),
+                }
+            };
+        }
+        hash_many_blocks!(1 2 3 4 5 6 7 8 9 10 11 12 13 14 15);
+
+        // The final blocks are where the lengths differ, so each lane gets its own block length.
+        let mut final_blocks = [[0; BLOCK_LEN]; MAX_SIMD_DEGREE];
+        let mut block_lens = [0; MAX_SIMD_DEGREE];
+        for (lane, &i) in indices.iter().enumerate() {
+            let final_block = &self.inputs[i][last_chunk * CHUNK_LEN + blocks * BLOCK_LEN..];
+            final_blocks[lane][..final_block.len()].copy_from_slice(final_block);
+            block_lens[lane] = final_block.len() as u8;
+        }
+        let final_blocks: ArrayVec<&[u8; BLOCK_LEN], MAX_SIMD_DEGREE> =
+            final_blocks[..lanes].iter().collect();
+        let mut final_flags = self.flags | CHUNK_END;
+        if blocks == 0 {
+            final_flags |= CHUNK_START;
+        }
+        if chunks == 1 {
+            final_flags |= ROOT;
+        }
+        self.platform.compress_many(
+            &mut cvs[last_chunk][..lanes * OUT_LEN],
+            &final_blocks,
+            &block_lens[..lanes],
+            last_chunk as u64,
+            final_flags,
+        );
+
+        // Merge the chunk CVs a level at a time, pairing them up from the left and carrying an odd
+        // one up to the next level. That's the same left-heavy tree as for any other input. The
+        // parents in each level are hashed together, with ROOT on the last one.
+        let mut level_len = chunks;
+        while level_len > 1 {
+            let mut parents = [[0; BLOCK_LEN]; MAX_SIMD_DEGREE * BATCH_MAX_CHUNKS / 2];
+            for pair in 0..level_len / 2 {
+                for lane in 0..lanes {
+                    let parent = &mut parents[pair * lanes + lane];
+                    parent[..OUT_LEN].copy_from_slice(&cvs[2 * pair][lane * OUT_LEN..][..OUT_LEN]);
+                    parent[OUT_LEN..]
+                        .copy_from_slice(&cvs[2 * pair + 1][lane * OUT_LEN..][..OUT_LEN]);
+                }
+            }
+            let parents: ArrayVec<&[u8; BLOCK_LEN], { MAX_SIMD_DEGREE * BATCH_MAX_CHUNKS / 2 }> =
+                parents[..level_len / 2 * lanes].iter().collect();
+            let mut out = [0; MAX_SIMD_DEGREE * BATCH_MAX_CHUNKS / 2 * OUT_LEN];
+            self.platform.hash_many(
+                &parents,
+                self.key,
+                0,
+                IncrementCounter::No,
+                self.flags | PARENT,
+                0,
+                if level_len == 2 { ROOT } else { 0 },
+                &mut out,
+            );
+            for pair in 0..level_len / 2 {
+                cvs[pair][..lanes * OUT_LEN]
+                    .copy_from_slice(&out[pair * lanes * OUT_LEN..][..lanes * OUT_LEN]);
+            }
+            if level_len % 2 == 1 {
+                cvs[level_len / 2] = cvs[level_len - 1];
+            }
+            level_len = level_len.div_ceil(2);
+        }
+
+        for (lane, &i) in indices.iter().enumerate() {
+            write(i, *array_ref!(cvs[0], lane * OUT_LEN, OUT_LEN));
+        }
+    }
+
+    // Hash N bytes of full blocks from the given chunk of each input with hash_many(), one input
+    // per lane, starting a new chunk.
+    fn hash_many<const N: usize>(
+        &self,
+        indices: &[usize],
+        chunk: usize,
+        flags_end: u8,
+        out: &mut [u8],
+    ) {
+        let mut lanes = ArrayVec::<&[u8; N], MAX_SIMD_DEGREE>::new();
+        for &i in indices {
+            lanes.push(self.inputs[i][chunk * CHUNK_LEN..][..N].try_into().unwrap());
+        }
+        self.platform.hash_many(
+            &lanes,
+            self.key,
+            chunk as u64,
+            IncrementCounter::No,
+            self.flags,
+            CHUNK_START,
+            flags_end,
+            out,
+        );
+    }
 }
 
 fn parent_node_output(
@@ -1106,7 +1495,13 @@ impl Hasher {
     ///
     /// [`derive_key`]: fn.derive_key.html
     pub fn new_derive_key(context: &str) -> Self {
-        let context_key = hazmat::hash_derive_key_context(context);
+        Self::new_derive_key_raw(context.as_bytes())
+    }
+
+    /// As [`new_derive_key`](Hasher::new_derive_key), but with a context string of raw bytes.
+    /// See [`derive_key_raw`].
+    pub fn new_derive_key_raw(context: &[u8]) -> Self {
+        let context_key = hazmat::hash_derive_key_context_raw(context);
         let context_key_words = platform::words_from_le_bytes_32(&context_key);
         Self::new_internal(&context_key_words, DERIVE_KEY_MATERIAL)
     }
@@ -1198,7 +1593,8 @@ impl Hasher {
     /// Add input bytes to the hash state. You can call this any number of times.
     ///
     /// This method is always single-threaded. For multithreading support, see
-    /// [`update_rayon`](#method.update_rayon) (enabled with the `rayon` Cargo feature).
+    /// [`update_rayon`](#method.update_rayon) (enabled with the `rayon` Cargo feature) and
+    /// [`update_with_join`](#method.update_with_join).
     ///
     /// Note that the degree of SIMD parallelism that `update` can use is limited by the size of
     /// this input buffer. See [`update_reader`](#method.update_reader).
@@ -1206,7 +1602,30 @@ impl Hasher {
         self.update_with_join::<join::SerialJoin>(input)
     }
 
-    fn update_with_join<J: join::Join>(&mut self, mut input: &[u8]) -> &mut Self {
+    /// As [`update`](Hasher::update), but using the given [`Join`](join::Join)
+    /// implementation for multithreading.
+    ///
+    /// [`update_rayon`](Hasher::update_rayon) is equivalent to
+    /// `update_with_join::<join::RayonJoin>`. Without the `rayon` feature, you can use
+    /// [`join::ScopedThreadJoin`], which only depends on the standard library, or you can
+    /// implement [`Join`](join::Join) for your own thread pool. See the [`join`] module docs.
+    ///
+    /// As with `update_rayon`, the input buffer needs to be large to get any benefit from
+    /// multithreading, and the threshold depends on the `Join` implementation.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// use blake3::join::ScopedThreadJoin;
+    ///
+    /// let input = vec![0; 10_000_000];
+    /// let mut hasher = blake3::Hasher::new();
+    /// hasher.update_with_join::<ScopedThreadJoin>(&input);
+    /// assert_eq!(hasher.finalize(), blake3::hash(&input));
+    /// # }
+    /// ```
+    pub fn update_with_join<J: join::Join>(&mut self, mut input: &[u8]) -> &mut Self {
         let input_offset = self.initial_chunk_counter * CHUNK_LEN as u64;
         if let Some(max) = hazmat::max_subtree_len(input_offset) {
             let remaining = max - self.count();
@@ -1419,6 +1838,21 @@ impl Hasher {
         OutputReader::new(self.final_output())
     }
 
+    /// Finalize the hash state and return an `N`-byte [`Digest`] of the input.
+    ///
+    /// This is equivalent to filling an `N`-byte buffer from [`finalize_xof`](Self::finalize_xof),
+    /// but the result keeps the constant-time equality and hex formatting of
+    /// [`Hash`](struct@Hash). Shorter outputs are prefixes of longer ones, so
+    /// `finalize_len::<32>()` is the same as [`finalize`](Self::finalize).
+    ///
+    /// This method is idempotent. Calling it twice will give the same result.
+    /// You can also add more input and finalize again.
+    pub fn finalize_len<const N: usize>(&self) -> Digest<N> {
+        let mut bytes = [0; N];
+        self.finalize_xof().fill(&mut bytes);
+        Digest::from_bytes(bytes)
+    }
+
     /// Return the total number of bytes hashed so far.
     ///
     /// [`hazmat::HasherExt::set_input_offset`] does not affect this value. This only counts bytes
@@ -1474,6 +1908,53 @@ impl Hasher {
         Ok(self)
     }
 
+    /// As [`update_reader`](Hasher::update_reader), but reading from a Tokio
+    /// [`AsyncRead`](https://docs.rs/tokio/latest/tokio/io/trait.AsyncRead.html) implementation.
+    ///
+    /// This uses the same large internal buffer as `update_reader`, allocated on the heap. Note
+    /// that hashing is CPU-bound, and this method doesn't yield to the executor between reads
+    /// that complete immediately. For very large inputs that are already in memory or in the page
+    /// cache, hashing in [`spawn_blocking`](https://docs.rs/tokio/latest/tokio/task/fn.spawn_blocking.html)
+    /// might still be a better fit.
+    ///
+    /// This method requires the `tokio` Cargo feature, which is disabled by default but enabled
+    /// on [docs.rs](https://docs.rs).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// async fn hash_stream(
+    ///     stream: impl tokio::io::AsyncRead + Unpin,
+    /// ) -> std::io::Result<blake3::Hash> {
+    ///     let mut hasher = blake3::Hasher::new();
+    ///     hasher.update_async_reader(stream).await?;
+    ///     Ok(hasher.finalize())
+    /// }
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub async fn update_async_reader(
+        &mut self,
+        reader: impl tokio::io::AsyncRead + Unpin,
+    ) -> std::io::Result<&mut Self> {
+        io::copy_wide_tokio(reader, self).await?;
+        Ok(self)
+    }
+
+    /// As [`update_async_reader`](Hasher::update_async_reader), but reading from a
+    /// [`futures_io::AsyncRead`](https://docs.rs/futures-io/latest/futures_io/trait.AsyncRead.html)
+    /// implementation, for runtimes other than Tokio.
+    ///
+    /// This method requires the `futures-io` Cargo feature, which is disabled by default but
+    /// enabled on [docs.rs](https://docs.rs).
+    #[cfg(feature = "futures-io")]
+    pub async fn update_futures_reader(
+        &mut self,
+        reader: impl futures_io::AsyncRead + Unpin,
+    ) -> std::io::Result<&mut Self> {
+        io::copy_wide_futures(reader, self).await?;
+        Ok(self)
+    }
+
     /// As [`update`](Hasher::update), but using Rayon-based multithreading
     /// internally.
     ///
@@ -1605,6 +2086,68 @@ impl Hasher {
         }
         Ok(self)
     }
+
+    /// As [`update_with_join`](Hasher::update_with_join), but reading the contents of a file
+    /// using positional reads (`pread` on Unix, `ReadFile` with an offset on Windows) rather than
+    /// memory mapping.
+    ///
+    /// The file is split into the same power-of-2 subtrees that [`update`](Hasher::update) uses,
+    /// and each worker reads and hashes its own subtrees into a buffer of up to 1 MiB. This gets
+    /// most of the multithreaded performance of
+    /// [`update_mmap_rayon`](Hasher::update_mmap_rayon), and it works on network filesystems and
+    /// FUSE mounts where memory mapping is slow or unsupported. It also avoids the risk of
+    /// `SIGBUS` if the file is truncated while it's being hashed. In that case this method returns
+    /// an [`UnexpectedEof`](std::io::ErrorKind::UnexpectedEof) error instead, and the state of the
+    /// `Hasher` is unspecified. If the file is extended while it's being hashed, bytes past its
+    /// original length are ignored.
+    ///
+    /// Files that aren't regular files, like pipes, are read serially with
+    /// [`update_reader`](Hasher::update_reader), as are files of 1 MiB or less and all files on
+    /// platforms other than Unix and Windows. That threshold might change at any time.
+    ///
+    /// As with [`update_mmap`](Hasher::update_mmap), this method takes a path rather than a
+    /// [`File`](std::fs::File), because it ignores the file's seek position.
+    ///
+    /// This method requires the `std` Cargo feature, which is enabled by default.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use std::io;
+    /// # fn main() -> io::Result<()> {
+    /// use blake3::join::ScopedThreadJoin;
+    ///
+    /// let mut hasher = blake3::Hasher::new();
+    /// hasher.update_file_with_join::<ScopedThreadJoin>("big_file.dat")?;
+    /// println!("{}", hasher.finalize());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn update_file_with_join<J: join::Join>(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> std::io::Result<&mut Self> {
+        let file = std::fs::File::open(path.as_ref())?;
+        io::update_file_with_join::<J>(self, &file)?;
+        Ok(self)
+    }
+
+    /// As [`update_file_with_join`](Hasher::update_file_with_join), using Rayon-based
+    /// multithreading. This is the default behavior of `b3sum --no-mmap`.
+    ///
+    /// The same performance warning applies here as for
+    /// [`update_mmap_rayon`](Hasher::update_mmap_rayon), especially for spinning disks.
+    ///
+    /// This method requires the `rayon` Cargo feature, which is disabled by default but enabled
+    /// on [docs.rs](https://docs.rs).
+    #[cfg(feature = "rayon")]
+    pub fn update_file_parallel(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> std::io::Result<&mut Self> {
+        self.update_file_with_join::<join::RayonJoin>(path)
+    }
 }
 
 // Don't derive(Debug), because the state may be secret.
@@ -1639,6 +2182,64 @@ impl std::io::Write for Hasher {
     }
 }
 
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncWrite for Hasher {
+    /// This is equivalent to [`update`](#method.update), and it never returns `Pending`.
+    #[inline]
+    fn poll_write(
+        self: core::pin::Pin<&mut Self>,
+        _cx: &mut core::task::Context<'_>,
+        input: &[u8],
+    ) -> core::task::Poll<std::io::Result<usize>> {
+        core::task::Poll::Ready(std::io::Write::write(self.get_mut(), input))
+    }
+
+    #[inline]
+    fn poll_flush(
+        self: core::pin::Pin<&mut Self>,
+        _cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<std::io::Result<()>> {
+        core::task::Poll::Ready(Ok(()))
+    }
+
+    #[inline]
+    fn poll_shutdown(
+        self: core::pin::Pin<&mut Self>,
+        _cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<std::io::Result<()>> {
+        core::task::Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl futures_io::AsyncWrite for Hasher {
+    /// This is equivalent to [`update`](#method.update), and it never returns `Pending`.
+    #[inline]
+    fn poll_write(
+        self: core::pin::Pin<&mut Self>,
+        _cx: &mut core::task::Context<'_>,
+        input: &[u8],
+    ) -> core::task::Poll<std::io::Result<usize>> {
+        core::task::Poll::Ready(std::io::Write::write(self.get_mut(), input))
+    }
+
+    #[inline]
+    fn poll_flush(
+        self: core::pin::Pin<&mut Self>,
+        _cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<std::io::Result<()>> {
+        core::task::Poll::Ready(Ok(()))
+    }
+
+    #[inline]
+    fn poll_close(
+        self: core::pin::Pin<&mut Self>,
+        _cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<std::io::Result<()>> {
+        core::task::Poll::Ready(Ok(()))
+    }
+}
+
 #[cfg(feature = "zeroize")]
 impl Zeroize for Hasher {
     fn zeroize(&mut self) {
@@ -1680,6 +2281,11 @@ impl Zeroize for Hasher {
 /// from an unknown position in the output stream to recover its block index. Callers with strong
 /// secret keys aren't affected in practice, but secret offsets are a [design
 /// smell](https://en.wikipedia.org/wiki/Design_smell) in any case.
+///
+/// When the `traits-preview` Cargo feature is enabled, this type implements the `StreamCipher`
+/// and `StreamCipherSeek` traits from the [`cipher`](https://crates.io/crates/cipher) crate, using
+/// [`xor_into`](OutputReader::xor_into). The same SemVer caveats apply as for the `digest` traits
+/// on [`Hasher`].
 #[derive(Clone)]
 pub struct OutputReader {
     inner: Output,
@@ -1726,7 +2332,96 @@ impl OutputReader {
     /// reading further, the behavior is unspecified.
     ///
     /// [`Read::read`]: #method.read
-    pub fn fill(&mut self, mut buf: &mut [u8]) {
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        self.fill_with_join::<join::SerialJoin>(buf)
+    }
+
+    /// XOR output bytes into a buffer and advance the position of the `OutputReader`, as if
+    /// calling [`fill`](OutputReader::fill) on a temporary buffer and XORing that into `buf`.
+    ///
+    /// This lets you use the extended output of a keyed hash as a keystream, without writing the
+    /// whole keystream to memory first. Applying the same keystream twice, from the same position,
+    /// restores the original bytes. Use [`set_position`](OutputReader::set_position) to start from
+    /// somewhere other than the current position.
+    ///
+    /// BLAKE3 isn't a standardized stream cipher, and using it as one is up to you. In particular,
+    /// never reuse the same key and input for two different streams, and note that this provides
+    /// no authentication.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let key = [42; 32];
+    /// let mut data = *b"some secret bytes";
+    /// blake3::Hasher::new_keyed(&key).update(b"nonce").finalize_xof().xor_into(&mut data);
+    /// assert_ne!(&data, b"some secret bytes");
+    /// blake3::Hasher::new_keyed(&key).update(b"nonce").finalize_xof().xor_into(&mut data);
+    /// assert_eq!(&data, b"some secret bytes");
+    /// ```
+    pub fn xor_into(&mut self, mut buf: &mut [u8]) {
+        // Generate full blocks into a small buffer that stays in cache, rather than filling a
+        // temporary buffer the size of the input.
+        let mut keystream = [0; MAX_SIMD_DEGREE * BLOCK_LEN];
+        while !buf.is_empty() {
+            if self.position_within_block != 0 || buf.len() < BLOCK_LEN {
+                self.xor_one_block(&mut buf);
+                continue;
+            }
+            let len = cmp::min(buf.len() / BLOCK_LEN * BLOCK_LEN, keystream.len());
+            self.inner.platform.xof_many(
+                &self.inner.input_chaining_value,
+                &self.inner.block,
+                self.inner.block_len,
+                self.inner.counter,
+                self.inner.flags | ROOT,
+                &mut keystream[..len],
+            );
+            xor_bytes(&mut buf[..len], &keystream[..len]);
+            self.inner.counter += (len / BLOCK_LEN) as u64;
+            buf = &mut core::mem::take(&mut buf)[len..];
+        }
+    }
+
+    // Like fill_one_block, but XOR the output bytes into the buffer.
+    fn xor_one_block(&mut self, buf: &mut &mut [u8]) {
+        let output_block: [u8; BLOCK_LEN] = self.inner.root_output_block();
+        let output_bytes = &output_block[self.position_within_block as usize..];
+        let take = cmp::min(buf.len(), output_bytes.len());
+        xor_bytes(&mut buf[..take], &output_bytes[..take]);
+        self.position_within_block += take as u8;
+        if self.position_within_block == BLOCK_LEN as u8 {
+            self.inner.counter += 1;
+            self.position_within_block = 0;
+        }
+        *buf = &mut core::mem::take(buf)[take..];
+    }
+
+    /// As [`fill`](OutputReader::fill), but using the given [`Join`](join::Join)
+    /// implementation to fill large buffers with multiple threads.
+    ///
+    /// Each 64-byte output block depends only on its position, so the output bytes and the final
+    /// position of the reader are the same as with `fill`. As with
+    /// [`update_with_join`](Hasher::update_with_join), the buffer needs to be large to get any
+    /// benefit from multithreading, on the order of megabytes for
+    /// [`ScopedThreadJoin`](join::ScopedThreadJoin).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # #[cfg(feature = "std")] {
+    /// use blake3::join::ScopedThreadJoin;
+    ///
+    /// let mut output = vec![0; 10_000_000];
+    /// let mut reader = blake3::Hasher::new().update(b"seed").finalize_xof();
+    /// reader.fill_with_join::<ScopedThreadJoin>(&mut output);
+    /// assert_eq!(reader.position(), 10_000_000);
+    ///
+    /// let mut expected = vec![0; 10_000_000];
+    /// blake3::Hasher::new().update(b"seed").finalize_xof().fill(&mut expected);
+    /// assert_eq!(output, expected);
+    /// # }
+    /// ```
+    pub fn fill_with_join<J: join::Join>(&mut self, mut buf: &mut [u8]) {
         if buf.is_empty() {
             return;
         }
@@ -1740,14 +2435,8 @@ impl OutputReader {
         let full_blocks_len = full_blocks * BLOCK_LEN;
         if full_blocks > 0 {
             debug_assert_eq!(0, self.position_within_block);
-            self.inner.platform.xof_many(
-                &self.inner.input_chaining_value,
-                &self.inner.block,
-                self.inner.block_len,
-                self.inner.counter,
-                self.inner.flags | ROOT,
-                &mut buf[..full_blocks_len],
-            );
+            self.inner
+                .root_output_blocks_with_join::<J>(self.inner.counter, &mut buf[..full_blocks_len]);
             self.inner.counter += full_blocks as u64;
             buf = &mut buf[full_blocks * BLOCK_LEN..];
         }
@@ -1782,6 +2471,26 @@ impl OutputReader {
         self.position_within_block = (position % BLOCK_LEN as u64) as u8;
         self.inner.counter = position / BLOCK_LEN as u64;
     }
+
+    /// As [`fill`](OutputReader::fill), but using Rayon-based multithreading
+    /// internally.
+    ///
+    /// This method is gated by the `rayon` Cargo feature, which is disabled by
+    /// default but enabled on [docs.rs](https://docs.rs). It's equivalent to
+    /// [`fill_with_join::<RayonJoin>`](OutputReader::fill_with_join). As with
+    /// [`update_rayon`](Hasher::update_rayon), it's only faster than `fill` for
+    /// large buffers, and it's important to benchmark your specific use case.
+    #[cfg(feature = "rayon")]
+    pub fn fill_rayon(&mut self, buf: &mut [u8]) {
+        self.fill_with_join::<join::RayonJoin>(buf)
+    }
+}
+
+fn xor_bytes(dest: &mut [u8], src: &[u8]) {
+    debug_assert_eq!(dest.len(), src.len());
+    for (d, s) in dest.iter_mut().zip(src) {
+        *d ^= s;
+    }
 }
 
 // Don't derive(Debug), because the state may be secret.
@@ -1827,6 +2536,70 @@ impl std::io::Seek for OutputReader {
     }
 }
 
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncRead for OutputReader {
+    /// This is equivalent to [`fill`](#method.fill), and it never returns `Pending`. If the
+    /// unfilled part of `buf` isn't initialized, this might fill only part of it.
+    #[inline]
+    fn poll_read(
+        self: core::pin::Pin<&mut Self>,
+        _cx: &mut core::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> core::task::Poll<std::io::Result<()>> {
+        // Zeroing uninitialized memory only for fill to overwrite it is wasted work, so beyond
+        // what's already initialized, produce at most one xof_many batch per call.
+        let initialized = buf.initialized().len() - buf.filled().len();
+        let len = cmp::min(
+            buf.remaining(),
+            cmp::max(initialized, MAX_SIMD_DEGREE * BLOCK_LEN),
+        );
+        let dest = buf.initialize_unfilled_to(len);
+        self.get_mut().fill(dest);
+        buf.advance(len);
+        core::task::Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncSeek for OutputReader {
+    fn start_seek(self: core::pin::Pin<&mut Self>, pos: std::io::SeekFrom) -> std::io::Result<()> {
+        std::io::Seek::seek(self.get_mut(), pos)?;
+        Ok(())
+    }
+
+    #[inline]
+    fn poll_complete(
+        self: core::pin::Pin<&mut Self>,
+        _cx: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<std::io::Result<u64>> {
+        core::task::Poll::Ready(Ok(self.position()))
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl futures_io::AsyncRead for OutputReader {
+    /// This is equivalent to [`fill`](#method.fill), and it never returns `Pending`.
+    #[inline]
+    fn poll_read(
+        self: core::pin::Pin<&mut Self>,
+        _cx: &mut core::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> core::task::Poll<std::io::Result<usize>> {
+        core::task::Poll::Ready(std::io::Read::read(self.get_mut(), buf))
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl futures_io::AsyncSeek for OutputReader {
+    fn poll_seek(
+        self: core::pin::Pin<&mut Self>,
+        _cx: &mut core::task::Context<'_>,
+        pos: std::io::SeekFrom,
+    ) -> core::task::Poll<std::io::Result<u64>> {
+        core::task::Poll::Ready(std::io::Seek::seek(self.get_mut(), pos))
+    }
+}
+
 #[cfg(feature = "zeroize")]
 impl Zeroize for OutputReader {
     fn zeroize(&mut self) {
@@ -0,0 +1,216 @@
+//! Encoding and decoding BLAKE3 [multihashes](https://github.com/multiformats/multihash).
+//!
+//! A multihash is a varint hash function code (0x1e for BLAKE3), followed by a varint digest
+//! length, followed by the digest itself. BLAKE3 is an extendable-output function, so its
+//! multihashes can have any digest length, and the functions here support that. For the default
+//! 32-byte digest, [`Hash::to_multihash`](crate::Hash::to_multihash) and
+//! [`Hash::from_multihash`](crate::Hash::from_multihash) are more convenient.
+//!
+//! None of these functions allocate.
+//!
+//! # Example
+//!
+//! ```
+//! let mut reader = blake3::Hasher::new().update(b"foo").finalize_xof();
+//! let mut multihash = [0; blake3::multihash::encoded_len(64)];
+//! let len = blake3::multihash::encode_xof(&mut reader, 64, &mut multihash);
+//! assert_eq!(len, multihash.len());
+//! assert_eq!(multihash[..3], [0x1e, 0x40, 0x04]);
+//!
+//! let digest = blake3::multihash::decode(&multihash)?;
+//! assert_eq!(digest.len(), 64);
+//! assert_eq!(digest[..32], blake3::hash(b"foo").as_bytes()[..]);
+//! # Ok::<(), blake3::DecodeError>(())
+//! ```
+
+use crate::{DecodeError, OUT_LEN, OutputReader};
+
+/// The multihash code for BLAKE3.
+pub const CODE: u64 = 0x1e;
+
+/// The length of the multihash of a default-length [`Hash`](crate::Hash).
+pub const HASH_LEN: usize = encoded_len(OUT_LEN);
+
+// A u64 varint is at most 10 bytes, but the multiformats spec caps varints at 9 bytes (63 bits).
+const MAX_VARINT_LEN: usize = 9;
+
+/// The length of a BLAKE3 multihash with a digest of `digest_len` bytes.
+pub const fn encoded_len(digest_len: usize) -> usize {
+    varint_len(CODE) + varint_len(digest_len as u64) + digest_len
+}
+
+/// Write the multihash of `digest` to the start of `out`, and return the number of bytes
+/// written, which is [`encoded_len(digest.len())`](encoded_len).
+///
+/// # Panics
+///
+/// Panics if `out` is shorter than `encoded_len(digest.len())`.
+pub fn encode(digest: &[u8], out: &mut [u8]) -> usize {
+    let header_len = encode_header(digest.len(), out);
+    out[header_len..][..digest.len()].copy_from_slice(digest);
+    header_len + digest.len()
+}
+
+/// Fill a `digest_len`-byte digest from `reader`, and write its multihash to the start of `out`.
+/// Return the number of bytes written, which is [`encoded_len(digest_len)`](encoded_len).
+///
+/// The digest starts at the reader's current position, and the reader advances past it, as with
+/// [`OutputReader::fill`].
+///
+/// # Panics
+///
+/// Panics if `out` is shorter than `encoded_len(digest_len)`.
+pub fn encode_xof(reader: &mut OutputReader, digest_len: usize, out: &mut [u8]) -> usize {
+    let header_len = encode_header(digest_len, out);
+    reader.fill(&mut out[header_len..][..digest_len]);
+    header_len + digest_len
+}
+
+/// Decode a BLAKE3 multihash, and return the digest, which can be any length.
+///
+/// This returns an error if the code isn't 0x1e, if either varint isn't minimally encoded, or if
+/// the length of `multihash` doesn't match the encoded digest length.
+pub fn decode(multihash: &[u8]) -> Result<&[u8], DecodeError> {
+    let (code, rest) = read_varint(multihash)?;
+    if code != CODE {
+        return Err(DecodeError::wrong_code(code));
+    }
+    let (digest_len, digest) = read_varint(rest)?;
+    if digest_len != digest.len() as u64 {
+        return Err(DecodeError::digest_len_mismatch(digest_len, digest.len()));
+    }
+    Ok(digest)
+}
+
+fn encode_header(digest_len: usize, out: &mut [u8]) -> usize {
+    assert!(
+        out.len() >= encoded_len(digest_len),
+        "output buffer too short for multihash"
+    );
+    let code_len = write_varint(CODE, out);
+    code_len + write_varint(digest_len as u64, &mut out[code_len..])
+}
+
+const fn varint_len(mut value: u64) -> usize {
+    let mut len = 1;
+    while value >= 0x80 {
+        value >>= 7;
+        len += 1;
+    }
+    len
+}
+
+fn write_varint(mut value: u64, out: &mut [u8]) -> usize {
+    let mut i = 0;
+    while value >= 0x80 {
+        out[i] = (value as u8) | 0x80;
+        value >>= 7;
+        i += 1;
+    }
+    out[i] = value as u8;
+    i + 1
+}
+
+// Read an unsigned LEB128 varint, and return it with the rest of the input.
+fn read_varint(input: &[u8]) -> Result<(u64, &[u8]), DecodeError> {
+    let mut value = 0;
+    for (i, &byte) in input.iter().enumerate().take(MAX_VARINT_LEN) {
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            // A trailing zero byte means the same value fit in fewer bytes.
+            if byte == 0 && i > 0 {
+                return Err(DecodeError::invalid_varint());
+            }
+            return Ok((value, &input[i + 1..]));
+        }
+    }
+    if input.len() >= MAX_VARINT_LEN {
+        Err(DecodeError::invalid_varint())
+    } else {
+        Err(DecodeError::truncated())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_varints() {
+        let mut buf = [0; MAX_VARINT_LEN];
+        for value in [
+            0,
+            1,
+            0x7f,
+            0x80,
+            300,
+            0x3fff,
+            0x4000,
+            u32::MAX as u64,
+            (1 << 63) - 1,
+        ] {
+            let len = write_varint(value, &mut buf);
+            assert_eq!(len, varint_len(value));
+            let (decoded, rest) = read_varint(&buf[..len]).unwrap();
+            assert_eq!(decoded, value);
+            assert!(rest.is_empty());
+            if len > 1 {
+                assert!(read_varint(&buf[..len - 1]).is_err());
+            }
+        }
+        // Non-minimal encodings of 0 and 1.
+        assert!(read_varint(&[0x80, 0x00]).is_err());
+        assert!(read_varint(&[0x81, 0x00]).is_err());
+        // Longer than 9 bytes.
+        assert!(read_varint(&[0xff; 10]).is_err());
+        assert!(read_varint(&[]).is_err());
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let mut input = [0; 1000];
+        crate::test::paint_test_input(&mut input);
+        let mut expected = [0; 300];
+        crate::Hasher::new()
+            .update(&input)
+            .finalize_xof()
+            .fill(&mut expected);
+        let mut buf = [0xff; encoded_len(300) + 1];
+        for digest_len in [0, 1, 20, 32, 64, 127, 128, 300] {
+            let expected_len = encoded_len(digest_len);
+            assert_eq!(expected_len, 2 + (digest_len >= 128) as usize + digest_len);
+
+            let len = encode(&expected[..digest_len], &mut buf);
+            assert_eq!(len, expected_len);
+            assert_eq!(decode(&buf[..len]).unwrap(), &expected[..digest_len]);
+
+            buf.fill(0xff);
+            let mut reader = crate::Hasher::new().update(&input).finalize_xof();
+            let len = encode_xof(&mut reader, digest_len, &mut buf);
+            assert_eq!(len, expected_len);
+            assert_eq!(reader.position(), digest_len as u64);
+            assert_eq!(decode(&buf[..len]).unwrap(), &expected[..digest_len]);
+
+            // Extra or missing bytes are errors.
+            assert!(decode(&buf[..len + 1]).is_err());
+            assert!(decode(&buf[..len - 1]).is_err());
+        }
+
+        // Starting partway through the output stream.
+        let mut reader = crate::Hasher::new().update(&input).finalize_xof();
+        reader.set_position(100);
+        let len = encode_xof(&mut reader, 32, &mut buf);
+        assert_eq!(decode(&buf[..len]).unwrap(), &expected[100..132]);
+
+        // A different hash function (0x12 is SHA2-256).
+        buf[0] = 0x12;
+        assert!(decode(&buf[..len]).is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_encode_short_buffer() {
+        let mut buf = [0; HASH_LEN - 1];
+        encode(&[0; OUT_LEN], &mut buf);
+    }
+}
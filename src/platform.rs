@@ -1,4 +1,4 @@
-use crate::{BLOCK_LEN, CVWords, IncrementCounter, portable};
+use crate::{BLOCK_LEN, CVWords, IncrementCounter, OUT_LEN, portable};
 use arrayref::{array_mut_ref, array_ref};
 
 cfg_if::cfg_if! {
@@ -334,6 +334,37 @@ impl Platform {
             Platform::AVX512 => unsafe {
                 crate::avx512::xof_many(cv, block, block_len, counter, flags, out)
             },
+            // The AVX-512 assembly doesn't have xof_many on Windows. AVX-512 implies AVX2.
+            #[cfg(blake3_avx512_ffi)]
+            #[cfg(not(unix))]
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::AVX512 => unsafe {
+                crate::rust_avx2::xof_many(cv, block, block_len, counter, flags, out)
+            },
+            // Safe because detect() checked for platform support. The assembly implementations of
+            // SSE2, SSE4.1, and AVX2 don't have xof_many, so always use the intrinsics ones.
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::SSE2 => unsafe {
+                crate::rust_sse2::xof_many(cv, block, block_len, counter, flags, out)
+            },
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::SSE41 => unsafe {
+                crate::rust_sse41::xof_many(cv, block, block_len, counter, flags, out)
+            },
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::AVX2 => unsafe {
+                crate::rust_avx2::xof_many(cv, block, block_len, counter, flags, out)
+            },
+            // Assumed to be safe if the "neon" feature is on.
+            #[cfg(blake3_neon)]
+            Platform::NEON => unsafe {
+                crate::neon::xof_many(cv, block, block_len, counter, flags, out)
+            },
+            // Assumed to be safe if the "wasm32_simd" feature is on.
+            #[cfg(blake3_wasm32_simd)]
+            Platform::WASM32_SIMD => unsafe {
+                crate::wasm32_simd::xof_many(cv, block, block_len, counter, flags, out)
+            },
             _ => {
                 // For platforms without an optimized xof_many, fall back to a loop over
                 // compress_xof. This is still faster than portable code.
@@ -347,6 +378,52 @@ impl Platform {
         }
     }
 
+    // Compress one block for each chaining value in `cvs`, in place. Each block has its own length,
+    // but they all share the counter and flags. hash_batch uses this for the final blocks of its
+    // inputs, which can be partial.
+    pub fn compress_many(
+        &self,
+        cvs: &mut [u8],
+        blocks: &[&[u8; BLOCK_LEN]],
+        block_lens: &[u8],
+        counter: u64,
+        flags: u8,
+    ) {
+        debug_assert_eq!(cvs.len(), blocks.len() * OUT_LEN, "one CV per block");
+        debug_assert_eq!(block_lens.len(), blocks.len(), "one length per block");
+        match self {
+            // Safe because detect() checked for platform support. Like xof_many, only the
+            // intrinsics implementations have compress_many, so always use them.
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::SSE2 => unsafe {
+                crate::rust_sse2::compress_many(cvs, blocks, block_lens, counter, flags)
+            },
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::SSE41 => unsafe {
+                crate::rust_sse41::compress_many(cvs, blocks, block_lens, counter, flags)
+            },
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::AVX2 => unsafe {
+                crate::rust_avx2::compress_many(cvs, blocks, block_lens, counter, flags)
+            },
+            // AVX-512 implies AVX2.
+            #[cfg(blake3_avx512_ffi)]
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            Platform::AVX512 => unsafe {
+                crate::rust_avx2::compress_many(cvs, blocks, block_lens, counter, flags)
+            },
+            _ => {
+                for ((cv, block), &block_len) in
+                    cvs.chunks_exact_mut(OUT_LEN).zip(blocks).zip(block_lens)
+                {
+                    let mut cv_words = words_from_le_bytes_32(array_ref!(cv, 0, OUT_LEN));
+                    self.compress_in_place(&mut cv_words, block, block_len, counter, flags);
+                    cv.copy_from_slice(&le_bytes_from_words_32(&cv_words));
+                }
+            }
+        }
+    }
+
     // Explicit platform constructors, for benchmarks.
 
     pub fn portable() -> Self {
@@ -5,7 +5,7 @@ use crate::{
 use arrayref::{array_mut_ref, array_ref};
 
 #[inline(always)]
-fn g(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, x: u32, y: u32) {
+const fn g(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, x: u32, y: u32) {
     state[a] = state[a].wrapping_add(state[b]).wrapping_add(x);
     state[d] = (state[d] ^ state[a]).rotate_right(16);
     state[c] = state[c].wrapping_add(state[d]);
@@ -17,7 +17,7 @@ fn g(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize, x: u32, y: u
 }
 
 #[inline(always)]
-fn round(state: &mut [u32; 16], msg: &[u32; 16], round: usize) {
+const fn round(state: &mut [u32; 16], msg: &[u32; 16], round: usize) {
     // Select the message schedule based on the round.
     let schedule = MSG_SCHEDULE[round];
 
@@ -43,7 +43,19 @@ fn compress_pre(
     flags: u8,
 ) -> [u32; 16] {
     let block_words = crate::platform::words_from_le_bytes_64(block);
+    compress_words(cv, &block_words, block_len, counter, flags)
+}
 
+// The compression function without the final XORs, taking the block as words. This is a const
+// fn, so that the const_fn module can use it too.
+#[inline(always)]
+pub const fn compress_words(
+    cv: &CVWords,
+    block_words: &[u32; 16],
+    block_len: u8,
+    counter: u64,
+    flags: u8,
+) -> [u32; 16] {
     let mut state = [
         cv[0],
         cv[1],
@@ -63,13 +75,13 @@ fn compress_pre(
         flags as u32,
     ];
 
-    round(&mut state, &block_words, 0);
-    round(&mut state, &block_words, 1);
-    round(&mut state, &block_words, 2);
-    round(&mut state, &block_words, 3);
-    round(&mut state, &block_words, 4);
-    round(&mut state, &block_words, 5);
-    round(&mut state, &block_words, 6);
+    round(&mut state, block_words, 0);
+    round(&mut state, block_words, 1);
+    round(&mut state, block_words, 2);
+    round(&mut state, block_words, 3);
+    round(&mut state, block_words, 4);
+    round(&mut state, block_words, 5);
+    round(&mut state, block_words, 6);
 
     state
 }
@@ -746,6 +746,233 @@ pub unsafe fn hash_many<const N: usize>(
     }
 }
 
+#[target_feature(enable = "sse4.1")]
+unsafe fn xof4(
+    cv: &CVWords,
+    block: &[u8; BLOCK_LEN],
+    block_len: u8,
+    counter: u64,
+    flags: u8,
+    out: &mut [u8; DEGREE * BLOCK_LEN],
+) {
+    // This is the transposed compression function from hash4, with the same message block and
+    // chaining value in every lane, and the counter incrementing across lanes.
+    let block_words = crate::platform::words_from_le_bytes_64(block);
+    unsafe {
+        let msg_vecs = [
+            set1(block_words[0]),
+            set1(block_words[1]),
+            set1(block_words[2]),
+            set1(block_words[3]),
+            set1(block_words[4]),
+            set1(block_words[5]),
+            set1(block_words[6]),
+            set1(block_words[7]),
+            set1(block_words[8]),
+            set1(block_words[9]),
+            set1(block_words[10]),
+            set1(block_words[11]),
+            set1(block_words[12]),
+            set1(block_words[13]),
+            set1(block_words[14]),
+            set1(block_words[15]),
+        ];
+        let cv_vecs = [
+            set1(cv[0]),
+            set1(cv[1]),
+            set1(cv[2]),
+            set1(cv[3]),
+            set1(cv[4]),
+            set1(cv[5]),
+            set1(cv[6]),
+            set1(cv[7]),
+        ];
+        let (counter_low_vec, counter_high_vec) = load_counters(counter, IncrementCounter::Yes);
+        let mut v = [
+            cv_vecs[0],
+            cv_vecs[1],
+            cv_vecs[2],
+            cv_vecs[3],
+            cv_vecs[4],
+            cv_vecs[5],
+            cv_vecs[6],
+            cv_vecs[7],
+            set1(IV[0]),
+            set1(IV[1]),
+            set1(IV[2]),
+            set1(IV[3]),
+            counter_low_vec,
+            counter_high_vec,
+            set1(block_len as u32),
+            set1(flags as u32),
+        ];
+        round(&mut v, &msg_vecs, 0);
+        round(&mut v, &msg_vecs, 1);
+        round(&mut v, &msg_vecs, 2);
+        round(&mut v, &msg_vecs, 3);
+        round(&mut v, &msg_vecs, 4);
+        round(&mut v, &msg_vecs, 5);
+        round(&mut v, &msg_vecs, 6);
+        for i in 0..8 {
+            v[i] = xor(v[i], v[i + 8]);
+            v[i + 8] = xor(v[i + 8], cv_vecs[i]);
+        }
+
+        let squares = mut_array_refs!(&mut v, DEGREE, DEGREE, DEGREE, DEGREE);
+        transpose_vecs(squares.0);
+        transpose_vecs(squares.1);
+        transpose_vecs(squares.2);
+        transpose_vecs(squares.3);
+        // Each square now contains a quarter of each output block, one block per vec.
+        for i in 0..DEGREE {
+            storeu(v[i], out.as_mut_ptr().add((4 * i + 0) * 4 * DEGREE));
+            storeu(v[i + 4], out.as_mut_ptr().add((4 * i + 1) * 4 * DEGREE));
+            storeu(v[i + 8], out.as_mut_ptr().add((4 * i + 2) * 4 * DEGREE));
+            storeu(v[i + 12], out.as_mut_ptr().add((4 * i + 3) * 4 * DEGREE));
+        }
+    }
+}
+
+#[target_feature(enable = "sse4.1")]
+pub unsafe fn xof_many(
+    cv: &CVWords,
+    block: &[u8; BLOCK_LEN],
+    block_len: u8,
+    mut counter: u64,
+    flags: u8,
+    mut out: &mut [u8],
+) {
+    debug_assert_eq!(0, out.len() % BLOCK_LEN, "whole blocks only");
+    while out.len() >= DEGREE * BLOCK_LEN {
+        unsafe {
+            xof4(
+                cv,
+                block,
+                block_len,
+                counter,
+                flags,
+                array_mut_ref!(out, 0, DEGREE * BLOCK_LEN),
+            );
+        }
+        counter += DEGREE as u64;
+        out = &mut out[DEGREE * BLOCK_LEN..];
+    }
+    for out_block in out.chunks_exact_mut(BLOCK_LEN) {
+        *array_mut_ref!(out_block, 0, BLOCK_LEN) =
+            unsafe { compress_xof(cv, block, block_len, counter, flags) };
+        counter += 1;
+    }
+}
+
+#[target_feature(enable = "sse4.1")]
+unsafe fn compress4(
+    cvs: &mut [u8; DEGREE * OUT_LEN],
+    blocks: &[*const u8; DEGREE],
+    block_lens: &[u8; DEGREE],
+    counter: u64,
+    flags: u8,
+) {
+    // This is the transposed compression function from hash4, with a different chaining value,
+    // message block, and block length in each lane. The CVs are laid out like the outputs of
+    // hash4, so loading them is the reverse of storing those.
+    unsafe {
+        let mut h_vecs = [
+            loadu(cvs.as_ptr().add(0 * 4 * DEGREE)),
+            loadu(cvs.as_ptr().add(2 * 4 * DEGREE)),
+            loadu(cvs.as_ptr().add(4 * 4 * DEGREE)),
+            loadu(cvs.as_ptr().add(6 * 4 * DEGREE)),
+            loadu(cvs.as_ptr().add(1 * 4 * DEGREE)),
+            loadu(cvs.as_ptr().add(3 * 4 * DEGREE)),
+            loadu(cvs.as_ptr().add(5 * 4 * DEGREE)),
+            loadu(cvs.as_ptr().add(7 * 4 * DEGREE)),
+        ];
+        let squares = mut_array_refs!(&mut h_vecs, DEGREE, DEGREE);
+        transpose_vecs(squares.0);
+        transpose_vecs(squares.1);
+        let msg_vecs = transpose_msg_vecs(blocks, 0);
+        let mut v = [
+            h_vecs[0],
+            h_vecs[1],
+            h_vecs[2],
+            h_vecs[3],
+            h_vecs[4],
+            h_vecs[5],
+            h_vecs[6],
+            h_vecs[7],
+            set1(IV[0]),
+            set1(IV[1]),
+            set1(IV[2]),
+            set1(IV[3]),
+            set1(counter_low(counter)),
+            set1(counter_high(counter)),
+            set4(
+                block_lens[0] as u32,
+                block_lens[1] as u32,
+                block_lens[2] as u32,
+                block_lens[3] as u32,
+            ),
+            set1(flags as u32),
+        ];
+        round(&mut v, &msg_vecs, 0);
+        round(&mut v, &msg_vecs, 1);
+        round(&mut v, &msg_vecs, 2);
+        round(&mut v, &msg_vecs, 3);
+        round(&mut v, &msg_vecs, 4);
+        round(&mut v, &msg_vecs, 5);
+        round(&mut v, &msg_vecs, 6);
+        for i in 0..8 {
+            h_vecs[i] = xor(v[i], v[i + 8]);
+        }
+
+        let squares = mut_array_refs!(&mut h_vecs, DEGREE, DEGREE);
+        transpose_vecs(squares.0);
+        transpose_vecs(squares.1);
+        storeu(h_vecs[0], cvs.as_mut_ptr().add(0 * 4 * DEGREE));
+        storeu(h_vecs[4], cvs.as_mut_ptr().add(1 * 4 * DEGREE));
+        storeu(h_vecs[1], cvs.as_mut_ptr().add(2 * 4 * DEGREE));
+        storeu(h_vecs[5], cvs.as_mut_ptr().add(3 * 4 * DEGREE));
+        storeu(h_vecs[2], cvs.as_mut_ptr().add(4 * 4 * DEGREE));
+        storeu(h_vecs[6], cvs.as_mut_ptr().add(5 * 4 * DEGREE));
+        storeu(h_vecs[3], cvs.as_mut_ptr().add(6 * 4 * DEGREE));
+        storeu(h_vecs[7], cvs.as_mut_ptr().add(7 * 4 * DEGREE));
+    }
+}
+
+#[target_feature(enable = "sse4.1")]
+pub unsafe fn compress_many(
+    mut cvs: &mut [u8],
+    mut blocks: &[&[u8; BLOCK_LEN]],
+    mut block_lens: &[u8],
+    counter: u64,
+    flags: u8,
+) {
+    debug_assert_eq!(cvs.len(), blocks.len() * OUT_LEN, "one CV per block");
+    debug_assert_eq!(block_lens.len(), blocks.len(), "one length per block");
+    while blocks.len() >= DEGREE {
+        // Safe because the layout of arrays is guaranteed, and because the blocks are arrays of
+        // BLOCK_LEN bytes.
+        let block_ptrs: &[*const u8; DEGREE] =
+            unsafe { &*(blocks.as_ptr() as *const [*const u8; DEGREE]) };
+        unsafe {
+            compress4(
+                array_mut_ref!(cvs, 0, DEGREE * OUT_LEN),
+                block_ptrs,
+                array_ref!(block_lens, 0, DEGREE),
+                counter,
+                flags,
+            );
+        }
+        cvs = &mut cvs[DEGREE * OUT_LEN..];
+        blocks = &blocks[DEGREE..];
+        block_lens = &block_lens[DEGREE..];
+    }
+    for ((cv, block), &block_len) in cvs.chunks_exact_mut(OUT_LEN).zip(blocks).zip(block_lens) {
+        let mut cv_words = crate::platform::words_from_le_bytes_32(array_ref!(cv, 0, OUT_LEN));
+        unsafe { compress_in_place(&mut cv_words, block, block_len, counter, flags) };
+        cv.copy_from_slice(&crate::platform::le_bytes_from_words_32(&cv_words));
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -797,4 +1024,20 @@ mod test {
         }
         crate::test::test_hash_many_fn(hash_many, hash_many);
     }
+
+    #[test]
+    fn test_xof_many() {
+        if !crate::platform::sse41_detected() {
+            return;
+        }
+        crate::test::test_xof_many_fn(xof_many);
+    }
+
+    #[test]
+    fn test_compress_many() {
+        if !crate::platform::sse41_detected() {
+            return;
+        }
+        crate::test::test_compress_many_fn(compress_many);
+    }
 }
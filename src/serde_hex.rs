@@ -0,0 +1,258 @@
+//! Serde adapters that represent hashes as hex strings in human-readable formats.
+//!
+//! With the `serde` feature, [`Hash`](struct@Hash) serializes as an array of 32 integers. That's
+//! compact in binary formats, but it's awkward in JSON or TOML configs and APIs. The functions in
+//! this module are meant for `#[serde(with = "blake3::serde_hex")]`. They serialize a lowercase
+//! hex string when the format is human-readable (like JSON), and raw bytes when it isn't (like
+//! CBOR or bincode). They work with any 32-byte value that converts to and from `[u8; 32]`,
+//! including [`Hash`](struct@Hash), [`hazmat::ChainingValue`](crate::hazmat::ChainingValue), and
+//! [`hazmat::ContextKey`](crate::hazmat::ContextKey).
+//!
+//! Deserialization also accepts the array of 32 integers that the default `Hash` implementation
+//! produces, and the bytestring that some older versions of this crate produced. In
+//! self-describing formats like JSON or TOML, that means switching a field to this module doesn't
+//! break data that's already been written. In formats that aren't self-describing, like bincode or
+//! postcard, the encoding changes, and existing data can't be read back.
+//!
+//! For a `Hash` that isn't a struct field, like an element of a `Vec` or a map key, wrap it in
+//! [`HexHash`]. For variable-length outputs from [`OutputReader`](crate::OutputReader), like a
+//! `Vec<u8>`, use the [`bytes`] submodule.
+//!
+//! # Example
+//!
+//! ```
+//! # #[cfg(feature = "std")] {
+//! #[derive(serde::Serialize, serde::Deserialize)]
+//! struct Asset {
+//!     name: String,
+//!     #[serde(with = "blake3::serde_hex")]
+//!     hash: blake3::Hash,
+//! }
+//!
+//! let asset = Asset {
+//!     name: "foo".into(),
+//!     hash: blake3::hash(b"foo"),
+//! };
+//! let json = serde_json::to_string(&asset).unwrap();
+//! assert_eq!(
+//!     json,
+//!     r#"{"name":"foo","hash":"04e0bb39f30b1a3feb89f536c93be15055482df748674b00d26e5a75777702e9"}"#,
+//! );
+//! # }
+//! ```
+
+use crate::{Hash, OUT_LEN};
+use core::fmt;
+use serde::de::{Error, SeqAccess, Visitor};
+use serde::{Deserializer, Serializer};
+
+/// Serialize a 32-byte value as a hex string or as bytes. See the [module docs](self).
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Clone + Into<[u8; OUT_LEN]>,
+    S: Serializer,
+{
+    let bytes: [u8; OUT_LEN] = value.clone().into();
+    if serializer.is_human_readable() {
+        serializer.serialize_str(&Hash::from_bytes(bytes).to_hex())
+    } else {
+        serializer.serialize_bytes(&bytes)
+    }
+}
+
+/// Deserialize a 32-byte value from a hex string, bytes, or an array of integers. See the
+/// [module docs](self).
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: From<[u8; OUT_LEN]>,
+    D: Deserializer<'de>,
+{
+    let bytes = if deserializer.is_human_readable() {
+        deserializer.deserialize_any(FixedVisitor)?
+    } else {
+        deserializer.deserialize_bytes(FixedVisitor)?
+    };
+    Ok(T::from(bytes))
+}
+
+struct FixedVisitor;
+
+impl<'de> Visitor<'de> for FixedVisitor {
+    type Value = [u8; OUT_LEN];
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("64 hex characters or 32 bytes")
+    }
+
+    fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
+        Hash::from_hex(v).map(Into::into).map_err(E::custom)
+    }
+
+    fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+        v.try_into().map_err(|_| E::invalid_length(v.len(), &self))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = [0; OUT_LEN];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = seq
+                .next_element()?
+                .ok_or_else(|| A::Error::invalid_length(i, &self))?;
+        }
+        if seq.next_element::<u8>()?.is_some() {
+            return Err(A::Error::invalid_length(OUT_LEN + 1, &self));
+        }
+        Ok(bytes)
+    }
+}
+
+/// A [`Hash`](struct@Hash) that serializes as a hex string in human-readable formats, using the
+/// functions in [this module](self).
+///
+/// This is for places where `#[serde(with = ...)]` doesn't reach, like the elements of a `Vec`
+/// or the keys of a map. It's equivalent to `Hash` in every other way.
+///
+/// ```
+/// # #[cfg(feature = "std")] {
+/// use blake3::serde_hex::HexHash;
+///
+/// let hashes = vec![HexHash(blake3::hash(b"foo"))];
+/// let json = serde_json::to_string(&hashes).unwrap();
+/// assert_eq!(json, r#"["04e0bb39f30b1a3feb89f536c93be15055482df748674b00d26e5a75777702e9"]"#);
+/// let parsed: Vec<HexHash> = serde_json::from_str(&json).unwrap();
+/// assert_eq!(parsed, hashes);
+/// # }
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, core::hash::Hash)]
+pub struct HexHash(pub Hash);
+
+impl From<Hash> for HexHash {
+    #[inline]
+    fn from(hash: Hash) -> Self {
+        Self(hash)
+    }
+}
+
+impl From<HexHash> for Hash {
+    #[inline]
+    fn from(hex_hash: HexHash) -> Self {
+        hex_hash.0
+    }
+}
+
+impl fmt::Display for HexHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl serde::Serialize for HexHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize(&self.0, serializer)
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for HexHash {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize::<Hash, D>(deserializer).map(Self)
+    }
+}
+
+/// Serde adapters for variable-length outputs, like a `Vec<u8>` filled from an
+/// [`OutputReader`](crate::OutputReader).
+///
+/// As in the [parent module](super), these serialize a lowercase hex string in human-readable
+/// formats and raw bytes otherwise, and deserialization also accepts an array of integers. Any
+/// length is allowed, including zero. This module is gated by the `std` feature.
+///
+/// ```
+/// #[derive(serde::Serialize, serde::Deserialize)]
+/// struct Subkey {
+///     #[serde(with = "blake3::serde_hex::bytes")]
+///     key: Vec<u8>,
+/// }
+///
+/// let mut key = vec![0; 5];
+/// blake3::derive_key_xof("example.com 2019-12-25 16:18:03 session tokens v1", b"key material")
+///     .fill(&mut key);
+/// let json = serde_json::to_string(&Subkey { key: key.clone() }).unwrap();
+/// let parsed: Subkey = serde_json::from_str(&json).unwrap();
+/// assert_eq!(parsed.key, key);
+/// ```
+#[cfg(feature = "std")]
+pub mod bytes {
+    use super::*;
+
+    /// Serialize bytes as a hex string or as bytes. See the [module docs](self).
+    pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        T: AsRef<[u8]> + ?Sized,
+        S: Serializer,
+    {
+        let bytes = value.as_ref();
+        if serializer.is_human_readable() {
+            const TABLE: &[u8; 16] = b"0123456789abcdef";
+            let mut hex = String::with_capacity(2 * bytes.len());
+            for &b in bytes {
+                hex.push(TABLE[(b >> 4) as usize] as char);
+                hex.push(TABLE[(b & 0xf) as usize] as char);
+            }
+            serializer.serialize_str(&hex)
+        } else {
+            serializer.serialize_bytes(bytes)
+        }
+    }
+
+    /// Deserialize bytes from a hex string, bytes, or an array of integers. See the [module
+    /// docs](self).
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: From<Vec<u8>>,
+        D: Deserializer<'de>,
+    {
+        let bytes = if deserializer.is_human_readable() {
+            deserializer.deserialize_any(VecVisitor)?
+        } else {
+            deserializer.deserialize_byte_buf(VecVisitor)?
+        };
+        Ok(T::from(bytes))
+    }
+
+    struct VecVisitor;
+
+    impl<'de> Visitor<'de> for VecVisitor {
+        type Value = Vec<u8>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a hex string or bytes")
+        }
+
+        fn visit_str<E: Error>(self, v: &str) -> Result<Self::Value, E> {
+            fn hex_val(byte: u8) -> Option<u8> {
+                (byte as char).to_digit(16).map(|d| d as u8)
+            }
+            if v.len() % 2 != 0 {
+                return Err(E::custom("odd number of hex characters"));
+            }
+            v.as_bytes()
+                .chunks_exact(2)
+                .map(|pair| match (hex_val(pair[0]), hex_val(pair[1])) {
+                    (Some(high), Some(low)) => Ok(16 * high + low),
+                    _ => Err(E::invalid_value(serde::de::Unexpected::Str(v), &self)),
+                })
+                .collect()
+        }
+
+        fn visit_bytes<E: Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+            Ok(v.to_vec())
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+            while let Some(byte) = seq.next_element()? {
+                bytes.push(byte);
+            }
+            Ok(bytes)
+        }
+    }
+}
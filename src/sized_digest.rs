@@ -0,0 +1,196 @@
+use crate::{Hash, HexError, OUT_LEN};
+use core::fmt;
+
+#[cfg(feature = "zeroize")]
+use zeroize::Zeroize;
+
+/// An output of any fixed size `N`, which provides constant-time equality checking.
+///
+/// This is the variable-length counterpart of [`Hash`](struct@Hash), which is equivalent to
+/// `Digest<32>`. It's returned by [`Hasher::finalize_len`](crate::Hasher::finalize_len), and
+/// it's what you get from [`truncate`](Self::truncate) and [`Hash::truncate`]. Because BLAKE3
+/// output is a stream, a truncated digest is always a prefix of a longer one from the same input,
+/// so for example a `Digest<16>` identifier can be checked against a `Digest<64>` by truncating
+/// the latter.
+///
+/// As with `Hash`, `Digest` doesn't implement `Deref` or `AsRef`, to avoid losing the
+/// constant-time property through an implicit conversion. It implements [`Display`] and
+/// [`FromStr`] using lowercase hexadecimal, and there's a [`from_hex`](Self::from_hex) method.
+/// To get a hex string, use `to_string()` or the `{}` format specifier.
+///
+/// # Example
+///
+/// ```
+/// let mut hasher = blake3::Hasher::new();
+/// hasher.update(b"foo");
+/// let long_id: blake3::Digest<64> = hasher.finalize_len();
+/// let short_id: blake3::Digest<16> = hasher.finalize_len();
+/// assert_eq!(long_id.truncate::<16>(), short_id);
+/// assert_eq!(hasher.finalize().truncate::<16>(), short_id);
+/// # #[cfg(feature = "std")]
+/// assert_eq!(short_id.to_string(), "04e0bb39f30b1a3feb89f536c93be150");
+/// ```
+///
+/// [`Display`]: https://doc.rust-lang.org/std/fmt/trait.Display.html
+/// [`FromStr`]: https://doc.rust-lang.org/std/str/trait.FromStr.html
+#[derive(Clone, Copy, Eq)]
+pub struct Digest<const N: usize>([u8; N]);
+
+impl<const N: usize> Digest<N> {
+    /// The raw bytes of the `Digest`. Note that byte arrays don't provide constant-time equality
+    /// checking, so if you need to compare digests, prefer the `Digest` type.
+    #[inline]
+    pub const fn as_bytes(&self) -> &[u8; N] {
+        &self.0
+    }
+
+    /// Create a `Digest` from its raw bytes representation.
+    pub const fn from_bytes(bytes: [u8; N]) -> Self {
+        Self(bytes)
+    }
+
+    /// The raw bytes of the `Digest`, as a slice. Note that byte slices don't provide
+    /// constant-time equality checking.
+    #[inline]
+    pub const fn as_slice(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+
+    /// Create a `Digest` from its raw bytes representation as a slice.
+    ///
+    /// Returns an error if the slice is not exactly `N` bytes long.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, core::array::TryFromSliceError> {
+        Ok(Self::from_bytes(bytes.try_into()?))
+    }
+
+    /// Decode a `Digest` from hexadecimal. Both uppercase and lowercase ASCII bytes are
+    /// supported. An input length other than `2 * N` results in an error.
+    pub fn from_hex(hex: impl AsRef<[u8]>) -> Result<Self, HexError> {
+        crate::const_fn::bytes_from_hex(hex.as_ref()).map(Self)
+    }
+
+    /// Return the first `M` bytes of this digest. This is the same as finalizing the same input
+    /// with an output length of `M`.
+    ///
+    /// It's a compile-time error for `M` to be greater than `N`.
+    pub const fn truncate<const M: usize>(&self) -> Digest<M> {
+        const { assert!(M <= N, "can't truncate a digest to a longer length") };
+        let mut bytes = [0; M];
+        let mut i = 0;
+        while i < M {
+            bytes[i] = self.0[i];
+            i += 1;
+        }
+        Digest(bytes)
+    }
+}
+
+impl Hash {
+    /// Return the first `M` bytes of this hash as a [`Digest`]. This is the same as
+    /// [`Hasher::finalize_len`](crate::Hasher::finalize_len) with an output length of `M`.
+    ///
+    /// It's a compile-time error for `M` to be greater than 32.
+    pub const fn truncate<const M: usize>(&self) -> Digest<M> {
+        Digest::from_bytes(*self.as_bytes()).truncate()
+    }
+}
+
+impl<const N: usize> From<[u8; N]> for Digest<N> {
+    #[inline]
+    fn from(bytes: [u8; N]) -> Self {
+        Self::from_bytes(bytes)
+    }
+}
+
+impl<const N: usize> From<Digest<N>> for [u8; N] {
+    #[inline]
+    fn from(digest: Digest<N>) -> Self {
+        digest.0
+    }
+}
+
+impl From<Hash> for Digest<OUT_LEN> {
+    #[inline]
+    fn from(hash: Hash) -> Self {
+        Self::from_bytes(hash.into())
+    }
+}
+
+impl From<Digest<OUT_LEN>> for Hash {
+    #[inline]
+    fn from(digest: Digest<OUT_LEN>) -> Self {
+        Self::from_bytes(digest.0)
+    }
+}
+
+impl<const N: usize> core::str::FromStr for Digest<N> {
+    type Err = HexError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_hex(s)
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<const N: usize> Zeroize for Digest<N> {
+    fn zeroize(&mut self) {
+        // Destructuring to trigger compile error as a reminder to update this impl.
+        let Self(bytes) = self;
+        bytes.zeroize();
+    }
+}
+
+/// This implementation is constant-time.
+impl<const N: usize> PartialEq for Digest<N> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        constant_time_eq::constant_time_eq_n(&self.0, &other.0)
+    }
+}
+
+/// This implementation is constant-time.
+impl<const N: usize> PartialEq<[u8; N]> for Digest<N> {
+    #[inline]
+    fn eq(&self, other: &[u8; N]) -> bool {
+        constant_time_eq::constant_time_eq_n(&self.0, other)
+    }
+}
+
+/// This implementation is constant-time if the target is `N` bytes long.
+impl<const N: usize> PartialEq<[u8]> for Digest<N> {
+    #[inline]
+    fn eq(&self, other: &[u8]) -> bool {
+        constant_time_eq::constant_time_eq(&self.0, other)
+    }
+}
+
+impl<const N: usize> core::hash::Hash for Digest<N> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl<const N: usize> fmt::Display for Digest<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Unlike Hash::to_hex, there's no fixed-size buffer here, because ArrayString<{ 2 * N }>
+        // isn't expressible on stable Rust. Write 32 bytes of hex at a time instead.
+        let table = b"0123456789abcdef";
+        for chunk in self.0.chunks(32) {
+            let mut buf = [0; 64];
+            for (i, &b) in chunk.iter().enumerate() {
+                buf[2 * i] = table[(b >> 4) as usize];
+                buf[2 * i + 1] = table[(b & 0xf) as usize];
+            }
+            f.write_str(core::str::from_utf8(&buf[..2 * chunk.len()]).unwrap())?;
+        }
+        Ok(())
+    }
+}
+
+impl<const N: usize> fmt::Debug for Digest<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("Digest")
+            .field(&format_args!("\"{}\"", self))
+            .finish()
+    }
+}
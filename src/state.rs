@@ -0,0 +1,614 @@
+//! Saving and restoring the state of a [`Hasher`].
+//!
+//! The byte format of a [`HasherState`] is stable, and future versions of this crate will keep
+//! accepting version 1 snapshots. All integers are little-endian:
+//!
+//! | field                   | size        | notes                                            |
+//! |-------------------------|-------------|--------------------------------------------------|
+//! | magic                   | 4           | `b"B3HS"`                                        |
+//! | version                 | 1           | currently 1                                      |
+//! | mode flags              | 1           | 0 for hash, 16 for keyed_hash, 64 for derive_key |
+//! | key fingerprint         | 8           | see below                                        |
+//! | initial chunk counter   | 8           | nonzero only with `set_input_offset`             |
+//! | chunk counter           | 8           |                                                  |
+//! | chunk chaining value    | 32          |                                                  |
+//! | blocks compressed       | 1           | within the current chunk                         |
+//! | block buffer length     | 1           |                                                  |
+//! | block buffer            | 64          | zero-padded                                      |
+//! | CV stack length         | 1           | at most 55                                       |
+//! | CV stack                | 32 per CV   |                                                  |
+//! | checksum                | 8           | the first 8 bytes of the BLAKE3 hash of the rest |
+//!
+//! The key fingerprint is the first 8 bytes of a [`derive_key`](crate::derive_key) output, with
+//! the key (or the IV in the default hash mode, or the context key in the key derivation mode) as
+//! the key material. It lets [`Hasher::restore_state`] catch snapshots taken with a different key
+//! or context string, without storing the key itself in the snapshot.
+
+use crate::{
+    CHUNK_LEN, ChunkState, DERIVE_KEY_MATERIAL, Hasher, KEYED_HASH, MAX_DEPTH, OUT_LEN, Platform,
+    platform,
+};
+use arrayref::array_ref;
+use arrayvec::ArrayVec;
+use core::fmt;
+
+const MAGIC: &[u8; 4] = b"B3HS";
+const VERSION: u8 = 1;
+const KEY_FINGERPRINT_CONTEXT: &str = "BLAKE3 2025-06-01 Hasher state key fingerprint v1";
+const KEY_FINGERPRINT_LEN: usize = 8;
+const CHECKSUM_LEN: usize = 8;
+// Everything before the CV stack.
+const FIXED_LEN: usize = 4 + 1 + 1 + KEY_FINGERPRINT_LEN + 8 + 8 + OUT_LEN + 1 + 1 + 64 + 1;
+
+// The maximum size in bytes of a serialized HasherState.
+const MAX_STATE_LEN: usize = FIXED_LEN + (MAX_DEPTH + 1) * OUT_LEN + CHECKSUM_LEN;
+
+/// A snapshot of a [`Hasher`], returned by [`Hasher::save_state`].
+///
+/// `HasherState` is a validated byte string in a stable, versioned format. Use
+/// [`as_bytes`](HasherState::as_bytes) to store it and [`from_bytes`](HasherState::from_bytes) to
+/// load it again, possibly in a different process or on a different machine. When the `serde`
+/// feature is enabled, it also implements `Serialize` and `Deserialize` as a byte string.
+///
+/// The snapshot includes buffered input and chaining values derived from the input, so it's as
+/// sensitive as the input itself. It includes a checksum for catching accidental corruption, but
+/// it isn't authenticated. If an attacker can modify snapshots, you need a MAC on top.
+#[derive(Clone, PartialEq, Eq)]
+pub struct HasherState {
+    bytes: ArrayVec<u8, MAX_STATE_LEN>,
+}
+
+impl HasherState {
+    /// The serialized bytes of the snapshot.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Parse and validate a serialized snapshot.
+    ///
+    /// This checks the format version, the checksum, and the internal consistency of the saved
+    /// state. It doesn't check the mode or the key; [`Hasher::restore_state`] does that.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, StateError> {
+        if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(StateError::InvalidMagic);
+        }
+        if bytes.len() < FIXED_LEN + CHECKSUM_LEN {
+            return Err(StateError::InvalidLength(bytes.len()));
+        }
+        if bytes[4] != VERSION {
+            return Err(StateError::UnsupportedVersion(bytes[4]));
+        }
+        let stack_len = bytes[FIXED_LEN - 1] as usize;
+        if stack_len > MAX_DEPTH + 1 {
+            return Err(StateError::Corrupt);
+        }
+        if bytes.len() != FIXED_LEN + stack_len * OUT_LEN + CHECKSUM_LEN {
+            return Err(StateError::InvalidLength(bytes.len()));
+        }
+        let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_LEN);
+        if crate::hash(body).as_bytes()[..CHECKSUM_LEN] != *checksum {
+            return Err(StateError::Corrupt);
+        }
+        let state = Self {
+            bytes: bytes.try_into().unwrap(),
+        };
+        state.check_invariants()?;
+        Ok(state)
+    }
+
+    fn flags(&self) -> u8 {
+        self.bytes[5]
+    }
+
+    fn key_fingerprint(&self) -> &[u8; KEY_FINGERPRINT_LEN] {
+        array_ref!(self.bytes, 6, KEY_FINGERPRINT_LEN)
+    }
+
+    fn initial_chunk_counter(&self) -> u64 {
+        u64::from_le_bytes(*array_ref!(self.bytes, 14, 8))
+    }
+
+    fn chunk_counter(&self) -> u64 {
+        u64::from_le_bytes(*array_ref!(self.bytes, 22, 8))
+    }
+
+    fn chunk_cv(&self) -> &[u8; OUT_LEN] {
+        array_ref!(self.bytes, 30, OUT_LEN)
+    }
+
+    fn blocks_compressed(&self) -> u8 {
+        self.bytes[62]
+    }
+
+    fn buf_len(&self) -> u8 {
+        self.bytes[63]
+    }
+
+    fn buf(&self) -> &[u8; 64] {
+        array_ref!(self.bytes, 64, 64)
+    }
+
+    fn cv_stack(&self) -> impl Iterator<Item = &[u8; OUT_LEN]> {
+        self.bytes[FIXED_LEN..self.bytes.len() - CHECKSUM_LEN]
+            .chunks_exact(OUT_LEN)
+            .map(|cv| array_ref!(cv, 0, OUT_LEN))
+    }
+
+    // The checksum catches accidents, but these checks also make sure that a snapshot can't
+    // violate the invariants that Hasher relies on, even if it was constructed deliberately. A
+    // forged snapshot can still produce a wrong hash, but it can't make Hasher panic or overflow.
+    fn check_invariants(&self) -> Result<(), StateError> {
+        let stack_len = self.cv_stack().count();
+        let chunk_len = 64 * self.blocks_compressed() as usize + self.buf_len() as usize;
+        let ok = matches!(self.flags(), 0 | KEYED_HASH | DERIVE_KEY_MATERIAL)
+            && self.buf_len() <= 64
+            && chunk_len <= CHUNK_LEN
+            // A full block is only buffered at the end of a chunk, not compressed, so the block
+            // count is at most 15.
+            && (self.blocks_compressed() as usize) < CHUNK_LEN / 64
+            && self.buf()[self.buf_len() as usize..].iter().all(|&b| b == 0)
+            && self.chunk_counter() >= self.initial_chunk_counter()
+            // The input offset and Hasher::count can't overflow.
+            && self
+                .chunk_counter()
+                .checked_mul(CHUNK_LEN as u64)
+                .and_then(|len| len.checked_add(chunk_len as u64))
+                .is_some()
+            // After set_input_offset, the input can't outgrow the subtree at that offset.
+            && crate::hazmat::max_subtree_len(self.initial_chunk_counter() * CHUNK_LEN as u64)
+                .is_none_or(|max_len| {
+                    let chunks = self.chunk_counter() - self.initial_chunk_counter();
+                    chunks * CHUNK_LEN as u64 + chunk_len as u64 <= max_len
+                })
+            && match (self.chunk_counter() - self.initial_chunk_counter(), chunk_len) {
+                // No complete chunks means no chaining values in the stack.
+                (0, _) => stack_len == 0,
+                // With partial chunk input, update() has already merged the stack.
+                (chunks, 1..) => stack_len == chunks.count_ones() as usize,
+                // Otherwise update() ended by pushing the two halves of a subtree without merging
+                // them, so there's exactly one extra CV. Finalization needs at least two.
+                (chunks, 0) => stack_len == chunks.count_ones() as usize + 1,
+            };
+        if ok { Ok(()) } else { Err(StateError::Corrupt) }
+    }
+}
+
+// Don't derive(Debug), because the state may be secret.
+impl fmt::Debug for HasherState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HasherState")
+            .field("len", &self.bytes.len())
+            .finish()
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl zeroize::Zeroize for HasherState {
+    fn zeroize(&mut self) {
+        // Destructuring to trigger compile error as a reminder to update this impl.
+        let Self { bytes } = self;
+
+        bytes.zeroize();
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for HasherState {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.as_bytes())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for HasherState {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct StateVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for StateVisitor {
+            type Value = HasherState;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a serialized BLAKE3 hasher state")
+            }
+
+            fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<HasherState, E> {
+                HasherState::from_bytes(bytes).map_err(E::custom)
+            }
+
+            // Formats without a native byte string type, like JSON, use a sequence of integers.
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<HasherState, A::Error> {
+                let mut bytes = ArrayVec::<u8, MAX_STATE_LEN>::new();
+                while let Some(byte) = seq.next_element()? {
+                    bytes.try_push(byte).map_err(|_| {
+                        serde::de::Error::custom(StateError::InvalidLength(MAX_STATE_LEN + 1))
+                    })?;
+                }
+                HasherState::from_bytes(&bytes).map_err(serde::de::Error::custom)
+            }
+        }
+
+        deserializer.deserialize_bytes(StateVisitor)
+    }
+}
+
+/// The error type for [`HasherState::from_bytes`] and [`Hasher::restore_state`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StateError {
+    /// The bytes don't start with the `HasherState` magic number.
+    InvalidMagic,
+    /// The snapshot uses a format version that this version of the crate doesn't support.
+    UnsupportedVersion(u8),
+    /// The snapshot is the wrong length for its header.
+    InvalidLength(usize),
+    /// The checksum didn't match, or the saved state is internally inconsistent.
+    Corrupt,
+    /// The snapshot was taken in a different mode (hash, keyed hash, or derive key), or with a
+    /// different key or context string, than the `Hasher` it's being restored into.
+    ModeMismatch,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::InvalidMagic => write!(f, "not a BLAKE3 hasher state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "unsupported hasher state version: {version}")
+            }
+            StateError::InvalidLength(len) => write!(f, "invalid hasher state length: {len}"),
+            StateError::Corrupt => write!(f, "corrupt hasher state"),
+            StateError::ModeMismatch => write!(f, "hasher state mode or key mismatch"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for StateError {}
+
+fn key_fingerprint(key: &crate::CVWords) -> [u8; KEY_FINGERPRINT_LEN] {
+    let key_bytes = platform::le_bytes_from_words_32(key);
+    let derived = crate::derive_key(KEY_FINGERPRINT_CONTEXT, &key_bytes);
+    *array_ref!(derived, 0, KEY_FINGERPRINT_LEN)
+}
+
+impl Hasher {
+    /// Save a snapshot of the current state.
+    ///
+    /// Restoring the snapshot with [`restore_state`](Hasher::restore_state), possibly in another
+    /// process, and continuing with the same input gives the same final output as if the
+    /// `Hasher` had never stopped. See [`HasherState`] for the format.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # fn main() -> Result<(), blake3::StateError> {
+    /// let mut hasher = blake3::Hasher::new_keyed(&[42; 32]);
+    /// hasher.update(b"foo");
+    /// let saved: Vec<u8> = hasher.save_state().as_bytes().to_vec();
+    ///
+    /// // Later, maybe in another process...
+    /// let state = blake3::HasherState::from_bytes(&saved)?;
+    /// let mut resumed = blake3::Hasher::new_keyed(&[42; 32]);
+    /// resumed.restore_state(&state)?;
+    /// resumed.update(b"bar");
+    /// assert_eq!(resumed.finalize(), blake3::keyed_hash(&[42; 32], b"foobar"));
+    ///
+    /// // Restoring into a Hasher with a different key is an error.
+    /// assert!(blake3::Hasher::new().restore_state(&state).is_err());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn save_state(&self) -> HasherState {
+        let mut bytes = ArrayVec::<u8, MAX_STATE_LEN>::new();
+        let chunk_state = &self.chunk_state;
+        bytes.try_extend_from_slice(MAGIC).unwrap();
+        bytes.push(VERSION);
+        bytes.push(chunk_state.flags);
+        bytes
+            .try_extend_from_slice(&key_fingerprint(&self.key))
+            .unwrap();
+        bytes
+            .try_extend_from_slice(&self.initial_chunk_counter.to_le_bytes())
+            .unwrap();
+        bytes
+            .try_extend_from_slice(&chunk_state.chunk_counter.to_le_bytes())
+            .unwrap();
+        bytes
+            .try_extend_from_slice(&platform::le_bytes_from_words_32(&chunk_state.cv))
+            .unwrap();
+        bytes.push(chunk_state.blocks_compressed);
+        bytes.push(chunk_state.buf_len);
+        bytes.try_extend_from_slice(&chunk_state.buf).unwrap();
+        bytes.push(self.cv_stack.len() as u8);
+        for cv in &self.cv_stack {
+            bytes.try_extend_from_slice(cv).unwrap();
+        }
+        let checksum = crate::hash(&bytes);
+        bytes
+            .try_extend_from_slice(&checksum.as_bytes()[..CHECKSUM_LEN])
+            .unwrap();
+        HasherState { bytes }
+    }
+
+    /// Restore a snapshot from [`save_state`](Hasher::save_state), replacing the current state.
+    ///
+    /// The `Hasher` must have been constructed the same way as the one that saved the snapshot,
+    /// with the same key for [`new_keyed`](Hasher::new_keyed) or the same context string for
+    /// [`new_derive_key`](Hasher::new_derive_key). Otherwise this returns
+    /// [`StateError::ModeMismatch`] and leaves the `Hasher` unchanged.
+    pub fn restore_state(&mut self, state: &HasherState) -> Result<&mut Self, StateError> {
+        if state.flags() != self.chunk_state.flags
+            || *state.key_fingerprint() != key_fingerprint(&self.key)
+        {
+            return Err(StateError::ModeMismatch);
+        }
+        let platform = Platform::detect();
+        self.chunk_state = ChunkState {
+            cv: platform::words_from_le_bytes_32(state.chunk_cv()),
+            chunk_counter: state.chunk_counter(),
+            buf: *state.buf(),
+            buf_len: state.buf_len(),
+            blocks_compressed: state.blocks_compressed(),
+            flags: state.flags(),
+            platform,
+        };
+        self.initial_chunk_counter = state.initial_chunk_counter();
+        self.cv_stack.clear();
+        self.cv_stack.extend(state.cv_stack().copied());
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hazmat::HasherExt;
+    use crate::test::{TEST_CASES, TEST_CASES_MAX, TEST_KEY, paint_test_input};
+
+    fn resume(hasher: &Hasher, fresh: Hasher) -> Hasher {
+        let saved = hasher.save_state();
+        let loaded = HasherState::from_bytes(saved.as_bytes()).unwrap();
+        assert_eq!(saved, loaded);
+        let mut resumed = fresh;
+        resumed.restore_state(&loaded).unwrap();
+        resumed
+    }
+
+    #[test]
+    fn test_save_and_restore() {
+        let mut input = [0; TEST_CASES_MAX];
+        paint_test_input(&mut input);
+        for &case in TEST_CASES {
+            let input = &input[..case];
+            let expected = crate::hash(input);
+            let expected_keyed = crate::keyed_hash(&TEST_KEY, input);
+            let expected_derived = crate::derive_key("state test", input);
+            for &split in TEST_CASES {
+                if split > case {
+                    continue;
+                }
+                let mut hasher = Hasher::new();
+                hasher.update(&input[..split]);
+                let mut resumed = resume(&hasher, Hasher::new());
+                resumed.update(&input[split..]);
+                assert_eq!(expected, resumed.finalize(), "case {case} split {split}");
+
+                let mut hasher = Hasher::new_keyed(&TEST_KEY);
+                hasher.update(&input[..split]);
+                let mut resumed = resume(&hasher, Hasher::new_keyed(&TEST_KEY));
+                resumed.update(&input[split..]);
+                assert_eq!(expected_keyed, resumed.finalize());
+
+                let mut hasher = Hasher::new_derive_key("state test");
+                hasher.update(&input[..split]);
+                let mut resumed = resume(&hasher, Hasher::new_derive_key("state test"));
+                resumed.update(&input[split..]);
+                assert_eq!(expected_derived, *resumed.finalize().as_bytes());
+            }
+        }
+    }
+
+    #[test]
+    fn test_restore_input_offset() {
+        let mut input = [0; 4 * CHUNK_LEN];
+        paint_test_input(&mut input);
+        let expected = Hasher::new()
+            .set_input_offset(4 * CHUNK_LEN as u64)
+            .update(&input)
+            .finalize_non_root();
+        let mut hasher = Hasher::new();
+        hasher
+            .set_input_offset(4 * CHUNK_LEN as u64)
+            .update(&input[..1500]);
+        let mut resumed = resume(&hasher, Hasher::new());
+        resumed.update(&input[1500..]);
+        assert_eq!(expected, resumed.finalize_non_root());
+    }
+
+    #[test]
+    fn test_mode_mismatch() {
+        let state = Hasher::new_keyed(&TEST_KEY).update(b"foo").save_state();
+        for mut wrong in [
+            Hasher::new(),
+            Hasher::new_keyed(&[0; 32]),
+            Hasher::new_derive_key("foo"),
+        ] {
+            let before = wrong.save_state();
+            assert_eq!(
+                wrong.restore_state(&state).unwrap_err(),
+                StateError::ModeMismatch,
+            );
+            // A failed restore doesn't change the Hasher.
+            assert_eq!(before, wrong.save_state());
+        }
+        let state = Hasher::new_derive_key("foo").save_state();
+        assert_eq!(
+            Hasher::new_derive_key("bar")
+                .restore_state(&state)
+                .unwrap_err(),
+            StateError::ModeMismatch,
+        );
+    }
+
+    #[test]
+    fn test_corrupt_states() {
+        let mut input = [0; 3 * CHUNK_LEN + 100];
+        paint_test_input(&mut input);
+        let state = Hasher::new().update(&input).save_state();
+        let bytes = state.as_bytes();
+
+        // Flipping any bit is caught, by the magic number, the version, the length, or the
+        // checksum.
+        for i in 0..bytes.len() {
+            let mut bad = ArrayVec::<u8, MAX_STATE_LEN>::new();
+            bad.try_extend_from_slice(bytes).unwrap();
+            bad[i] ^= 1;
+            assert!(HasherState::from_bytes(&bad).is_err(), "byte {i}");
+        }
+        for len in 0..bytes.len() {
+            assert!(HasherState::from_bytes(&bytes[..len]).is_err(), "len {len}");
+        }
+        assert_eq!(
+            HasherState::from_bytes(b"nope").unwrap_err(),
+            StateError::InvalidMagic,
+        );
+
+        // A future version is rejected even with a valid checksum.
+        let mut future = ArrayVec::<u8, MAX_STATE_LEN>::new();
+        future
+            .try_extend_from_slice(&bytes[..bytes.len() - CHECKSUM_LEN])
+            .unwrap();
+        future[4] = 2;
+        let checksum = crate::hash(&future);
+        future
+            .try_extend_from_slice(&checksum.as_bytes()[..CHECKSUM_LEN])
+            .unwrap();
+        assert_eq!(
+            HasherState::from_bytes(&future).unwrap_err(),
+            StateError::UnsupportedVersion(2),
+        );
+
+        // An inconsistent state is rejected even with a valid checksum. Here the buffer length
+        // claims there's more than a chunk of partial input.
+        let mut inconsistent = ArrayVec::<u8, MAX_STATE_LEN>::new();
+        inconsistent
+            .try_extend_from_slice(&bytes[..bytes.len() - CHECKSUM_LEN])
+            .unwrap();
+        inconsistent[63] = 65;
+        let checksum = crate::hash(&inconsistent);
+        inconsistent
+            .try_extend_from_slice(&checksum.as_bytes()[..CHECKSUM_LEN])
+            .unwrap();
+        assert_eq!(
+            HasherState::from_bytes(&inconsistent).unwrap_err(),
+            StateError::Corrupt,
+        );
+    }
+
+    type StateBytes = ArrayVec<u8, MAX_STATE_LEN>;
+
+    // Rewrite fields of a valid snapshot and fix up its checksum and length, so that only
+    // check_invariants can catch the change.
+    fn forge(state: &HasherState, edit: &dyn Fn(&mut StateBytes)) -> StateBytes {
+        let bytes = state.as_bytes();
+        let mut forged = StateBytes::new();
+        forged
+            .try_extend_from_slice(&bytes[..bytes.len() - CHECKSUM_LEN])
+            .unwrap();
+        edit(&mut forged);
+        forged[FIXED_LEN - 1] = ((forged.len() - FIXED_LEN) / OUT_LEN) as u8;
+        let checksum = crate::hash(&forged);
+        forged
+            .try_extend_from_slice(&checksum.as_bytes()[..CHECKSUM_LEN])
+            .unwrap();
+        forged
+    }
+
+    #[test]
+    fn test_forged_states() {
+        let mut input = [0; 2 * CHUNK_LEN];
+        paint_test_input(&mut input);
+        let partial = Hasher::new().update(&input[..CHUNK_LEN + 100]).save_state();
+        let lazy = Hasher::new().update(&input).save_state();
+        // Sanity check that forge() itself doesn't break anything.
+        HasherState::from_bytes(&forge(&partial, &|_| {})).unwrap();
+        HasherState::from_bytes(&forge(&lazy, &|_| {})).unwrap();
+
+        let set_u64 = |bytes: &mut StateBytes, offset: usize, n: u64| {
+            bytes[offset..][..8].copy_from_slice(&n.to_le_bytes());
+        };
+        let assert_corrupt = |state: &HasherState, edit: &dyn Fn(&mut StateBytes)| {
+            assert_eq!(
+                HasherState::from_bytes(&forge(state, edit)).unwrap_err(),
+                StateError::Corrupt,
+            );
+        };
+        // Hasher::count would overflow.
+        assert_corrupt(&partial, &|b| set_u64(b, 22, 1 << 60));
+        assert_corrupt(&partial, &|b| {
+            set_u64(b, 14, 1 << 59);
+            set_u64(b, 22, 1 << 60);
+        });
+        // With an input offset of 1 chunk, the subtree is at most 1 chunk long.
+        assert_corrupt(&partial, &|b| {
+            set_u64(b, 14, 1);
+            set_u64(b, 22, 2);
+        });
+        // Too many CVs for the number of chunks.
+        assert_corrupt(&lazy, &|b| {
+            for _ in 0..40 {
+                b.try_extend_from_slice(&[0; OUT_LEN]).unwrap();
+            }
+        });
+        // A lazily merged stack with no extra CV, which finalization would index out of bounds.
+        assert_corrupt(&lazy, &|b| b.truncate(FIXED_LEN + OUT_LEN));
+        assert_corrupt(&lazy, &|b| b.truncate(FIXED_LEN));
+    }
+
+    #[test]
+    fn test_format_is_stable() {
+        // If this test fails, the format changed, and we need to bump the version.
+        let state = Hasher::new().update(b"abc").save_state();
+        let bytes = state.as_bytes();
+        assert_eq!(bytes.len(), FIXED_LEN + CHECKSUM_LEN);
+        assert_eq!(&bytes[..6], b"B3HS\x01\x00");
+        assert_eq!(&bytes[14..30], &[0; 16]);
+        assert_eq!(bytes[62], 0);
+        assert_eq!(bytes[63], 3);
+        assert_eq!(&bytes[64..67], b"abc");
+        assert_eq!(bytes[FIXED_LEN - 1], 0);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    #[cfg(feature = "serde")]
+    fn test_serde() {
+        let mut input = [0; 5 * CHUNK_LEN + 7];
+        paint_test_input(&mut input);
+        let mut hasher = Hasher::new();
+        hasher.update(&input[..2 * CHUNK_LEN + 3]);
+        let state = hasher.save_state();
+
+        let json = serde_json::to_string(&state).unwrap();
+        let from_json: HasherState = serde_json::from_str(&json).unwrap();
+        assert_eq!(state, from_json);
+
+        let mut cbor = Vec::<u8>::new();
+        ciborium::into_writer(&state, &mut cbor).unwrap();
+        let from_cbor: HasherState = ciborium::from_reader(&cbor[..]).unwrap();
+        assert_eq!(state, from_cbor);
+
+        let mut resumed = Hasher::new();
+        resumed.restore_state(&from_cbor).unwrap();
+        resumed.update(&input[2 * CHUNK_LEN + 3..]);
+        assert_eq!(resumed.finalize(), crate::hash(&input));
+
+        // Corrupt input is a deserialization error.
+        let mut bad_cbor = cbor.clone();
+        *bad_cbor.last_mut().unwrap() ^= 1;
+        assert!(ciborium::from_reader::<HasherState, _>(&bad_cbor[..]).is_err());
+    }
+}
@@ -210,6 +210,85 @@ pub fn test_hash_many_fn(
     }
 }
 
+#[allow(unused)]
+type CompressManyFn = unsafe fn(
+    cvs: &mut [u8],
+    blocks: &[&[u8; BLOCK_LEN]],
+    block_lens: &[u8],
+    counter: u64,
+    flags: u8,
+);
+
+// A shared helper function for platform-specific tests.
+#[allow(unused)]
+pub fn test_compress_many_fn(compress_many_fn: CompressManyFn) {
+    // 31 (16 + 8 + 4 + 2 + 1) blocks, with lengths spread from 0 to 64.
+    const NUM_BLOCKS: usize = 31;
+    let mut input_buf = [0; NUM_BLOCKS + BLOCK_LEN];
+    paint_test_input(&mut input_buf);
+    let mut blocks = [[0; BLOCK_LEN]; NUM_BLOCKS];
+    let mut block_lens = [0; NUM_BLOCKS];
+    for i in 0..NUM_BLOCKS {
+        let block_len = i * BLOCK_LEN / (NUM_BLOCKS - 1);
+        blocks[i][..block_len].copy_from_slice(&input_buf[i..][..block_len]);
+        block_lens[i] = block_len as u8;
+    }
+    let block_refs: ArrayVec<&[u8; BLOCK_LEN], NUM_BLOCKS> = blocks.iter().collect();
+    let mut cvs = [0; NUM_BLOCKS * OUT_LEN];
+    paint_test_input(&mut cvs);
+    let flags = crate::CHUNK_END | crate::ROOT | crate::KEYED_HASH;
+
+    // The same counter values as in test_hash_many_fn.
+    let initial_counters = [0, u32::MAX as u64, i32::MAX as u64];
+    for counter in initial_counters {
+        #[cfg(feature = "std")]
+        dbg!(counter);
+
+        let mut portable_cvs = cvs;
+        for (i, cv) in portable_cvs.chunks_exact_mut(OUT_LEN).enumerate() {
+            let mut cv_words = crate::platform::words_from_le_bytes_32(array_ref!(cv, 0, OUT_LEN));
+            crate::portable::compress_in_place(
+                &mut cv_words,
+                &blocks[i],
+                block_lens[i],
+                counter,
+                flags,
+            );
+            cv.copy_from_slice(&crate::platform::le_bytes_from_words_32(&cv_words));
+        }
+
+        let mut test_cvs = cvs;
+        unsafe { compress_many_fn(&mut test_cvs, &block_refs, &block_lens, counter, flags) };
+        assert_eq!(portable_cvs, test_cvs);
+    }
+}
+
+#[test]
+fn test_platform_compress_many() {
+    // This covers the fallback loop, and whichever implementation detect() picks.
+    unsafe fn portable_compress_many(
+        cvs: &mut [u8],
+        blocks: &[&[u8; BLOCK_LEN]],
+        block_lens: &[u8],
+        counter: u64,
+        flags: u8,
+    ) {
+        crate::platform::Platform::portable()
+            .compress_many(cvs, blocks, block_lens, counter, flags);
+    }
+    unsafe fn detected_compress_many(
+        cvs: &mut [u8],
+        blocks: &[&[u8; BLOCK_LEN]],
+        block_lens: &[u8],
+        counter: u64,
+        flags: u8,
+    ) {
+        crate::platform::Platform::detect().compress_many(cvs, blocks, block_lens, counter, flags);
+    }
+    test_compress_many_fn(portable_compress_many);
+    test_compress_many_fn(detected_compress_many);
+}
+
 #[allow(unused)]
 type XofManyFunction = unsafe fn(
     cv: &CVWords,
@@ -364,6 +443,14 @@ fn test_compare_reference_impl() {
                 assert_eq!(hasher.finalize(), *array_ref!(expected_out, 0, 32));
                 assert_eq!(hasher.finalize(), test_out);
             }
+            // incremental (scoped threads)
+            #[cfg(feature = "std")]
+            {
+                let mut hasher = crate::Hasher::new();
+                hasher.update_with_join::<crate::join::ScopedThreadJoin>(input);
+                assert_eq!(hasher.finalize(), *array_ref!(expected_out, 0, 32));
+                assert_eq!(hasher.finalize(), test_out);
+            }
             // xof
             let mut extended = [0; OUT];
             hasher.finalize_xof().fill(&mut extended);
@@ -624,6 +711,117 @@ fn test_xof_seek() {
     }
 }
 
+#[test]
+fn test_xor_into() {
+    const LEN: usize = 5 * crate::platform::MAX_SIMD_DEGREE * BLOCK_LEN + 99;
+    let mut keystream = [0; LEN];
+    crate::Hasher::new_keyed(&[7; 32])
+        .finalize_xof()
+        .fill(&mut keystream);
+
+    // Exercise partial blocks at both ends, and more than one internal keystream buffer.
+    for (start, end) in [(0, LEN), (0, 1), (1, 64), (63, 65), (64, 200), (5, LEN - 5)] {
+        let mut buf = [0x5a; LEN];
+        let mut reader = crate::Hasher::new_keyed(&[7; 32]).finalize_xof();
+        reader.set_position(start as u64);
+        reader.xor_into(&mut buf[start..end]);
+        assert_eq!(reader.position(), end as u64);
+        for i in start..end {
+            assert_eq!(
+                buf[i],
+                0x5a ^ keystream[i],
+                "start {start} end {end} byte {i}"
+            );
+        }
+
+        // Applying the keystream again undoes it.
+        reader.set_position(start as u64);
+        reader.xor_into(&mut buf[start..end]);
+        assert!(buf.iter().all(|&b| b == 0x5a));
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_fill_with_join() {
+    fn check<J: crate::join::Join>(expected: &[u8], start: usize, len: usize) {
+        let mut reader = crate::Hasher::new().update(b"foo").finalize_xof();
+        reader.set_position(start as u64);
+        let mut out = vec![0; len];
+        reader.fill_with_join::<J>(&mut out);
+        assert_eq!(
+            &expected[start..][..len],
+            &out[..],
+            "start {start} len {len}"
+        );
+        assert_eq!(reader.position(), (start + len) as u64);
+    }
+
+    // Several times XOF_JOIN_LEAF_LEN, so that the output gets split.
+    const MAX_LEN: usize = 100_000;
+    let mut expected = vec![0; 2 * BLOCK_LEN + MAX_LEN];
+    crate::Hasher::new()
+        .update(b"foo")
+        .finalize_xof()
+        .fill(&mut expected);
+    for start in [0, 1, 63, 64, 65, 2 * BLOCK_LEN] {
+        for len in [0, 1, 64, 1000, MAX_LEN - 1, MAX_LEN] {
+            check::<crate::join::SerialJoin>(&expected, start, len);
+            check::<crate::join::ScopedThreadJoin>(&expected, start, len);
+            #[cfg(feature = "rayon")]
+            check::<crate::join::RayonJoin>(&expected, start, len);
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    {
+        let mut reader = crate::Hasher::new().update(b"foo").finalize_xof();
+        reader.set_position(5);
+        let mut out = vec![0; MAX_LEN];
+        reader.fill_rayon(&mut out);
+        assert_eq!(&expected[5..][..MAX_LEN], &out[..]);
+        assert_eq!(reader.position(), 5 + MAX_LEN as u64);
+    }
+}
+
+#[test]
+#[cfg(feature = "std")]
+fn test_hash_batch() {
+    const KEY: &[u8; 32] = b"whats the Elvish word for friend";
+    const CONTEXT: &str = "BLAKE3 2019-12-27 16:29:52 test vectors context";
+    // Every length up to a bit more than one chunk, lengths of every shape up to past the 4 KiB
+    // batching limit, and repeats of a few lengths, in an interleaved order, so that the buckets
+    // for each shape are split up and some fill every SIMD lane.
+    let mut buf = [0; 5 * CHUNK_LEN + 1];
+    paint_test_input(&mut buf);
+    let mut lengths: Vec<usize> = (0..=CHUNK_LEN + 2 * BLOCK_LEN).collect();
+    lengths.extend((CHUNK_LEN..=5 * CHUNK_LEN).step_by(BLOCK_LEN / 2 - 1));
+    lengths.extend((1..=5).flat_map(|chunks| [chunks * CHUNK_LEN - 1, chunks * CHUNK_LEN + 1]));
+    lengths.extend((0..=4 * CHUNK_LEN).step_by(BLOCK_LEN).cycle().take(300));
+    lengths.sort_by_key(|len| len % 7);
+    let inputs: Vec<&[u8]> = lengths.iter().map(|&len| &buf[..len]).collect();
+
+    let mut hashes = vec![crate::Hash::from_bytes([0; 32]); inputs.len()];
+    crate::hash_batch(&inputs, &mut hashes);
+    let mut keyed_hashes = hashes.clone();
+    crate::keyed_hash_batch(KEY, &inputs, &mut keyed_hashes);
+    let mut derived_keys = vec![[0; 32]; inputs.len()];
+    crate::derive_key_batch(CONTEXT, &inputs, &mut derived_keys);
+    for (i, input) in inputs.iter().enumerate() {
+        assert_eq!(hashes[i], crate::hash(input), "len {}", input.len());
+        assert_eq!(keyed_hashes[i], crate::keyed_hash(KEY, input));
+        assert_eq!(derived_keys[i], crate::derive_key(CONTEXT, input));
+    }
+
+    crate::hash_batch(&[], &mut []);
+}
+
+#[test]
+#[should_panic]
+fn test_hash_batch_wrong_out_len() {
+    crate::hash_batch(&[b"foo"], &mut []);
+}
+
 #[test]
 fn test_msg_schedule_permutation() {
     let permutation = [2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8];
@@ -667,6 +865,58 @@ fn test_reset() {
     assert_eq!(kdf.finalize(), expected);
 }
 
+#[test]
+fn test_derive_key_raw_and_xof() {
+    let context = "BLAKE3 2019-12-27 16:29:52 test vectors context";
+    let mut input = [0; 2 * CHUNK_LEN + 1];
+    paint_test_input(&mut input);
+    let expected = crate::derive_key(context, &input);
+    assert_eq!(expected, crate::derive_key_raw(context.as_bytes(), &input));
+    assert_eq!(
+        crate::Hasher::new_derive_key_raw(context.as_bytes())
+            .update(&input)
+            .finalize(),
+        expected,
+    );
+
+    let mut long = [0; 3 * BLOCK_LEN + 5];
+    crate::Hasher::new_derive_key(context)
+        .update(&input)
+        .finalize_xof()
+        .fill(&mut long);
+    assert_eq!(long[..32], expected);
+    let mut xof_out = [0; 3 * BLOCK_LEN + 5];
+    crate::derive_key_xof(context, &input).fill(&mut xof_out);
+    assert_eq!(long, xof_out);
+    let mut into_out = [0; 3 * BLOCK_LEN + 5];
+    crate::derive_key_into(context, &input, &mut into_out);
+    assert_eq!(long, into_out);
+    let mut xof_raw_out = [0; 3 * BLOCK_LEN + 5];
+    crate::derive_key_xof_raw(context.as_bytes(), &input).fill(&mut xof_raw_out);
+    assert_eq!(long, xof_raw_out);
+    let mut into_raw_out = [0; 3 * BLOCK_LEN + 5];
+    crate::derive_key_into_raw(context.as_bytes(), &input, &mut into_raw_out);
+    assert_eq!(long, into_raw_out);
+
+    // Contexts that aren't UTF-8 are hashed as-is.
+    let raw_context = b"\xff\xfe not UTF-8";
+    let raw_key = crate::derive_key_raw(raw_context, &input);
+    assert_eq!(
+        crate::Hasher::new_derive_key_raw(raw_context)
+            .update(&input)
+            .finalize(),
+        raw_key,
+    );
+    let mut raw_long = [0; 3 * BLOCK_LEN + 5];
+    crate::derive_key_xof_raw(raw_context, &input).fill(&mut raw_long);
+    assert_eq!(raw_long[..32], raw_key);
+    let mut raw_into = [0; 3 * BLOCK_LEN + 5];
+    crate::derive_key_into_raw(raw_context, &input, &mut raw_into);
+    assert_eq!(raw_long, raw_into);
+    let lossy_context = "\u{fffd}\u{fffd} not UTF-8";
+    assert_ne!(crate::derive_key(lossy_context, &input), raw_key);
+}
+
 #[test]
 fn test_hex_encoding_decoding() {
     let digest_str = "04e0bb39f30b1a3feb89f536c93be15055482df748674b00d26e5a75777702e9";
@@ -766,6 +1016,69 @@ fn test_hash_conversions() {
     assert!(crate::Hash::from_slice([42; 100].as_slice()).is_err());
 }
 
+#[test]
+fn test_finalize_len_digest() {
+    let mut input = [0; 3 * CHUNK_LEN + 1];
+    paint_test_input(&mut input);
+    let mut hasher = crate::Hasher::new();
+    hasher.update(&input);
+    let mut expected = [0; 100];
+    hasher.finalize_xof().fill(&mut expected);
+
+    let digest100: crate::Digest<100> = hasher.finalize_len();
+    assert_eq!(digest100, expected);
+    assert_eq!(digest100.as_bytes(), &expected);
+    let digest64 = digest100.truncate::<64>();
+    assert_eq!(digest64, expected[..64]);
+    assert_eq!(digest64, hasher.finalize_len::<64>());
+    let digest16: crate::Digest<16> = hasher.finalize_len();
+    assert_eq!(digest64.truncate::<16>(), digest16);
+    assert_eq!(hasher.finalize().truncate::<16>(), digest16);
+    assert_eq!(hasher.finalize_len::<0>(), [0; 0]);
+    let digest32: crate::Digest<32> = hasher.finalize().into();
+    assert_eq!(crate::Hash::from(digest32), hasher.finalize());
+    assert_eq!(digest32, hasher.finalize_len::<32>());
+
+    // Hex formatting and parsing.
+    let foo16 = crate::Hasher::new().update(b"foo").finalize_len::<16>();
+    let foo16_hex = "04e0bb39f30b1a3feb89f536c93be150";
+    assert_eq!(foo16, crate::Digest::from_hex(foo16_hex).unwrap());
+    assert_eq!(foo16, foo16_hex.parse::<crate::Digest<16>>().unwrap());
+    assert_eq!(
+        foo16,
+        crate::Digest::from_hex(foo16_hex.to_uppercase()).unwrap()
+    );
+    assert!(crate::Digest::<16>::from_hex(&foo16_hex[1..]).is_err());
+    assert!(crate::Digest::<32>::from_hex(foo16_hex).is_err());
+    #[cfg(feature = "std")]
+    {
+        assert_eq!(foo16.to_string(), foo16_hex);
+        assert_eq!(format!("{:?}", foo16), format!("Digest({:?})", foo16_hex));
+        let hex100 = digest100.to_string();
+        assert_eq!(hex100.len(), 200);
+        assert_eq!(crate::Digest::<100>::from_hex(&hex100).unwrap(), digest100);
+        assert_eq!(
+            crate::Digest::<32>::from_hex("abc")
+                .unwrap_err()
+                .to_string(),
+            "expected 64 hex bytes, received 3",
+        );
+        assert_eq!(
+            crate::Digest::<16>::from_hex("abc")
+                .unwrap_err()
+                .to_string(),
+            "expected 32 hex bytes, received 3",
+        );
+    }
+
+    // Slices.
+    assert_eq!(
+        crate::Digest::<16>::from_slice(digest16.as_slice()).unwrap(),
+        digest16
+    );
+    assert!(crate::Digest::<16>::from_slice(digest64.as_slice()).is_err());
+}
+
 #[test]
 const fn test_hash_const_conversions() {
     let bytes = [42; 32];
@@ -887,6 +1200,204 @@ fn test_update_reader_interrupted() -> std::io::Result<()> {
     Ok(())
 }
 
+// A minimal executor for the async IO tests. None of the readers and writers in these tests wait
+// on anything external, so polling in a loop is enough.
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+fn block_on<F: core::future::Future>(future: F) -> F::Output {
+    let mut future = core::pin::pin!(future);
+    let mut cx = core::task::Context::from_waker(core::task::Waker::noop());
+    loop {
+        if let core::task::Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+// Returns Pending and then Interrupted before every successful read, and reads at most 1000 bytes
+// at a time.
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+struct FlakyAsyncReader<'a> {
+    slice: &'a [u8],
+    calls: usize,
+}
+
+#[cfg(any(feature = "tokio", feature = "futures-io"))]
+impl FlakyAsyncReader<'_> {
+    fn poll_read_slice(
+        &mut self,
+        cx: &mut core::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> core::task::Poll<std::io::Result<usize>> {
+        self.calls += 1;
+        match self.calls % 3 {
+            1 => {
+                cx.waker().wake_by_ref();
+                core::task::Poll::Pending
+            }
+            2 => core::task::Poll::Ready(Err(std::io::ErrorKind::Interrupted.into())),
+            _ => {
+                let take = self.slice.len().min(buf.len()).min(1000);
+                buf[..take].copy_from_slice(&self.slice[..take]);
+                self.slice = &self.slice[take..];
+                core::task::Poll::Ready(Ok(take))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl tokio::io::AsyncRead for FlakyAsyncReader<'_> {
+    fn poll_read(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> core::task::Poll<std::io::Result<()>> {
+        let n = core::task::ready!(
+            self.get_mut()
+                .poll_read_slice(cx, buf.initialize_unfilled())
+        )?;
+        buf.advance(n);
+        core::task::Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl futures_io::AsyncRead for FlakyAsyncReader<'_> {
+    fn poll_read(
+        self: core::pin::Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> core::task::Poll<std::io::Result<usize>> {
+        self.get_mut().poll_read_slice(cx, buf)
+    }
+}
+
+#[test]
+#[cfg(feature = "tokio")]
+fn test_update_async_reader() -> std::io::Result<()> {
+    let mut input = vec![0; 100_000];
+    paint_test_input(&mut input);
+    let expected = crate::hash(&input);
+
+    let mut hasher = crate::Hasher::new();
+    block_on(hasher.update_async_reader(&input[..]))?;
+    assert_eq!(hasher.finalize(), expected);
+
+    let mut hasher = crate::Hasher::new();
+    let reader = FlakyAsyncReader {
+        slice: &input,
+        calls: 0,
+    };
+    block_on(hasher.update_async_reader(reader))?;
+    assert_eq!(hasher.finalize(), expected);
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "futures-io")]
+fn test_update_futures_reader() -> std::io::Result<()> {
+    let mut input = vec![0; 100_000];
+    paint_test_input(&mut input);
+    let expected = crate::hash(&input);
+
+    let mut hasher = crate::Hasher::new();
+    block_on(hasher.update_futures_reader(&input[..]))?;
+    assert_eq!(hasher.finalize(), expected);
+
+    let mut hasher = crate::Hasher::new();
+    let reader = FlakyAsyncReader {
+        slice: &input,
+        calls: 0,
+    };
+    block_on(hasher.update_futures_reader(reader))?;
+    assert_eq!(hasher.finalize(), expected);
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "tokio")]
+fn test_tokio_traits() -> std::io::Result<()> {
+    use core::future::poll_fn;
+    use core::pin::Pin;
+    use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+
+    let mut hasher = crate::Hasher::new();
+    let n = block_on(poll_fn(|cx| Pin::new(&mut hasher).poll_write(cx, b"foo")))?;
+    assert_eq!(n, 3);
+    block_on(poll_fn(|cx| Pin::new(&mut hasher).poll_flush(cx)))?;
+    block_on(poll_fn(|cx| Pin::new(&mut hasher).poll_shutdown(cx)))?;
+    assert_eq!(hasher.finalize(), crate::hash(b"foo"));
+
+    let mut expected = [0; 300];
+    hasher.finalize_xof().fill(&mut expected);
+    let mut reader = hasher.finalize_xof();
+    let mut buf = [0; 100];
+    let mut read_buf = ReadBuf::new(&mut buf);
+    block_on(poll_fn(|cx| {
+        Pin::new(&mut reader).poll_read(cx, &mut read_buf)
+    }))?;
+    assert_eq!(read_buf.filled(), &expected[..100]);
+    Pin::new(&mut reader).start_seek(std::io::SeekFrom::Current(50))?;
+    let position = block_on(poll_fn(|cx| Pin::new(&mut reader).poll_complete(cx)))?;
+    assert_eq!(position, 150);
+    let mut read_buf = ReadBuf::new(&mut buf);
+    block_on(poll_fn(|cx| {
+        Pin::new(&mut reader).poll_read(cx, &mut read_buf)
+    }))?;
+    assert_eq!(read_buf.filled(), &expected[150..250]);
+    // An uninitialized buffer gets filled in bounded pieces.
+    let mut expected = vec![0; 100_000];
+    hasher.finalize_xof().fill(&mut expected);
+    let mut reader = hasher.finalize_xof();
+    let mut uninit = vec![core::mem::MaybeUninit::uninit(); expected.len()];
+    let mut read_buf = ReadBuf::uninit(&mut uninit);
+    while read_buf.remaining() > 0 {
+        let before = read_buf.filled().len();
+        block_on(poll_fn(|cx| {
+            Pin::new(&mut reader).poll_read(cx, &mut read_buf)
+        }))?;
+        assert!(read_buf.filled().len() > before);
+    }
+    assert_eq!(read_buf.filled(), &expected[..]);
+    assert!(
+        Pin::new(&mut reader)
+            .start_seek(std::io::SeekFrom::End(0))
+            .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "futures-io")]
+fn test_futures_io_traits() -> std::io::Result<()> {
+    use core::future::poll_fn;
+    use core::pin::Pin;
+    use futures_io::{AsyncRead, AsyncSeek, AsyncWrite};
+
+    let mut hasher = crate::Hasher::new();
+    let n = block_on(poll_fn(|cx| Pin::new(&mut hasher).poll_write(cx, b"foo")))?;
+    assert_eq!(n, 3);
+    block_on(poll_fn(|cx| Pin::new(&mut hasher).poll_flush(cx)))?;
+    block_on(poll_fn(|cx| Pin::new(&mut hasher).poll_close(cx)))?;
+    assert_eq!(hasher.finalize(), crate::hash(b"foo"));
+
+    let mut expected = [0; 300];
+    hasher.finalize_xof().fill(&mut expected);
+    let mut reader = hasher.finalize_xof();
+    let mut buf = [0; 100];
+    let n = block_on(poll_fn(|cx| Pin::new(&mut reader).poll_read(cx, &mut buf)))?;
+    assert_eq!(n, 100);
+    assert_eq!(buf, expected[..100]);
+    let seek = std::io::SeekFrom::Current(50);
+    let position = block_on(poll_fn(|cx| Pin::new(&mut reader).poll_seek(cx, seek)))?;
+    assert_eq!(position, 150);
+    block_on(poll_fn(|cx| Pin::new(&mut reader).poll_read(cx, &mut buf)))?;
+    assert_eq!(buf, expected[150..250]);
+    let seek = std::io::SeekFrom::Current(-251);
+    assert!(block_on(poll_fn(|cx| Pin::new(&mut reader).poll_seek(cx, seek))).is_err());
+    Ok(())
+}
+
 #[test]
 #[cfg(feature = "mmap")]
 // NamedTempFile isn't Miri-compatible
@@ -909,6 +1420,84 @@ fn test_mmap() -> Result<(), std::io::Error> {
     Ok(())
 }
 
+#[test]
+#[cfg(feature = "std")]
+// NamedTempFile isn't Miri-compatible
+#[cfg(not(miri))]
+fn test_update_file_with_join() -> Result<(), std::io::Error> {
+    use crate::join::{ScopedThreadJoin, SerialJoin};
+    use std::io::prelude::*;
+    // Long enough to split into several positional reads, and not a power of 2.
+    let mut input = vec![0; 5 * (1 << 20) + 1234];
+    paint_test_input(&mut input);
+    let mut tempfile = tempfile::NamedTempFile::new()?;
+    tempfile.write_all(&input)?;
+    tempfile.flush()?;
+    let short_path = tempfile::NamedTempFile::new()?;
+    short_path.as_file().write_all(&input[..5000])?;
+    for prefix_len in [0, 1, CHUNK_LEN, 3 * CHUNK_LEN + 7] {
+        let mut expected = crate::Hasher::new_keyed(&TEST_KEY);
+        expected.update(&input[..prefix_len]).update(&input);
+
+        let mut hasher = crate::Hasher::new_keyed(&TEST_KEY);
+        hasher.update(&input[..prefix_len]);
+        hasher.update_file_with_join::<SerialJoin>(tempfile.path())?;
+        assert_eq!(
+            hasher.finalize(),
+            expected.finalize(),
+            "prefix {prefix_len}"
+        );
+
+        let mut hasher = crate::Hasher::new_keyed(&TEST_KEY);
+        hasher.update(&input[..prefix_len]);
+        hasher.update_file_with_join::<ScopedThreadJoin>(tempfile.path())?;
+        assert_eq!(
+            hasher.finalize(),
+            expected.finalize(),
+            "prefix {prefix_len}"
+        );
+
+        #[cfg(feature = "rayon")]
+        {
+            let mut hasher = crate::Hasher::new_keyed(&TEST_KEY);
+            hasher.update(&input[..prefix_len]);
+            hasher.update_file_parallel(tempfile.path())?;
+            assert_eq!(
+                hasher.finalize(),
+                expected.finalize(),
+                "prefix {prefix_len}"
+            );
+        }
+
+        // Short files take the serial fallback.
+        let mut expected = crate::Hasher::new_keyed(&TEST_KEY);
+        expected.update(&input[..prefix_len]).update(&input[..5000]);
+        let mut hasher = crate::Hasher::new_keyed(&TEST_KEY);
+        hasher.update(&input[..prefix_len]);
+        hasher.update_file_with_join::<ScopedThreadJoin>(short_path.path())?;
+        assert_eq!(
+            hasher.finalize(),
+            expected.finalize(),
+            "prefix {prefix_len}"
+        );
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg(feature = "std")]
+#[cfg(target_os = "linux")]
+fn test_update_file_with_join_virtual_file() -> Result<(), std::io::Error> {
+    // As in test_mmap_virtual_file, /proc/version reports a length of zero, but it isn't empty.
+    let virtual_filepath = "/proc/version";
+    let mut file_hasher = crate::Hasher::new();
+    file_hasher.update_file_with_join::<crate::join::ScopedThreadJoin>(virtual_filepath)?;
+    let mut read_hasher = crate::Hasher::new();
+    read_hasher.update_reader(std::fs::File::open(virtual_filepath)?)?;
+    assert_eq!(file_hasher.finalize(), read_hasher.finalize());
+    Ok(())
+}
+
 #[test]
 #[cfg(feature = "mmap")]
 #[cfg(target_os = "linux")]
@@ -993,6 +1582,78 @@ fn test_serde() {
     assert_eq!(hash_from_bytestring_cbor, hash);
 }
 
+#[test]
+#[cfg(feature = "std")]
+#[cfg(feature = "serde")]
+fn test_serde_hex() {
+    use crate::serde_hex::HexHash;
+
+    #[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+    struct Fields {
+        #[serde(with = "crate::serde_hex")]
+        hash: crate::Hash,
+        #[serde(with = "crate::serde_hex")]
+        cv: crate::hazmat::ChainingValue,
+        #[serde(with = "crate::serde_hex::bytes")]
+        xof: Vec<u8>,
+    }
+
+    let hash: crate::Hash = [0xfe; 32].into();
+    let fields = Fields {
+        hash,
+        cv: [0xab; 32],
+        xof: vec![0x01, 0x23, 0xef],
+    };
+    let hash_hex = "fe".repeat(32);
+    let cv_hex = "ab".repeat(32);
+    let json = serde_json::to_string(&fields).unwrap();
+    assert_eq!(
+        json,
+        format!(r#"{{"hash":"{hash_hex}","cv":"{cv_hex}","xof":"0123ef"}}"#),
+    );
+    assert_eq!(serde_json::from_str::<Fields>(&json).unwrap(), fields);
+
+    // Binary formats get raw bytes.
+    let mut cbor = Vec::<u8>::new();
+    ciborium::into_writer(&HexHash(hash), &mut cbor).unwrap();
+    let mut expected_cbor = vec![0x58, 0x20];
+    expected_cbor.extend_from_slice(&[0xfe; 32]);
+    assert_eq!(cbor, expected_cbor);
+    let from_cbor: HexHash = ciborium::from_reader(&cbor[..]).unwrap();
+    assert_eq!(from_cbor.0, hash);
+    let mut cbor = Vec::<u8>::new();
+    ciborium::into_writer(&fields, &mut cbor).unwrap();
+    assert_eq!(
+        ciborium::from_reader::<Fields, _>(&cbor[..]).unwrap(),
+        fields
+    );
+
+    // The legacy array forms still deserialize, in both JSON and CBOR.
+    let legacy_json = serde_json::to_string(&hash).unwrap();
+    let from_legacy_json: HexHash = serde_json::from_str(&legacy_json).unwrap();
+    assert_eq!(from_legacy_json.0, hash);
+    let mut legacy_cbor = Vec::<u8>::new();
+    ciborium::into_writer(&hash, &mut legacy_cbor).unwrap();
+    let from_legacy_cbor: HexHash = ciborium::from_reader(&legacy_cbor[..]).unwrap();
+    assert_eq!(from_legacy_cbor.0, hash);
+    let xof_array: Fields = serde_json::from_str(&format!(
+        r#"{{"hash":{legacy_json},"cv":"{cv_hex}","xof":[1,35,239]}}"#
+    ))
+    .unwrap();
+    assert_eq!(xof_array, fields);
+
+    // Uppercase hex is accepted, and invalid lengths and characters are errors.
+    let upper: HexHash = serde_json::from_str(&format!(r#""{}""#, "FE".repeat(32))).unwrap();
+    assert_eq!(upper.0, hash);
+    assert!(serde_json::from_str::<HexHash>(&format!(r#""{}""#, "fe".repeat(31))).is_err());
+    assert!(serde_json::from_str::<HexHash>(&format!(r#""{}""#, "fg".repeat(32))).is_err());
+    assert!(serde_json::from_str::<HexHash>(&format!("[{}1]", "1,".repeat(31))).is_ok());
+    assert!(serde_json::from_str::<HexHash>(&format!("[{}1]", "1,".repeat(30))).is_err());
+    assert!(serde_json::from_str::<HexHash>(&format!("[{}1]", "1,".repeat(32))).is_err());
+    let bad_xof = format!(r#"{{"hash":"{hash_hex}","cv":"{cv_hex}","xof":"012"}}"#);
+    assert!(serde_json::from_str::<Fields>(&bad_xof).is_err());
+}
+
 // `cargo +nightly miri test` currently works, but it takes forever, because some of our test
 // inputs are quite large. Most of our unsafe code is platform specific and incompatible with Miri
 // anyway, but we'd like it to be possible for callers to run their own tests under Miri, assuming
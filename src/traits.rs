@@ -1,9 +1,12 @@
 //! Implementations of commonly used traits like `Digest` and `Mac` from the
-//! [`digest`](https://crates.io/crates/digest) crate.
+//! [`digest`](https://crates.io/crates/digest) crate, and `StreamCipher` from the
+//! [`cipher`](https://crates.io/crates/cipher) crate.
 
+pub use cipher;
 pub use digest;
 
-use crate::{Hasher, OutputReader};
+use crate::{BLOCK_LEN, Hasher, OutputReader};
+use cipher::{InOutBuf, OverflowError, SeekNum, StreamCipherError};
 use digest::array::{Array, typenum::U32, typenum::U64};
 use digest::common;
 
@@ -85,6 +88,54 @@ impl digest::KeyInit for Hasher {
     }
 }
 
+impl cipher::StreamCipher for OutputReader {
+    #[inline]
+    fn check_remaining(&self, data_len: usize) -> Result<(), StreamCipherError> {
+        // The maximum output size of BLAKE3 is 2^64-1 bytes.
+        let remaining = u64::MAX - self.position();
+        if data_len as u64 > remaining {
+            return Err(StreamCipherError);
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn unchecked_apply_keystream_inout(&mut self, buf: InOutBuf<'_, '_, u8>) {
+        self.xor_into(buf.into_out_with_copied_in());
+    }
+
+    #[inline]
+    fn unchecked_write_keystream(&mut self, buf: &mut [u8]) {
+        self.fill(buf);
+    }
+}
+
+impl cipher::StreamCipherSeek for OutputReader {
+    fn try_current_pos<T: SeekNum>(&self) -> Result<T, OverflowError> {
+        // SeekNum counts whole blocks from the end of the current (possibly partial) block.
+        let position = self.position();
+        let block = position / BLOCK_LEN as u64;
+        let byte = (position % BLOCK_LEN as u64) as u8;
+        if byte == 0 {
+            T::from_block_byte(block, BLOCK_LEN as u8, BLOCK_LEN as u8)
+        } else {
+            T::from_block_byte(block + 1, byte, BLOCK_LEN as u8)
+        }
+    }
+
+    fn try_seek<T: SeekNum>(&mut self, pos: T) -> Result<(), StreamCipherError> {
+        let (block, byte): (u64, u8) = pos
+            .into_block_byte(BLOCK_LEN as u8)
+            .map_err(|_| StreamCipherError)?;
+        let position = block
+            .checked_mul(BLOCK_LEN as u64)
+            .and_then(|position| position.checked_add(byte as u64))
+            .ok_or(StreamCipherError)?;
+        self.set_position(position);
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use digest::array::AsArrayMut;
@@ -191,6 +242,48 @@ mod test {
         outer_state.finalize().into()
     }
 
+    #[test]
+    fn test_stream_cipher_traits() {
+        use cipher::{StreamCipher, StreamCipherSeek};
+
+        let key = [42; 32];
+        let mut reader = crate::Hasher::new_keyed(&key).finalize_xof();
+        let mut keystream = [0; 1000];
+        reader.fill(&mut keystream);
+
+        let mut buf = [0xaa; 1000];
+        let mut reader = crate::Hasher::new_keyed(&key).finalize_xof();
+        reader.seek(100u64);
+        assert_eq!(reader.current_pos::<u64>(), 100);
+        reader.apply_keystream(&mut buf[100..600]);
+        assert_eq!(reader.current_pos::<u32>(), 600);
+        reader.seek(0u32);
+        assert_eq!(reader.current_pos::<usize>(), 0);
+        reader.apply_keystream(&mut buf[..100]);
+        assert_eq!(reader.current_pos::<u64>(), 100);
+        for i in 0..600 {
+            assert_eq!(buf[i], 0xaa ^ keystream[i], "byte {i}");
+        }
+
+        let input = [0x55; 77];
+        let mut output = [0; 77];
+        reader.seek(3u64);
+        reader.apply_keystream_b2b(&input, &mut output);
+        for i in 0..77 {
+            assert_eq!(output[i], 0x55 ^ keystream[3 + i]);
+        }
+        let mut written = [0; 64];
+        reader.write_keystream(&mut written);
+        assert_eq!(written, keystream[80..144]);
+
+        // The output is limited to 2^64-1 bytes.
+        reader.set_position(u64::MAX - 10);
+        assert!(reader.try_apply_keystream(&mut [0; 10]).is_ok());
+        assert!(reader.try_apply_keystream(&mut [0; 1]).is_err());
+        assert!(reader.try_seek(u128::MAX).is_err());
+        assert!(reader.try_current_pos::<u32>().is_err());
+    }
+
     #[test]
     fn test_hmac_compatibility() {
         use hmac::{KeyInit, Mac, SimpleHmac};
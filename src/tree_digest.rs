@@ -0,0 +1,501 @@
+//! A single hash over a whole directory tree
+//!
+//! A tree digest commits to every entry under a directory: the relative path and type of each
+//! entry, whether each regular file is executable, the [`hash`](crate::hash) of each regular
+//! file's contents, and the target of each symlink. Two sets of entries have the same tree digest
+//! if and only if they have the same paths, types, contents, and symlink targets. File timestamps,
+//! ownership, and the order the filesystem lists entries in don't matter, and neither does the
+//! platform; see [Platform independence](#platform-independence).
+//!
+//! [`digest_dir`] walks a directory on disk, and [`TreeDigest`] builds a digest from entries that
+//! you supply yourself, for example from an archive or a manifest. Both produce the same result
+//! for the same entries. These require the `std` Cargo feature, which is enabled by default.
+//!
+//! # Format
+//!
+//! The digest is the [`derive_key`](crate::derive_key) mode of BLAKE3 with the context string
+//! `"BLAKE3 2025-06-01 tree_digest v1"`, applied to an encoding of every entry under the root
+//! (not including the root itself). Entries are sorted by path, comparing the UTF-8 bytes, and
+//! each entry is encoded as:
+//!
+//! - a one-byte type: `d` for a directory, `f` for a regular file, `x` for an executable regular
+//!   file, or `l` for a symlink
+//! - the length of the path in bytes, as an 8-byte little-endian integer, followed by the path
+//! - for `f` and `x`, the 32-byte hash of the file's contents
+//! - for `l`, the length of the symlink target in bytes, as an 8-byte little-endian integer,
+//!   followed by the target
+//!
+//! Paths are relative to the root, with components separated by `/`. A component can't be empty,
+//! `.`, or `..`, and it can't contain `/`, `\`, or NUL. Paths and symlink targets must be ASCII,
+//! symlink targets can't contain `\` or NUL, and no two paths can differ only in ASCII case.
+//! Every ancestor directory of an entry is also an entry. A file is executable if any of its Unix
+//! execute permission bits are set. Other file types, like FIFOs and device files, aren't
+//! supported.
+//!
+//! # Platform independence
+//!
+//! The restrictions above rule out trees that some platforms would store differently, so that
+//! the same tree has the same digest everywhere, or else it's an error:
+//!
+//! - Non-ASCII names can be stored in different Unicode normalization forms on different
+//!   filesystems. For example, HFS+ stores names in NFD.
+//! - A backslash is a path separator on Windows but an ordinary character elsewhere.
+//! - Names that differ only in case can't coexist on case-insensitive filesystems.
+//!
+//! The executable bit comes from the Unix permission bits, so [`digest_dir`] returns an
+//! [`Unsupported`](io::ErrorKind::Unsupported) error on platforms that don't have them, like
+//! Windows. [`TreeDigest`] works on every platform.
+//!
+//! # Example
+//!
+//! ```
+//! # fn main() -> Result<(), blake3::tree_digest::TreeDigestError> {
+//! use blake3::tree_digest::TreeDigest;
+//!
+//! let mut builder = TreeDigest::new();
+//! builder.add_file("bin/tool", true, &blake3::hash(b"#!/bin/sh\n"))?;
+//! builder.add_file("README", false, &blake3::hash(b"hello\n"))?;
+//! builder.add_dir("empty")?;
+//! let digest = builder.finalize();
+//!
+//! // The order entries are added doesn't matter, and parent directories are implicit.
+//! let mut other = TreeDigest::new();
+//! other.add_dir("empty")?;
+//! other.add_file("README", false, &blake3::hash(b"hello\n"))?;
+//! other.add_dir("bin")?;
+//! other.add_file("bin/tool", true, &blake3::hash(b"#!/bin/sh\n"))?;
+//! assert_eq!(digest, other.finalize());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{Hash, Hasher};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The context string for the tree digest, version 1.
+pub const CONTEXT: &str = "BLAKE3 2025-06-01 tree_digest v1";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Entry {
+    Dir,
+    File { executable: bool, hash: Hash },
+    Symlink { target: String },
+}
+
+/// A builder for a tree digest, from entries supplied by the caller.
+///
+/// See the [module level docs](self) for the format.
+#[derive(Clone, Debug, Default)]
+pub struct TreeDigest {
+    // Sorted by the UTF-8 bytes of the path, which is how String compares.
+    entries: BTreeMap<String, Entry>,
+    // Every path in entries, keyed by its ASCII lowercase form, for finding case conflicts.
+    lowercase_paths: BTreeMap<String, String>,
+}
+
+impl TreeDigest {
+    /// Create an empty builder. The digest of an empty directory is `TreeDigest::new().finalize()`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a regular file with the given [`hash`](crate::hash) of its contents. Its parent
+    /// directories are added implicitly.
+    pub fn add_file(
+        &mut self,
+        path: &str,
+        executable: bool,
+        hash: &Hash,
+    ) -> Result<&mut Self, TreeDigestError> {
+        let entry = Entry::File {
+            executable,
+            hash: *hash,
+        };
+        self.add(path, entry)
+    }
+
+    /// Add a directory. Its parent directories are added implicitly. Adding a directory that's
+    /// already present, explicitly or implicitly, does nothing. You only need to add directories
+    /// explicitly if they're empty.
+    pub fn add_dir(&mut self, path: &str) -> Result<&mut Self, TreeDigestError> {
+        self.add(path, Entry::Dir)
+    }
+
+    /// Add a symlink with the given target. The target isn't interpreted, but it must be ASCII
+    /// and can't contain `\` or NUL. Its parent directories are added implicitly.
+    pub fn add_symlink(&mut self, path: &str, target: &str) -> Result<&mut Self, TreeDigestError> {
+        if !target.is_ascii() || target.contains(['\\', '\0']) {
+            return Err(TreeDigestError::InvalidSymlinkTarget(target.to_string()));
+        }
+        let entry = Entry::Symlink {
+            target: target.to_string(),
+        };
+        self.add(path, entry)
+    }
+
+    fn add(&mut self, path: &str, entry: Entry) -> Result<&mut Self, TreeDigestError> {
+        check_path(path)?;
+        // Check the whole path before changing anything, so that errors leave self unchanged.
+        for (i, _) in path.match_indices('/') {
+            match self.entries.get(&path[..i]) {
+                Some(Entry::Dir) | None => {}
+                Some(_) => return Err(TreeDigestError::NotADirectory(path[..i].to_string())),
+            }
+        }
+        let prefixes = path.match_indices('/').map(|(i, _)| &path[..i]);
+        for prefix in prefixes.chain([path]) {
+            match self.lowercase_paths.get(&prefix.to_ascii_lowercase()) {
+                Some(existing) if existing != prefix => {
+                    return Err(TreeDigestError::CaseConflict(prefix.to_string()));
+                }
+                _ => {}
+            }
+        }
+        match self.entries.get(path) {
+            None => {}
+            Some(Entry::Dir) if entry == Entry::Dir => return Ok(self),
+            Some(_) => return Err(TreeDigestError::DuplicatePath(path.to_string())),
+        }
+        for (i, _) in path.match_indices('/') {
+            self.entries
+                .entry(path[..i].to_string())
+                .or_insert(Entry::Dir);
+            self.lowercase_paths
+                .insert(path[..i].to_ascii_lowercase(), path[..i].to_string());
+        }
+        self.entries.insert(path.to_string(), entry);
+        self.lowercase_paths
+            .insert(path.to_ascii_lowercase(), path.to_string());
+        Ok(self)
+    }
+
+    /// Compute the digest of all the entries added so far.
+    pub fn finalize(&self) -> Hash {
+        let mut hasher = Hasher::new_derive_key(CONTEXT);
+        for (path, entry) in &self.entries {
+            let type_byte = match entry {
+                Entry::Dir => b'd',
+                Entry::File {
+                    executable: false, ..
+                } => b'f',
+                Entry::File {
+                    executable: true, ..
+                } => b'x',
+                Entry::Symlink { .. } => b'l',
+            };
+            hasher.update(&[type_byte]);
+            hasher.update(&(path.len() as u64).to_le_bytes());
+            hasher.update(path.as_bytes());
+            match entry {
+                Entry::Dir => {}
+                Entry::File { hash, .. } => {
+                    hasher.update(hash.as_bytes());
+                }
+                Entry::Symlink { target } => {
+                    hasher.update(&(target.len() as u64).to_le_bytes());
+                    hasher.update(target.as_bytes());
+                }
+            }
+        }
+        hasher.finalize()
+    }
+}
+
+fn check_path(path: &str) -> Result<(), TreeDigestError> {
+    let valid = path.is_ascii()
+        && path.split('/').all(|component| {
+            !matches!(component, "" | "." | "..") && !component.contains(['\\', '\0'])
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(TreeDigestError::InvalidPath(path.to_string()))
+    }
+}
+
+/// The error type for [`TreeDigest`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TreeDigestError {
+    /// The path is empty, has an empty, `.`, or `..` component, isn't ASCII, or contains `\` or
+    /// NUL.
+    InvalidPath(String),
+    /// The symlink target isn't ASCII, or it contains `\` or NUL.
+    InvalidSymlinkTarget(String),
+    /// An entry with this path was already added, and it isn't a directory being added again.
+    DuplicatePath(String),
+    /// This path was already added as a file or symlink, but another entry is inside it.
+    NotADirectory(String),
+    /// This path differs only in ASCII case from a path that was already added.
+    CaseConflict(String),
+}
+
+impl fmt::Display for TreeDigestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TreeDigestError::InvalidPath(path) => write!(f, "invalid path: {path:?}"),
+            TreeDigestError::InvalidSymlinkTarget(target) => {
+                write!(f, "invalid symlink target: {target:?}")
+            }
+            TreeDigestError::DuplicatePath(path) => write!(f, "duplicate path: {path:?}"),
+            TreeDigestError::NotADirectory(path) => write!(f, "not a directory: {path:?}"),
+            TreeDigestError::CaseConflict(path) => write!(f, "path differs only in case: {path:?}"),
+        }
+    }
+}
+
+impl std::error::Error for TreeDigestError {}
+
+impl From<TreeDigestError> for io::Error {
+    fn from(e: TreeDigestError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Compute the tree digest of a directory on disk, hashing each file with
+/// [`update_reader`](Hasher::update_reader).
+///
+/// Symlinks aren't followed, and their targets are recorded as-is. Hidden files are included. This
+/// returns an error if any path or symlink target isn't allowed by the [format](self#format), if a
+/// file isn't a regular file, directory, or symlink, or if any IO fails. On platforms without
+/// Unix permission bits, like Windows, this always returns an
+/// [`Unsupported`](io::ErrorKind::Unsupported) error.
+pub fn digest_dir(path: impl AsRef<Path>) -> io::Result<Hash> {
+    digest_dir_with(path, |file_path| {
+        let mut hasher = Hasher::new();
+        hasher.update_reader(fs::File::open(file_path)?)?;
+        Ok(hasher.finalize())
+    })
+}
+
+/// As [`digest_dir`], but hashing each regular file with the given function, for example to use
+/// [`update_mmap_rayon`](Hasher::update_mmap_rayon). `hash_file` must return the ordinary
+/// [`hash`](crate::hash) of the file's contents.
+pub fn digest_dir_with(
+    path: impl AsRef<Path>,
+    mut hash_file: impl FnMut(&Path) -> io::Result<Hash>,
+) -> io::Result<Hash> {
+    if !cfg!(unix) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "tree digests need Unix permission bits",
+        ));
+    }
+    let mut builder = TreeDigest::new();
+    add_dir_contents(&mut builder, path.as_ref(), "", &mut hash_file)?;
+    Ok(builder.finalize())
+}
+
+fn add_dir_contents(
+    builder: &mut TreeDigest,
+    dir: &Path,
+    relative_dir: &str,
+    hash_file: &mut dyn FnMut(&Path) -> io::Result<Hash>,
+) -> io::Result<()> {
+    // The builder sorts entries, so the directory order doesn't matter.
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = entry.file_name().into_string().map_err(|name| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("non-UTF-8 path: {:?}", dir.join(name)),
+            )
+        })?;
+        let relative_path = if relative_dir.is_empty() {
+            name
+        } else {
+            format!("{relative_dir}/{name}")
+        };
+        // DirEntry::file_type doesn't follow symlinks.
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            builder.add_dir(&relative_path)?;
+            add_dir_contents(builder, &path, &relative_path, hash_file)?;
+        } else if file_type.is_file() {
+            let executable = is_executable(&entry.metadata()?);
+            let hash = hash_file(&path)?;
+            builder.add_file(&relative_path, executable, &hash)?;
+        } else if file_type.is_symlink() {
+            let target = fs::read_link(&path)?.into_os_string().into_string();
+            let target = target.map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("non-UTF-8 symlink target: {path:?}"),
+                )
+            })?;
+            builder.add_symlink(&relative_path, &target)?;
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported file type: {path:?}"),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    unreachable!("digest_dir_with returns an error first")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        // Compute the expected encoding by hand.
+        let file_hash = crate::hash(b"foo");
+        let mut expected = Hasher::new_derive_key(CONTEXT);
+        expected.update(b"d");
+        expected.update(&1u64.to_le_bytes());
+        expected.update(b"a");
+        expected.update(b"x");
+        expected.update(&3u64.to_le_bytes());
+        expected.update(b"a/b");
+        expected.update(file_hash.as_bytes());
+        expected.update(b"f");
+        expected.update(&3u64.to_le_bytes());
+        expected.update(b"a/c");
+        expected.update(file_hash.as_bytes());
+        expected.update(b"l");
+        expected.update(&2u64.to_le_bytes());
+        expected.update(b"a~");
+        expected.update(&5u64.to_le_bytes());
+        expected.update(b"a/../");
+        expected.update(b"d");
+        expected.update(&5u64.to_le_bytes());
+        expected.update(b"empty");
+
+        let mut builder = TreeDigest::new();
+        builder.add_dir("empty").unwrap();
+        builder.add_symlink("a~", "a/../").unwrap();
+        builder.add_file("a/c", false, &file_hash).unwrap();
+        builder.add_file("a/b", true, &file_hash).unwrap();
+        assert_eq!(expected.finalize(), builder.finalize());
+
+        // Pin the output, so that format changes can't happen by accident.
+        assert_eq!(
+            builder.finalize().to_hex().as_str(),
+            "b8e2ff252c0819eda908b397a9182bdbd24a9af49f631175295ccde7ecf09d35",
+        );
+        assert_eq!(
+            *TreeDigest::new().finalize().as_bytes(),
+            crate::derive_key(CONTEXT, b""),
+        );
+    }
+
+    #[test]
+    fn test_builder_errors() {
+        let hash = crate::hash(b"");
+        let mut builder = TreeDigest::new();
+        builder.add_file("a/b", false, &hash).unwrap();
+        for bad in [
+            "",
+            "/a",
+            "a/",
+            "a//b",
+            "./a",
+            "a/..",
+            "a\0b",
+            "a\\b",
+            "caf\u{e9}",
+        ] {
+            assert_eq!(
+                builder.add_dir(bad).unwrap_err(),
+                TreeDigestError::InvalidPath(bad.to_string()),
+            );
+        }
+        assert_eq!(
+            builder.add_file("a/b", false, &hash).unwrap_err(),
+            TreeDigestError::DuplicatePath("a/b".to_string()),
+        );
+        assert_eq!(
+            builder.add_symlink("a", "x").unwrap_err(),
+            TreeDigestError::DuplicatePath("a".to_string()),
+        );
+        assert_eq!(
+            builder.add_dir("a/b/c/d").unwrap_err(),
+            TreeDigestError::NotADirectory("a/b".to_string()),
+        );
+        for bad in ["a\\b", "a\0b", "caf\u{e9}"] {
+            assert_eq!(
+                builder.add_symlink("link", bad).unwrap_err(),
+                TreeDigestError::InvalidSymlinkTarget(bad.to_string()),
+            );
+        }
+        // Paths that differ only in case, including implicit parent directories.
+        assert_eq!(
+            builder.add_file("A/c", false, &hash).unwrap_err(),
+            TreeDigestError::CaseConflict("A".to_string()),
+        );
+        assert_eq!(
+            builder.add_dir("a/B").unwrap_err(),
+            TreeDigestError::CaseConflict("a/B".to_string()),
+        );
+        // Errors don't change the builder, and adding an existing directory is a no-op.
+        let before = builder.finalize();
+        builder.add_dir("a").unwrap();
+        assert_eq!(before, builder.finalize());
+        assert!(!builder.entries.contains_key("a/b/c"));
+        assert!(!builder.lowercase_paths.contains_key("a/b/c"));
+    }
+
+    #[test]
+    #[cfg(all(unix, not(miri)))]
+    fn test_digest_dir() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::create_dir_all(dir.path().join("sub/empty"))?;
+        fs::write(dir.path().join("sub/file"), b"hello")?;
+        fs::write(dir.path().join(".hidden"), b"hidden")?;
+        let mut builder = TreeDigest::new();
+        builder.add_file("sub/file", false, &crate::hash(b"hello"))?;
+        builder.add_file(".hidden", false, &crate::hash(b"hidden"))?;
+        builder.add_dir("sub/empty")?;
+        let tool = dir.path().join("tool");
+        fs::write(&tool, b"#!/bin/sh")?;
+        fs::set_permissions(&tool, std::os::unix::fs::PermissionsExt::from_mode(0o755))?;
+        builder.add_file("tool", true, &crate::hash(b"#!/bin/sh"))?;
+        std::os::unix::fs::symlink("sub/file", dir.path().join("link"))?;
+        builder.add_symlink("link", "sub/file")?;
+
+        let digest = digest_dir(dir.path())?;
+        assert_eq!(builder.finalize(), digest);
+
+        // Changing anything changes the digest.
+        fs::write(dir.path().join("sub/file"), b"hellp")?;
+        assert_ne!(digest, digest_dir(dir.path())?);
+        fs::write(dir.path().join("sub/file"), b"hello")?;
+        assert_eq!(digest, digest_dir(dir.path())?);
+        fs::remove_dir(dir.path().join("sub/empty"))?;
+        assert_ne!(digest, digest_dir(dir.path())?);
+
+        // Names that other platforms would store differently are errors.
+        for bad_name in ["Sub", "caf\u{e9}", "a\\b"] {
+            fs::write(dir.path().join(bad_name), b"")?;
+            let e = digest_dir(dir.path()).unwrap_err();
+            assert_eq!(e.kind(), io::ErrorKind::InvalidData, "{bad_name:?}");
+            fs::remove_file(dir.path().join(bad_name))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(not(unix))]
+    fn test_digest_dir_unsupported() {
+        let e = digest_dir(".").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::Unsupported);
+    }
+}
@@ -0,0 +1,579 @@
+//! Verified streaming with outboard trees
+//!
+//! Because BLAKE3 is a Merkle tree, a recipient who knows the root [`Hash`](struct@Hash) of some
+//! content can verify each chunk of that content as it arrives, without waiting for the rest. All
+//! they need are the interior chaining values of the tree along the path from the root to each
+//! chunk. This module computes those chaining values ahead of time and stores them separately from
+//! the content, in what [Bao](https://github.com/oconnor663/bao) calls an "outboard" encoding.
+//!
+//! The outboard encoding is an 8-byte little-endian content length, followed by every parent
+//! node of the tree in pre-order (that is, each parent before its left subtree, and its left
+//! subtree before its right subtree). Each parent node is 64 bytes, the chaining value of its left
+//! child followed by the chaining value of its right child. Content of one chunk or less has no
+//! parent nodes, and its outboard encoding is just the length. This is the same layout as the
+//! outboard format of Bao (without chunk groups), so the outboard encoding of some content is
+//! about 6% the size of the content itself.
+//!
+//! [`outboard`] produces the encoding, and [`SliceVerifier`] verifies a chunk-aligned slice of
+//! the content against the root [`Hash`](struct@Hash), one chunk at a time. These functions
+//! require the `std` Cargo feature, which is enabled by default.
+//!
+//! # Example
+//!
+//! ```
+//! # fn main() -> Result<(), blake3::verified::VerifyError> {
+//! use blake3::CHUNK_LEN;
+//! use blake3::verified::{SliceVerifier, outboard};
+//!
+//! let content = vec![0xab; 10 * CHUNK_LEN + 42];
+//! let (root_hash, outboard_bytes) = outboard(&content);
+//! assert_eq!(root_hash, blake3::hash(&content));
+//!
+//! // A recipient who trusts only `root_hash` can verify a slice starting at chunk 3 as it
+//! // streams in, without seeing chunks 0-2.
+//! let slice_start = 3 * CHUNK_LEN;
+//! let mut verifier = SliceVerifier::new(&root_hash, &outboard_bytes, slice_start as u64)?;
+//! for chunk in content[slice_start..].chunks(CHUNK_LEN) {
+//!     verifier.verify_chunk(chunk)?;
+//! }
+//!
+//! // Corrupt content is rejected.
+//! let mut verifier = SliceVerifier::new(&root_hash, &outboard_bytes, 0)?;
+//! let mut bad_chunk = content[..CHUNK_LEN].to_vec();
+//! bad_chunk[0] ^= 1;
+//! assert!(verifier.verify_chunk(&bad_chunk).is_err());
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::hazmat::{
+    ChainingValue, HasherExt, Mode, left_subtree_len, merge_subtrees_non_root, merge_subtrees_root,
+};
+use crate::{CHUNK_LEN, Hash, Hasher, OUT_LEN};
+use arrayref::array_ref;
+use core::fmt;
+
+/// The number of bytes in the length header at the front of an outboard encoding, 8.
+pub const HEADER_LEN: usize = 8;
+
+/// The number of bytes in each parent node of an outboard encoding, 64.
+pub const PARENT_LEN: usize = 2 * OUT_LEN;
+
+// The number of chunks in some content, counting the empty content as one chunk.
+fn count_chunks(content_len: u64) -> u64 {
+    if content_len == 0 {
+        1
+    } else {
+        content_len.div_ceil(CHUNK_LEN as u64)
+    }
+}
+
+/// The size in bytes of the outboard encoding of content of length `content_len`.
+///
+/// This is [`HEADER_LEN`] plus [`PARENT_LEN`] for every parent node in the tree. A tree with `N`
+/// chunks has `N - 1` parent nodes.
+pub fn outboard_len(content_len: u64) -> u64 {
+    HEADER_LEN as u64 + PARENT_LEN as u64 * (count_chunks(content_len) - 1)
+}
+
+fn chunk_or_subtree_cv(input: &[u8], input_offset: u64) -> ChainingValue {
+    Hasher::new()
+        .set_input_offset(input_offset)
+        .update(input)
+        .finalize_non_root()
+}
+
+// Write the parent nodes of the subtree at `input_offset` into `out` in pre-order, and return the
+// chaining value of the subtree.
+fn encode_subtree(input: &[u8], input_offset: u64, out: &mut Vec<u8>) -> ChainingValue {
+    if input.len() <= CHUNK_LEN {
+        return chunk_or_subtree_cv(input, input_offset);
+    }
+    let (left_cv, right_cv) = encode_parent(input, input_offset, out);
+    merge_subtrees_non_root(&left_cv, &right_cv, Mode::Hash)
+}
+
+fn encode_parent(
+    input: &[u8],
+    input_offset: u64,
+    out: &mut Vec<u8>,
+) -> (ChainingValue, ChainingValue) {
+    // Reserve space for this parent node before recursing, so that it comes before its children.
+    let parent_start = out.len();
+    out.extend_from_slice(&[0; PARENT_LEN]);
+    let left_len = left_subtree_len(input.len() as u64) as usize;
+    let (left, right) = input.split_at(left_len);
+    let left_cv = encode_subtree(left, input_offset, out);
+    let right_cv = encode_subtree(right, input_offset + left_len as u64, out);
+    out[parent_start..][..OUT_LEN].copy_from_slice(&left_cv);
+    out[parent_start + OUT_LEN..][..OUT_LEN].copy_from_slice(&right_cv);
+    (left_cv, right_cv)
+}
+
+/// Compute the root [`Hash`](struct@Hash) and the outboard encoding of `input`.
+///
+/// The root hash is always equal to [`blake3::hash(input)`](crate::hash). See the [module level
+/// docs](index.html) for a description of the encoding.
+///
+/// This function is single-threaded, and because it needs the chaining value of every chunk, it
+/// doesn't get the full benefit of SIMD parallelism. It's currently quite a bit slower than
+/// [`hash`](crate::hash).
+pub fn outboard(input: &[u8]) -> (Hash, Vec<u8>) {
+    let mut out = Vec::with_capacity(outboard_len(input.len() as u64) as usize);
+    out.extend_from_slice(&(input.len() as u64).to_le_bytes());
+    if input.len() <= CHUNK_LEN {
+        return (crate::hash(input), out);
+    }
+    let (left_cv, right_cv) = encode_parent(input, 0, &mut out);
+    debug_assert_eq!(out.len() as u64, outboard_len(input.len() as u64));
+    (merge_subtrees_root(&left_cv, &right_cv, Mode::Hash), out)
+}
+
+/// The error type for [`SliceVerifier`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum VerifyError {
+    /// The outboard encoding is shorter than its header, or its length doesn't match the content
+    /// length in its header.
+    InvalidOutboard,
+    /// The slice start is beyond the end of the content or isn't a multiple of [`CHUNK_LEN`].
+    InvalidSliceStart(u64),
+    /// A chunk was the wrong length for its position, or there was more input after the final
+    /// chunk. The only chunk that can be shorter than [`CHUNK_LEN`] is the final chunk.
+    UnexpectedChunkLen {
+        /// The offset of the chunk within the content.
+        offset: u64,
+        /// The expected length of the chunk.
+        expected: usize,
+        /// The length of the chunk the caller supplied.
+        found: usize,
+    },
+    /// A parent node from the outboard encoding didn't match the root hash or the chaining value
+    /// of its parent.
+    ParentMismatch {
+        /// The offset of the first chunk in the subtree under this parent.
+        offset: u64,
+    },
+    /// A chunk didn't match the root hash or the chaining value in its parent.
+    ChunkMismatch {
+        /// The offset of the chunk within the content.
+        offset: u64,
+    },
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyError::InvalidOutboard => write!(f, "invalid outboard encoding"),
+            VerifyError::InvalidSliceStart(start) => write!(f, "invalid slice start: {start}"),
+            VerifyError::UnexpectedChunkLen {
+                offset,
+                expected,
+                found,
+            } => write!(
+                f,
+                "expected {expected} bytes for the chunk at offset {offset}, found {found}",
+            ),
+            VerifyError::ParentMismatch { offset } => {
+                write!(f, "parent node mismatch for the subtree at offset {offset}")
+            }
+            VerifyError::ChunkMismatch { offset } => {
+                write!(f, "chunk mismatch at offset {offset}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+// A subtree whose chaining value has already been verified, either against the root hash or
+// against a verified parent node.
+#[derive(Clone, Debug)]
+struct VerifiedSubtree {
+    start: u64,
+    len: u64,
+    // The pre-order index of this subtree's parent node in the outboard encoding. Not meaningful
+    // for chunks.
+    parent_index: u64,
+    cv: ChainingValue,
+}
+
+/// An incremental verifier for a chunk-aligned slice of content.
+///
+/// The verifier starts at a chunk boundary given to [`new`](SliceVerifier::new), and each call to
+/// [`verify_chunk`](SliceVerifier::verify_chunk) checks the next chunk of content. Parent nodes
+/// from the outboard encoding are verified as they're needed, and each one is verified at most
+/// once, so verifying `N` consecutive chunks costs about `N` parent node compressions plus the
+/// path from the root.
+///
+/// Note that the content length in the outboard header is only authenticated by the root hash
+/// once the final chunk has been verified. Until then, a modified header could make the content
+/// look shorter or longer than it is, though it can't make any chunk verify with the wrong bytes.
+///
+/// See the [module level docs](index.html) for an example.
+#[derive(Clone, Debug)]
+pub struct SliceVerifier<'a> {
+    root_hash: Hash,
+    content_len: u64,
+    parents: &'a [u8],
+    position: u64,
+    finished: bool,
+    // The verified subtrees to the right of the current path, with the next one on top.
+    stack: Vec<VerifiedSubtree>,
+    // Scratch space for updating the stack, so that a failed verification leaves it unchanged.
+    new_stack: Vec<VerifiedSubtree>,
+}
+
+impl<'a> SliceVerifier<'a> {
+    /// Construct a verifier for the slice beginning at `slice_start` bytes into the content.
+    ///
+    /// `slice_start` must be a multiple of [`CHUNK_LEN`] and no greater than the content length.
+    /// Starting at the end of the content is allowed, but there's nothing to verify there.
+    pub fn new(
+        root_hash: &Hash,
+        outboard: &'a [u8],
+        slice_start: u64,
+    ) -> Result<Self, VerifyError> {
+        if outboard.len() < HEADER_LEN {
+            return Err(VerifyError::InvalidOutboard);
+        }
+        let content_len = u64::from_le_bytes(*array_ref!(outboard, 0, HEADER_LEN));
+        if outboard_len(content_len) != outboard.len() as u64 {
+            return Err(VerifyError::InvalidOutboard);
+        }
+        if slice_start % CHUNK_LEN as u64 != 0 || slice_start > content_len {
+            return Err(VerifyError::InvalidSliceStart(slice_start));
+        }
+        Ok(Self {
+            root_hash: *root_hash,
+            content_len,
+            parents: &outboard[HEADER_LEN..],
+            position: slice_start,
+            // The empty content still has one (empty) chunk to verify.
+            finished: slice_start == content_len && content_len > 0,
+            stack: Vec::new(),
+            new_stack: Vec::new(),
+        })
+    }
+
+    /// The content length from the outboard header.
+    pub fn content_len(&self) -> u64 {
+        self.content_len
+    }
+
+    /// The offset of the next chunk to be verified.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Verify the next chunk of the slice.
+    ///
+    /// Every chunk must be exactly [`CHUNK_LEN`] bytes, except for the final chunk of the
+    /// content, which can be shorter. If the content is empty, the only valid chunk is an empty
+    /// slice. On success, the [`position`](SliceVerifier::position) moves forward to the next
+    /// chunk. On failure, the position doesn't change, and the chunk must not be used.
+    pub fn verify_chunk(&mut self, chunk: &[u8]) -> Result<(), VerifyError> {
+        let chunk_start = self.position;
+        let expected_len = if self.finished {
+            0
+        } else {
+            core::cmp::min(self.content_len - chunk_start, CHUNK_LEN as u64) as usize
+        };
+        if self.finished || chunk.len() != expected_len {
+            return Err(VerifyError::UnexpectedChunkLen {
+                offset: chunk_start,
+                expected: expected_len,
+                found: chunk.len(),
+            });
+        }
+
+        // Content of one chunk or less has no parent nodes, and the chunk is the root.
+        if self.content_len <= CHUNK_LEN as u64 {
+            if crate::hash(chunk) != self.root_hash {
+                return Err(VerifyError::ChunkMismatch {
+                    offset: chunk_start,
+                });
+            }
+            self.advance(chunk.len());
+            return Ok(());
+        }
+
+        let mut new_stack = core::mem::take(&mut self.new_stack);
+        new_stack.clone_from(&self.stack);
+        let result = self.verify_chunk_inner(chunk, &mut new_stack);
+        if result.is_ok() {
+            core::mem::swap(&mut self.stack, &mut new_stack);
+            self.advance(chunk.len());
+        }
+        self.new_stack = new_stack;
+        result
+    }
+
+    fn verify_chunk_inner(
+        &self,
+        chunk: &[u8],
+        new_stack: &mut Vec<VerifiedSubtree>,
+    ) -> Result<(), VerifyError> {
+        let chunk_start = self.position;
+        if new_stack.is_empty() {
+            // Start from the root. Its parent node is verified against the root hash rather than
+            // a chaining value.
+            let (left_cv, right_cv) = self.read_parent(0)?;
+            if merge_subtrees_root(&left_cv, &right_cv, Mode::Hash) != self.root_hash {
+                return Err(VerifyError::ParentMismatch { offset: 0 });
+            }
+            push_children(new_stack, 0, self.content_len, 0, &left_cv, &right_cv);
+        }
+        loop {
+            // Both children of every verified parent are pushed, so after skipping any left
+            // subtrees that come before the slice start, the top of the stack always contains the
+            // next chunk.
+            let mut subtree = new_stack.pop().expect("the stack is never empty");
+            while subtree.start + subtree.len <= chunk_start {
+                subtree = new_stack.pop().expect("the stack is never empty");
+            }
+            debug_assert!(subtree.start <= chunk_start);
+            debug_assert!(chunk_start < subtree.start + subtree.len);
+            if subtree.len <= CHUNK_LEN as u64 {
+                if chunk_or_subtree_cv(chunk, chunk_start) != subtree.cv {
+                    return Err(VerifyError::ChunkMismatch {
+                        offset: chunk_start,
+                    });
+                }
+                return Ok(());
+            }
+            let (left_cv, right_cv) = self.read_parent(subtree.parent_index)?;
+            if merge_subtrees_non_root(&left_cv, &right_cv, Mode::Hash) != subtree.cv {
+                return Err(VerifyError::ParentMismatch {
+                    offset: subtree.start,
+                });
+            }
+            push_children(
+                new_stack,
+                subtree.start,
+                subtree.len,
+                subtree.parent_index,
+                &left_cv,
+                &right_cv,
+            );
+        }
+    }
+
+    fn advance(&mut self, chunk_len: usize) {
+        self.position += chunk_len as u64;
+        if self.position == self.content_len {
+            self.finished = true;
+        }
+    }
+
+    fn read_parent(
+        &self,
+        parent_index: u64,
+    ) -> Result<(ChainingValue, ChainingValue), VerifyError> {
+        // The outboard length was checked in new(), so this is always in bounds for a
+        // well-formed tree walk, but a bounds check here is cheap.
+        let start = parent_index as usize * PARENT_LEN;
+        let parent = self
+            .parents
+            .get(start..start + PARENT_LEN)
+            .ok_or(VerifyError::InvalidOutboard)?;
+        Ok((
+            *array_ref!(parent, 0, OUT_LEN),
+            *array_ref!(parent, OUT_LEN, OUT_LEN),
+        ))
+    }
+}
+
+fn push_children(
+    stack: &mut Vec<VerifiedSubtree>,
+    start: u64,
+    len: u64,
+    parent_index: u64,
+    left_cv: &ChainingValue,
+    right_cv: &ChainingValue,
+) {
+    let left_len = left_subtree_len(len);
+    // The left subtree's parent node comes right after this one, and the right subtree's parent
+    // node comes after all the parent nodes of the left subtree.
+    let left_parent_index = parent_index + 1;
+    let right_parent_index = left_parent_index + count_chunks(left_len) - 1;
+    stack.push(VerifiedSubtree {
+        start: start + left_len,
+        len: len - left_len,
+        parent_index: right_parent_index,
+        cv: *right_cv,
+    });
+    stack.push(VerifiedSubtree {
+        start,
+        len: left_len,
+        parent_index: left_parent_index,
+        cv: *left_cv,
+    });
+}
+
+/// Verify a whole chunk-aligned slice of content at once.
+///
+/// This is a convenience wrapper around [`SliceVerifier`]. The `slice` must begin at
+/// `slice_start`, which must be a multiple of [`CHUNK_LEN`], and it must end either at a chunk
+/// boundary or at the end of the content.
+pub fn verify_slice(
+    root_hash: &Hash,
+    outboard: &[u8],
+    slice_start: u64,
+    slice: &[u8],
+) -> Result<(), VerifyError> {
+    let mut verifier = SliceVerifier::new(root_hash, outboard, slice_start)?;
+    if slice.is_empty() && verifier.content_len() == 0 {
+        return verifier.verify_chunk(slice);
+    }
+    for chunk in slice.chunks(CHUNK_LEN) {
+        verifier.verify_chunk(chunk)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::{TEST_CASES, TEST_CASES_MAX, paint_test_input};
+
+    #[test]
+    fn test_outboard_len() {
+        assert_eq!(outboard_len(0), 8);
+        assert_eq!(outboard_len(1), 8);
+        assert_eq!(outboard_len(CHUNK_LEN as u64), 8);
+        assert_eq!(outboard_len(CHUNK_LEN as u64 + 1), 8 + 64);
+        assert_eq!(outboard_len(4 * CHUNK_LEN as u64), 8 + 3 * 64);
+    }
+
+    #[test]
+    fn test_outboard_matches_hash() {
+        let mut input = [0; TEST_CASES_MAX];
+        paint_test_input(&mut input);
+        for &case in TEST_CASES {
+            let (root_hash, encoded) = outboard(&input[..case]);
+            assert_eq!(root_hash, crate::hash(&input[..case]), "case {case}");
+            assert_eq!(encoded.len() as u64, outboard_len(case as u64));
+            assert_eq!(encoded[..HEADER_LEN], (case as u64).to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn test_outboard_preorder_layout() {
+        // Three chunks: the root parent covers [parent(chunk0, chunk1), chunk2].
+        let mut input = [0; 3 * CHUNK_LEN];
+        paint_test_input(&mut input);
+        let (_, encoded) = outboard(&input);
+        let cv = |i: usize| {
+            chunk_or_subtree_cv(&input[i * CHUNK_LEN..][..CHUNK_LEN], (i * CHUNK_LEN) as u64)
+        };
+        let left_parent = merge_subtrees_non_root(&cv(0), &cv(1), Mode::Hash);
+        let mut expected = (3 * CHUNK_LEN as u64).to_le_bytes().to_vec();
+        expected.extend_from_slice(&left_parent);
+        expected.extend_from_slice(&cv(2));
+        expected.extend_from_slice(&cv(0));
+        expected.extend_from_slice(&cv(1));
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn test_verify_every_slice_start() {
+        let mut input = [0; TEST_CASES_MAX];
+        paint_test_input(&mut input);
+        for &case in TEST_CASES {
+            let content = &input[..case];
+            let (root_hash, encoded) = outboard(content);
+            let mut start = 0;
+            loop {
+                verify_slice(&root_hash, &encoded, start as u64, &content[start..])
+                    .unwrap_or_else(|e| panic!("case {case} start {start}: {e}"));
+                // Also verify a short slice that ends at a chunk boundary.
+                let end = core::cmp::min(start + 2 * CHUNK_LEN, case);
+                verify_slice(&root_hash, &encoded, start as u64, &content[start..end]).unwrap();
+                start += CHUNK_LEN;
+                if start >= case {
+                    break;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_verify_rejects_corruption() {
+        let mut input = vec![0; 9 * CHUNK_LEN + 100];
+        paint_test_input(&mut input);
+        let (root_hash, encoded) = outboard(&input);
+
+        // Flip a bit in each chunk in turn.
+        for chunk_index in 0..10 {
+            let mut bad_input = input.clone();
+            bad_input[chunk_index * CHUNK_LEN] ^= 1;
+            let err = verify_slice(&root_hash, &encoded, 0, &bad_input).unwrap_err();
+            assert_eq!(
+                err,
+                VerifyError::ChunkMismatch {
+                    offset: (chunk_index * CHUNK_LEN) as u64,
+                }
+            );
+        }
+
+        // Flip a bit in each parent node in turn. Every parent is on the path to some chunk, so
+        // verifying the whole content must fail.
+        for i in (HEADER_LEN..encoded.len()).step_by(OUT_LEN) {
+            let mut bad_encoded = encoded.clone();
+            bad_encoded[i] ^= 1;
+            let err = verify_slice(&root_hash, &bad_encoded, 0, &input).unwrap_err();
+            assert!(matches!(err, VerifyError::ParentMismatch { .. }), "{err:?}");
+        }
+
+        // The wrong root hash.
+        let err = verify_slice(&crate::hash(b"foo"), &encoded, 0, &input).unwrap_err();
+        assert_eq!(err, VerifyError::ParentMismatch { offset: 0 });
+    }
+
+    #[test]
+    fn test_verify_errors() {
+        let input = [7; 2 * CHUNK_LEN + 1];
+        let (root_hash, encoded) = outboard(&input);
+        assert_eq!(
+            SliceVerifier::new(&root_hash, &encoded[..7], 0).unwrap_err(),
+            VerifyError::InvalidOutboard,
+        );
+        assert_eq!(
+            SliceVerifier::new(&root_hash, &encoded[..encoded.len() - 1], 0).unwrap_err(),
+            VerifyError::InvalidOutboard,
+        );
+        assert_eq!(
+            SliceVerifier::new(&root_hash, &encoded, 1).unwrap_err(),
+            VerifyError::InvalidSliceStart(1),
+        );
+        assert_eq!(
+            SliceVerifier::new(&root_hash, &encoded, 3 * CHUNK_LEN as u64).unwrap_err(),
+            VerifyError::InvalidSliceStart(3 * CHUNK_LEN as u64),
+        );
+
+        let mut verifier = SliceVerifier::new(&root_hash, &encoded, 0).unwrap();
+        assert_eq!(
+            verifier.verify_chunk(&input[..100]).unwrap_err(),
+            VerifyError::UnexpectedChunkLen {
+                offset: 0,
+                expected: CHUNK_LEN,
+                found: 100,
+            },
+        );
+        verifier.verify_chunk(&input[..CHUNK_LEN]).unwrap();
+        verifier.verify_chunk(&input[..CHUNK_LEN]).unwrap();
+        verifier.verify_chunk(&input[..1]).unwrap();
+        assert_eq!(verifier.position(), input.len() as u64);
+        assert!(matches!(
+            verifier.verify_chunk(&[]).unwrap_err(),
+            VerifyError::UnexpectedChunkLen { .. },
+        ));
+
+        // The empty content has exactly one empty chunk.
+        let (empty_hash, empty_encoded) = outboard(&[]);
+        let mut verifier = SliceVerifier::new(&empty_hash, &empty_encoded, 0).unwrap();
+        verifier.verify_chunk(&[]).unwrap();
+        assert!(verifier.verify_chunk(&[]).is_err());
+    }
+}